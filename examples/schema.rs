@@ -3,7 +3,10 @@ use std::fs::create_dir_all;
 
 use cosmwasm_schema::{export_schema, remove_schemas, schema_for};
 
-use sefi_bet_contract::msg::{ExecuteMsg, InstantiateMsg, QueryMsg};
+use sefi_bet_contract::msg::{
+    ExecuteMsg, HouseSettingsResponse, InstantiateMsg, LoyaltyResponse, PoolConfigResponse,
+    QueryMsg, SimulateBetResponse, SolvencyResponse, SudoMsg,
+};
 use sefi_bet_contract::state::State;
 
 fn main() {
@@ -15,5 +18,11 @@ fn main() {
     export_schema(&schema_for!(InstantiateMsg), &out_dir);
     export_schema(&schema_for!(ExecuteMsg), &out_dir);
     export_schema(&schema_for!(QueryMsg), &out_dir);
+    export_schema(&schema_for!(SudoMsg), &out_dir);
     export_schema(&schema_for!(State), &out_dir);
+    export_schema(&schema_for!(PoolConfigResponse), &out_dir);
+    export_schema(&schema_for!(SimulateBetResponse), &out_dir);
+    export_schema(&schema_for!(SolvencyResponse), &out_dir);
+    export_schema(&schema_for!(HouseSettingsResponse), &out_dir);
+    export_schema(&schema_for!(LoyaltyResponse), &out_dir);
 }
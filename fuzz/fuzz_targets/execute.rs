@@ -0,0 +1,60 @@
+//! Decodes arbitrary bytes as an `ExecuteMsg` and runs it against a contract
+//! with open bets in every pool. Errors are fine, panics are not.
+//!
+//! The first byte picks the sender and the next two the attached amount, the
+//! rest is the JSON message.
+
+#![no_main]
+
+use cosmwasm_std::testing::{mock_dependencies_with_balance, mock_env, mock_info};
+use cosmwasm_std::{coins, from_json, Addr};
+use libfuzzer_sys::fuzz_target;
+
+use sefi_bet_contract::contract::{execute, instantiate};
+use sefi_bet_contract::msg::{ExecuteMsg, InstantiateMsg};
+
+const DENOM: &str = "usei";
+const SENDERS: [&str; 4] = ["fee_address", "alice", "bob", "cosmos2contract"];
+
+fuzz_target!(|data: &[u8]| {
+    if data.len() < 3 {
+        return;
+    }
+    let msg: ExecuteMsg = match from_json(&data[3..]) {
+        Ok(msg) => msg,
+        Err(_) => return,
+    };
+    let sender = SENDERS[data[0] as usize % SENDERS.len()];
+    let amount = u16::from_le_bytes([data[1], data[2]]) as u128;
+
+    let mut deps = mock_dependencies_with_balance(&coins(u128::MAX, DENOM));
+    let msg_init = InstantiateMsg {
+        fee_address: Addr::unchecked("fee_address"),
+        start_flag: true,
+    };
+    instantiate(deps.as_mut(), mock_env(), mock_info("creator", &[]), msg_init).unwrap();
+    for bet in [
+        ExecuteMsg::AddBetUserInfinite { client_seed: None },
+        ExecuteMsg::AddBetUserWhale { client_seed: None },
+        ExecuteMsg::AddBetUserShrimp { client_seed: None },
+    ]
+    .iter()
+    {
+        for bettor in &["alice", "bob"] {
+            execute(
+                deps.as_mut(),
+                mock_env(),
+                mock_info(bettor, &coins(20, DENOM)),
+                bet.clone(),
+            )
+            .unwrap();
+        }
+    }
+
+    let funds = if amount == 0 {
+        vec![]
+    } else {
+        coins(amount, DENOM)
+    };
+    let _ = execute(deps.as_mut(), mock_env(), mock_info(sender, &funds), msg);
+});
@@ -0,0 +1,205 @@
+//! Optional allowlist gating.
+//! Once an allowlist config is set, only verified addresses can place bets.
+//! The local allowlist is checked first, then the attestation contract if
+//! one is configured. It is queried with:
+//!
+//! ```json
+//! { "is_verified": { "address": "sei1..." } }
+//! ```
+//!
+//! and must answer `{ "verified": true | false }`.
+
+use cosmwasm_std::{
+    to_json_binary, Addr, Deps, DepsMut, Env, MessageInfo, QueryRequest, Response, StdResult,
+    WasmQuery,
+};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::msg::AllowlistConfig;
+use crate::query::query_config;
+use crate::state::{ALLOWLIST, ALLOWLIST_CONFIG};
+use crate::ContractError;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum AttestationQueryMsg {
+    IsVerified { address: String },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct IsVerifiedResponse {
+    pub verified: bool,
+}
+
+pub fn set_allowlist_config(
+    deps: DepsMut,
+    _env: Env,
+    info: MessageInfo,
+    allowlist_config: Option<AllowlistConfig>,
+) -> Result<Response, ContractError> {
+    let config = query_config(deps.as_ref())?;
+    if info.sender != config.fee_address {
+        return Err(ContractError::Unauthorized);
+    }
+
+    match &allowlist_config {
+        Some(allowlist_config) => {
+            if let Some(contract) = &allowlist_config.attestation_contract {
+                deps.api.addr_validate(contract.as_str())?;
+            }
+            ALLOWLIST_CONFIG.save(deps.storage, allowlist_config)?;
+        }
+        None => ALLOWLIST_CONFIG.remove(deps.storage),
+    }
+
+    Ok(Response::new()
+        .add_attribute("action", "set_allowlist_config")
+        .add_attribute("gated", allowlist_config.is_some().to_string()))
+}
+
+pub fn update_allowlist(
+    deps: DepsMut,
+    _env: Env,
+    info: MessageInfo,
+    add: Vec<Addr>,
+    remove: Vec<Addr>,
+) -> Result<Response, ContractError> {
+    let config = query_config(deps.as_ref())?;
+    if info.sender != config.fee_address {
+        return Err(ContractError::Unauthorized);
+    }
+
+    for address in &add {
+        let address = deps.api.addr_validate(address.as_str())?;
+        ALLOWLIST.save(deps.storage, &address, &())?;
+    }
+    for address in &remove {
+        ALLOWLIST.remove(deps.storage, address);
+    }
+
+    Ok(Response::new()
+        .add_attribute("action", "update_allowlist")
+        .add_attribute("added", add.len().to_string())
+        .add_attribute("removed", remove.len().to_string()))
+}
+
+/// Whether `address` may bet. Everyone may while gating is off.
+pub fn is_allowed(deps: Deps, address: &Addr) -> StdResult<bool> {
+    let allowlist_config = match ALLOWLIST_CONFIG.may_load(deps.storage)? {
+        Some(allowlist_config) => allowlist_config,
+        None => return Ok(true),
+    };
+    if ALLOWLIST.has(deps.storage, address) {
+        return Ok(true);
+    }
+    match allowlist_config.attestation_contract {
+        Some(contract) => {
+            let res: IsVerifiedResponse =
+                deps.querier.query(&QueryRequest::Wasm(WasmQuery::Smart {
+                    contract_addr: contract.to_string(),
+                    msg: to_json_binary(&AttestationQueryMsg::IsVerified {
+                        address: address.to_string(),
+                    })?,
+                }))?;
+            Ok(res.verified)
+        }
+        None => Ok(false),
+    }
+}
+
+pub(crate) fn assert_allowed(deps: Deps, address: &Addr) -> Result<(), ContractError> {
+    if !is_allowed(deps, address)? {
+        return Err(ContractError::NotAllowed {
+            address: address.to_string(),
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::contract::{execute, instantiate};
+    use crate::msg::{ExecuteMsg, InstantiateMsg};
+    use cosmwasm_std::testing::{mock_dependencies_with_balance, mock_env, mock_info};
+    use cosmwasm_std::{coins, from_json, ContractResult, SystemResult};
+
+    const DENOM: &str = "usei";
+
+    fn bet(deps: DepsMut, bettor: &str) -> Result<Response, ContractError> {
+        execute(
+            deps,
+            mock_env(),
+            mock_info(bettor, &coins(10, DENOM)),
+            ExecuteMsg::AddBetUserInfinite { client_seed: None },
+        )
+    }
+
+    #[test]
+    fn test_gated_bets() {
+        let mut deps = mock_dependencies_with_balance(&coins(10_000, DENOM));
+        // Mock attestation contract that only verifies "verified".
+        deps.querier.update_wasm(|query| match query {
+            WasmQuery::Smart { contract_addr, msg } => {
+                assert_eq!("attestor", contract_addr);
+                let AttestationQueryMsg::IsVerified { address } = from_json(msg).unwrap();
+                let res = IsVerifiedResponse {
+                    verified: address == "verified",
+                };
+                SystemResult::Ok(ContractResult::Ok(to_json_binary(&res).unwrap()))
+            }
+            _ => panic!("unexpected query"),
+        });
+
+        let admin = mock_info("fee_address", &[]);
+        let msg = InstantiateMsg {
+            fee_address: Addr::unchecked("fee_address"),
+            start_flag: true,
+        };
+        instantiate(deps.as_mut(), mock_env(), admin.clone(), msg).unwrap();
+        bet(deps.as_mut(), "anyone").unwrap();
+
+        let allowlist_config = AllowlistConfig {
+            attestation_contract: Some(Addr::unchecked("attestor")),
+        };
+        set_allowlist_config(
+            deps.as_mut(),
+            mock_env(),
+            admin.clone(),
+            Some(allowlist_config),
+        )
+        .unwrap();
+        update_allowlist(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("anyone", &[]),
+            vec![Addr::unchecked("anyone")],
+            vec![],
+        )
+        .unwrap_err();
+        update_allowlist(
+            deps.as_mut(),
+            mock_env(),
+            admin.clone(),
+            vec![Addr::unchecked("listed")],
+            vec![],
+        )
+        .unwrap();
+
+        let err = bet(deps.as_mut(), "anyone").unwrap_err();
+        assert!(matches!(err, ContractError::NotAllowed { .. }));
+        bet(deps.as_mut(), "verified").unwrap();
+        bet(deps.as_mut(), "listed").unwrap();
+
+        update_allowlist(
+            deps.as_mut(),
+            mock_env(),
+            admin,
+            vec![],
+            vec![Addr::unchecked("listed")],
+        )
+        .unwrap();
+        bet(deps.as_mut(), "listed").unwrap_err();
+    }
+}
@@ -0,0 +1,242 @@
+//! House-funded pot bonuses.
+//! The admin can add house money to the open round of a pool. The bonus
+//! goes to the winner on top of the prize, free of the house fee, but it is
+//! no stake: it adds no weight to the draw and earns no one a share. A
+//! cancelled round sends its bonus back to the fee address.
+
+use cosmwasm_std::{Coin, DepsMut, Env, MessageInfo, Response, StdResult, Storage};
+
+use crate::contract::{current_round, one_coin};
+use crate::governance;
+use crate::msg::PoolId;
+use crate::query::query_config;
+use crate::solvency;
+use crate::state::{State, POOL_DENOMS, POT_BONUSES};
+use crate::ContractError;
+
+pub fn fund_pot(
+    deps: DepsMut,
+    _env: Env,
+    info: MessageInfo,
+    pool: PoolId,
+) -> Result<Response, ContractError> {
+    let config = query_config(deps.as_ref())?;
+    if info.sender != config.fee_address {
+        return Err(ContractError::Unauthorized);
+    }
+    let funds = one_coin(&info)?;
+    assert_round_denom(deps.storage, &config, pool, &funds.denom)?;
+
+    let mut bonus = pot_bonus(deps.storage, pool)?;
+    match bonus.iter_mut().find(|coin| coin.denom == funds.denom) {
+        Some(coin) => coin.amount += funds.amount,
+        None => bonus.push(funds.clone()),
+    }
+    POT_BONUSES.save(deps.storage, pool.as_str(), &bonus)?;
+    solvency::owe(deps.storage, &funds.denom, funds.amount.u128())?;
+    governance::fix_round_fee(deps.storage, pool.as_str())?;
+
+    Ok(Response::new()
+        .add_attribute("action", "fund_pot")
+        .add_attribute("pool_id", pool.to_string())
+        .add_attribute("round_id", current_round(deps.storage, pool)?.to_string())
+        .add_attribute("bonus", funds.to_string()))
+}
+
+/// House money added to the open round of `pool`.
+pub fn pot_bonus(storage: &dyn Storage, pool: PoolId) -> StdResult<Vec<Coin>> {
+    Ok(POT_BONUSES
+        .may_load(storage, pool.as_str())?
+        .unwrap_or_default())
+}
+
+/// Whether the open round of `pool` has started: it has bets, or a bonus
+/// that already fixed its denom.
+pub(crate) fn round_started(
+    storage: &dyn Storage,
+    config: &State,
+    pool: PoolId,
+) -> StdResult<bool> {
+    Ok(!config.user_list(pool).is_empty() || !pot_bonus(storage, pool)?.is_empty())
+}
+
+/// Checks that `denom` can join the open round of `pool`: it is accepted by
+/// a multi-denom pool, or it is the denom of the round's first bet or
+/// bonus.
+pub(crate) fn assert_round_denom(
+    storage: &dyn Storage,
+    config: &State,
+    pool: PoolId,
+    denom: &str,
+) -> Result<(), ContractError> {
+    if let Some(pool_denoms) = POOL_DENOMS.may_load(storage, pool.as_str())? {
+        if !pool_denoms.iter().any(|rate| rate.denom == denom) {
+            return Err(ContractError::DenomNotAccepted {
+                denom: denom.to_string(),
+            });
+        }
+        return Ok(());
+    }
+    // Without accepted denoms, a round is held in a single denom.
+    let round_denom = match config.user_list(pool).first() {
+        Some(first_bet) => Some(first_bet.coin_type.clone()),
+        None => pot_bonus(storage, pool)?
+            .first()
+            .map(|bonus| bonus.denom.clone()),
+    };
+    match round_denom {
+        Some(expected) if expected != denom => Err(ContractError::InvalidDenom {
+            expected,
+            received: denom.to_string(),
+        }),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::contract::{execute, instantiate};
+    use crate::governance::sudo;
+    use crate::msg::{
+        DenomRate, ExecuteMsg, InstantiateMsg, RandomnessMode, SudoMsg, TicketConfig,
+    };
+    use crate::state::ROUND_HISTORY;
+    use cosmwasm_std::testing::{mock_dependencies_with_balance, mock_env, mock_info};
+    use cosmwasm_std::{coin, coins, Addr, BankMsg, CosmosMsg, Decimal, Response};
+
+    const DENOM: &str = "usei";
+
+    fn sends(res: &Response) -> Vec<CosmosMsg> {
+        res.messages.iter().map(|sub| sub.msg.clone()).collect()
+    }
+
+    fn send(to_address: &str, amount: u128) -> CosmosMsg {
+        BankMsg::Send {
+            to_address: to_address.to_string(),
+            amount: coins(amount, DENOM),
+        }
+        .into()
+    }
+
+    #[test]
+    fn test_pot_bonus() {
+        let mut deps = mock_dependencies_with_balance(&coins(10_000, DENOM));
+        let admin = mock_info("fee_address", &[]);
+        let msg = InstantiateMsg {
+            fee_address: Addr::unchecked("fee_address"),
+            start_flag: true,
+        };
+        instantiate(deps.as_mut(), mock_env(), admin.clone(), msg).unwrap();
+        let fund = |deps: DepsMut, sender: &str, amount: Coin| {
+            execute(
+                deps,
+                mock_env(),
+                mock_info(sender, &[amount]),
+                ExecuteMsg::FundPot {
+                    pool_id: PoolId::Infinite,
+                },
+            )
+        };
+        let bet = |deps: DepsMut, bettor: &str, amount: Coin| {
+            execute(
+                deps,
+                mock_env(),
+                mock_info(bettor, &[amount]),
+                ExecuteMsg::AddBetUserInfinite { client_seed: None },
+            )
+        };
+
+        let err = fund(deps.as_mut(), "alice", coin(1000, DENOM)).unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized));
+        fund(deps.as_mut(), "fee_address", coin(1000, DENOM)).unwrap();
+        // The bonus sets the round's denom like a first bet, so the pool
+        // can't be reconfigured under it.
+        let err = bet(deps.as_mut(), "alice", coin(100, "uatom")).unwrap_err();
+        assert!(matches!(err, ContractError::InvalidDenom { .. }));
+        let ticket_config = TicketConfig {
+            price: 10,
+            denom: "uatom".to_string(),
+            max_per_user: 10,
+            supply: 100,
+        };
+        let denoms = vec![DenomRate {
+            denom: "uatom".to_string(),
+            rate: Decimal::one(),
+        }];
+        for msg in [
+            ExecuteMsg::SetTicketConfig {
+                pool_id: PoolId::Infinite,
+                ticket_config: Some(ticket_config),
+            },
+            ExecuteMsg::SetPoolDenoms {
+                pool_id: PoolId::Infinite,
+                denoms: Some(denoms),
+            },
+            ExecuteMsg::SetRandomnessMode {
+                pool_id: PoolId::Infinite,
+                mode: RandomnessMode::Entropy,
+            },
+        ] {
+            let err = execute(deps.as_mut(), mock_env(), admin.clone(), msg).unwrap_err();
+            assert!(matches!(err, ContractError::RoundInProgress { .. }));
+        }
+        bet(deps.as_mut(), "alice", coin(100, DENOM)).unwrap();
+        bet(deps.as_mut(), "bob", coin(300, DENOM)).unwrap();
+        assert_eq!(
+            vec![coin(1000, DENOM)],
+            pot_bonus(&deps.storage, PoolId::Infinite).unwrap()
+        );
+
+        // Only the 400 staked weigh in the draw: seed 7 draws 5898 bps, so
+        // the target 235 falls in Bob's 100..400. He wins the prize of the
+        // stakes and the whole bonus, which pays no fee.
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            admin.clone(),
+            ExecuteMsg::ToggleGameInfinte {},
+        )
+        .unwrap();
+        let res = execute(
+            deps.as_mut(),
+            mock_env(),
+            admin,
+            ExecuteMsg::EndGameInfinte { seed: 7 },
+        )
+        .unwrap();
+        assert_eq!(
+            vec![send("fee_address", 16), send("bob", 1384)],
+            sends(&res)
+        );
+        let record = ROUND_HISTORY
+            .load(&deps.storage, (PoolId::Infinite.as_str(), 1))
+            .unwrap();
+        assert_eq!((coin(400, DENOM), 384), (record.pot, record.prize));
+        assert_eq!(vec![coin(1000, DENOM)], record.bonus);
+        assert!(pot_bonus(&deps.storage, PoolId::Infinite)
+            .unwrap()
+            .is_empty());
+
+        // A cancelled round refunds the stakes to the bettors and the bonus
+        // to the fee address.
+        fund(deps.as_mut(), "fee_address", coin(500, DENOM)).unwrap();
+        bet(deps.as_mut(), "alice", coin(100, DENOM)).unwrap();
+        let res = sudo(
+            deps.as_mut(),
+            mock_env(),
+            SudoMsg::CancelRound {
+                pool_id: PoolId::Infinite,
+            },
+        )
+        .unwrap();
+        assert_eq!(
+            vec![send("alice", 100), send("fee_address", 500)],
+            sends(&res)
+        );
+        assert!(pot_bonus(&deps.storage, PoolId::Infinite)
+            .unwrap()
+            .is_empty());
+        crate::solvency::assert_tracked(deps.as_ref());
+    }
+}
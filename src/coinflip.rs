@@ -0,0 +1,446 @@
+//! Head-to-head coin flips.
+//! A player opens a challenge by staking on a side, another player matches
+//! the stake and the admin resolves the flip with the same randomness as the
+//! pot draws. Unmatched challenges can be refunded once they expire, and
+//! matched ones once the admin has let the resolution deadline pass.
+
+use cosmwasm_std::{coin, BankMsg, CosmosMsg, DepsMut, Env, MessageInfo, Response};
+
+use crate::allowlist;
+use crate::contract::{generate_random_value, one_coin, split_pot};
+use crate::events;
+use crate::governance;
+use crate::limits;
+use crate::msg::CoinSide;
+use crate::payout;
+use crate::query::query_config;
+use crate::solvency;
+use crate::state::{Challenge, CHALLENGES, CHALLENGE_COUNT};
+use crate::ContractError;
+
+/// How long a challenge stays open for matching, in seconds.
+pub const CHALLENGE_EXPIRY: u64 = 24 * 60 * 60;
+/// How long the admin has to resolve a matched challenge, in seconds.
+pub const RESOLVE_DEADLINE: u64 = 24 * 60 * 60;
+
+pub fn open_challenge(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    side: CoinSide,
+) -> Result<Response, ContractError> {
+    allowlist::assert_allowed(deps.as_ref(), &info.sender)?;
+    let stake = one_coin(&info)?;
+    limits::record_wager(
+        deps.storage,
+        env.block.time.seconds(),
+        &info.sender,
+        stake.amount.u128(),
+    )?;
+
+    let id = CHALLENGE_COUNT.may_load(deps.storage)?.unwrap_or_default() + 1;
+    CHALLENGE_COUNT.save(deps.storage, &id)?;
+
+    let challenge = Challenge {
+        id,
+        creator: info.sender.clone(),
+        side,
+        amount: stake.amount.u128(),
+        coin_type: stake.denom.clone(),
+        expires_at: env.block.time.seconds() + CHALLENGE_EXPIRY,
+        opponent: None,
+        fee_bps: governance::house_fee_bps(deps.storage)?,
+    };
+    CHALLENGES.save(deps.storage, id, &challenge)?;
+    solvency::owe(deps.storage, &stake.denom, stake.amount.u128())?;
+
+    Ok(Response::new()
+        .add_attribute("action", "open_challenge")
+        .add_attribute("challenge_id", id.to_string())
+        .add_attribute("creator", info.sender.to_string())
+        .add_attribute("stake", stake.to_string()))
+}
+
+pub fn accept_challenge(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    challenge_id: u64,
+) -> Result<Response, ContractError> {
+    allowlist::assert_allowed(deps.as_ref(), &info.sender)?;
+    let mut challenge = load_challenge(&deps, challenge_id)?;
+
+    if challenge.opponent.is_some() {
+        return Err(ContractError::ChallengeMatched { challenge_id });
+    }
+    if challenge.expires_at <= env.block.time.seconds() {
+        return Err(ContractError::ChallengeExpired { challenge_id });
+    }
+    if info.sender == challenge.creator {
+        return Err(ContractError::OwnChallenge);
+    }
+    let expected = coin(challenge.amount, challenge.coin_type.clone());
+    if info.funds != vec![expected.clone()] {
+        return Err(ContractError::StakeMismatch { expected });
+    }
+    limits::record_wager(
+        deps.storage,
+        env.block.time.seconds(),
+        &info.sender,
+        challenge.amount,
+    )?;
+
+    challenge.opponent = Some(info.sender.clone());
+    challenge.expires_at = env.block.time.seconds() + RESOLVE_DEADLINE;
+    CHALLENGES.save(deps.storage, challenge_id, &challenge)?;
+    solvency::owe(deps.storage, &challenge.coin_type, challenge.amount)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "accept_challenge")
+        .add_attribute("challenge_id", challenge_id.to_string())
+        .add_attribute("opponent", info.sender.to_string()))
+}
+
+pub fn cancel_challenge(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    challenge_id: u64,
+) -> Result<Response, ContractError> {
+    let challenge = load_challenge(&deps, challenge_id)?;
+
+    let expired = challenge.expires_at <= env.block.time.seconds();
+    match challenge.opponent {
+        // A matched challenge is only refunded once it can no longer be resolved.
+        Some(_) if !expired => return Err(ContractError::ChallengeMatched { challenge_id }),
+        None if info.sender != challenge.creator && !expired => {
+            return Err(ContractError::ChallengeNotExpired { challenge_id })
+        }
+        _ => {}
+    }
+
+    CHALLENGES.remove(deps.storage, challenge_id);
+
+    let stake = coin(challenge.amount, challenge.coin_type);
+    let mut res = Response::new()
+        .add_attribute("action", "cancel_challenge")
+        .add_attribute("challenge_id", challenge_id.to_string());
+    for player in std::iter::once(challenge.creator).chain(challenge.opponent) {
+        solvency::discharge(deps.storage, &stake.denom, stake.amount.u128())?;
+        res = res
+            .add_message(CosmosMsg::Bank(BankMsg::Send {
+                to_address: player.to_string(),
+                amount: vec![stake.clone()],
+            }))
+            .add_event(events::game_refund(
+                "coinflip",
+                challenge_id,
+                &player,
+                &stake,
+            ));
+    }
+    Ok(res)
+}
+
+pub fn resolve_challenge(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    challenge_id: u64,
+    seed: u64,
+) -> Result<Response, ContractError> {
+    let config = query_config(deps.as_ref())?;
+    if info.sender != config.fee_address {
+        return Err(ContractError::Unauthorized);
+    }
+    let challenge = load_challenge(&deps, challenge_id)?;
+    let opponent = match challenge.opponent {
+        Some(opponent) => opponent,
+        None => return Err(ContractError::ChallengeNotMatched { challenge_id }),
+    };
+    if challenge.expires_at <= env.block.time.seconds() {
+        return Err(ContractError::ChallengeExpired { challenge_id });
+    }
+
+    // Both players staked the same amount, so each side owns half of the range.
+    let outcome = if generate_random_value(seed) < 5000 {
+        CoinSide::Heads
+    } else {
+        CoinSide::Tails
+    };
+    let winner_address = if outcome == challenge.side {
+        challenge.creator
+    } else {
+        opponent
+    };
+
+    let (fees, prize) = split_pot(challenge.amount * 2, challenge.fee_bps);
+
+    // A zero fee is not sent, and a payout that bounces is escrowed.
+    let payouts = [
+        (payout::FEE_REPLY_ID, &config.fee_address, fees),
+        (payout::PRIZE_REPLY_ID, &winner_address, prize),
+    ];
+    let mut submessages = vec![];
+    for &(reply_id, recipient, amount) in &payouts {
+        submessages.extend(payout::payout_msg(
+            deps.storage,
+            reply_id,
+            recipient,
+            coin(amount, challenge.coin_type.clone()),
+            "coinflip",
+            challenge_id,
+        )?);
+    }
+
+    CHALLENGES.remove(deps.storage, challenge_id);
+    solvency::discharge(deps.storage, &challenge.coin_type, challenge.amount * 2)?;
+
+    Ok(Response::new()
+        .add_submessages(submessages)
+        .add_attribute("action", "resolve_challenge")
+        .add_attribute("challenge_id", challenge_id.to_string())
+        .add_attribute("winner", winner_address.to_string()))
+}
+
+fn load_challenge(deps: &DepsMut, challenge_id: u64) -> Result<Challenge, ContractError> {
+    CHALLENGES
+        .may_load(deps.storage, challenge_id)?
+        .ok_or(ContractError::ChallengeNotFound { challenge_id })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::contract::instantiate;
+    use crate::msg::InstantiateMsg;
+    use crate::query::query_open_challenges;
+    use crate::state::HOUSE_FEE_BPS;
+    use cosmwasm_std::testing::{mock_dependencies, mock_env, mock_info};
+    use cosmwasm_std::{coins, Addr, SubMsg};
+
+    const DENOM: &str = "usei";
+
+    /// Instantiates the contract and opens a 100 usei challenge for alice.
+    fn setup_challenge(mut deps: DepsMut, side: CoinSide) -> u64 {
+        let msg = InstantiateMsg {
+            fee_address: Addr::unchecked("fee_address"),
+            start_flag: true,
+        };
+        instantiate(deps.branch(), mock_env(), mock_info("creator", &[]), msg).unwrap();
+        open_challenge(
+            deps.branch(),
+            mock_env(),
+            mock_info("alice", &coins(100, DENOM)),
+            side,
+        )
+        .unwrap();
+        CHALLENGE_COUNT.load(deps.storage).unwrap()
+    }
+
+    #[test]
+    fn test_resolve_challenge_pays_winner() {
+        let mut deps = mock_dependencies();
+        let id = setup_challenge(deps.as_mut(), CoinSide::Heads);
+        accept_challenge(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("bob", &coins(100, DENOM)),
+            id,
+        )
+        .unwrap();
+
+        let res = resolve_challenge(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("fee_address", &[]),
+            id,
+            1231231809300,
+        )
+        .unwrap();
+
+        assert_eq!(2, res.messages.len());
+        assert_eq!(
+            res.messages[0],
+            SubMsg::reply_always(
+                BankMsg::Send {
+                    to_address: String::from("fee_address"),
+                    amount: coins(8, DENOM),
+                },
+                payout::FEE_REPLY_ID
+            )
+        );
+        let winner = res
+            .attributes
+            .iter()
+            .find(|attr| attr.key == "winner")
+            .unwrap();
+        assert!(winner.value == "alice" || winner.value == "bob");
+        assert!(CHALLENGES.may_load(&deps.storage, id).unwrap().is_none());
+        crate::solvency::assert_tracked(deps.as_ref());
+    }
+
+    #[test]
+    fn test_accept_requires_identical_stake() {
+        let mut deps = mock_dependencies();
+        let id = setup_challenge(deps.as_mut(), CoinSide::Tails);
+
+        let err = accept_challenge(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("bob", &coins(99, DENOM)),
+            id,
+        )
+        .unwrap_err();
+        assert!(matches!(err, ContractError::StakeMismatch { .. }));
+    }
+
+    #[test]
+    fn test_expired_challenge_is_refunded() {
+        let mut deps = mock_dependencies();
+        let id = setup_challenge(deps.as_mut(), CoinSide::Heads);
+        assert_eq!(
+            1,
+            query_open_challenges(deps.as_ref(), mock_env(), None, None)
+                .unwrap()
+                .len()
+        );
+
+        // Only the creator may cancel before expiry.
+        cancel_challenge(deps.as_mut(), mock_env(), mock_info("bob", &[]), id).unwrap_err();
+
+        let mut env = mock_env();
+        env.block.time = env.block.time.plus_seconds(CHALLENGE_EXPIRY);
+        assert!(
+            query_open_challenges(deps.as_ref(), env.clone(), None, None)
+                .unwrap()
+                .is_empty()
+        );
+
+        let res = cancel_challenge(deps.as_mut(), env, mock_info("bob", &[]), id).unwrap();
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: String::from("alice"),
+                amount: coins(100, DENOM),
+            })
+        );
+    }
+
+    #[test]
+    fn test_unresolved_match_refunds_both_stakes() {
+        let mut deps = mock_dependencies();
+        let id = setup_challenge(deps.as_mut(), CoinSide::Heads);
+        let mut matched_at = mock_env();
+        matched_at.block.time = matched_at.block.time.plus_seconds(CHALLENGE_EXPIRY - 1);
+        accept_challenge(
+            deps.as_mut(),
+            matched_at.clone(),
+            mock_info("bob", &coins(100, DENOM)),
+            id,
+        )
+        .unwrap();
+
+        // The creator's expiry no longer applies; the admin has until the
+        // resolution deadline.
+        let mut env = matched_at.clone();
+        env.block.time = env.block.time.plus_seconds(RESOLVE_DEADLINE - 1);
+        let err = cancel_challenge(deps.as_mut(), env, mock_info("alice", &[]), id).unwrap_err();
+        assert!(matches!(err, ContractError::ChallengeMatched { .. }));
+
+        let mut env = matched_at;
+        env.block.time = env.block.time.plus_seconds(RESOLVE_DEADLINE);
+        let err = resolve_challenge(
+            deps.as_mut(),
+            env.clone(),
+            mock_info("fee_address", &[]),
+            id,
+            1231231809300,
+        )
+        .unwrap_err();
+        assert!(matches!(err, ContractError::ChallengeExpired { .. }));
+
+        let res = cancel_challenge(deps.as_mut(), env, mock_info("bob", &[]), id).unwrap();
+        assert_eq!(
+            vec![
+                CosmosMsg::Bank(BankMsg::Send {
+                    to_address: String::from("alice"),
+                    amount: coins(100, DENOM),
+                }),
+                CosmosMsg::Bank(BankMsg::Send {
+                    to_address: String::from("bob"),
+                    amount: coins(100, DENOM),
+                }),
+            ],
+            res.messages
+                .iter()
+                .map(|sub| sub.msg.clone())
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(2, res.events.len());
+        assert!(CHALLENGES.may_load(&deps.storage, id).unwrap().is_none());
+        crate::solvency::assert_tracked(deps.as_ref());
+    }
+
+    /// Resolves a 2-sided challenge of `stake` opened at the current house
+    /// fee and returns the payouts.
+    fn resolve_matched(deps: &mut DepsMut, stake: u128) -> Response {
+        open_challenge(
+            deps.branch(),
+            mock_env(),
+            mock_info("carol", &coins(stake, DENOM)),
+            CoinSide::Heads,
+        )
+        .unwrap();
+        let id = CHALLENGE_COUNT.load(deps.storage).unwrap();
+        accept_challenge(
+            deps.branch(),
+            mock_env(),
+            mock_info("bob", &coins(stake, DENOM)),
+            id,
+        )
+        .unwrap();
+        resolve_challenge(
+            deps.branch(),
+            mock_env(),
+            mock_info("fee_address", &[]),
+            id,
+            1231231809300,
+        )
+        .unwrap()
+    }
+
+    /// The seed draws tails, so bob wins.
+    fn prize_payout(amount: u128) -> SubMsg {
+        SubMsg::reply_always(
+            BankMsg::Send {
+                to_address: String::from("bob"),
+                amount: coins(amount, DENOM),
+            },
+            payout::PRIZE_REPLY_ID,
+        )
+    }
+
+    #[test]
+    fn test_resolve_without_house_fee() {
+        let mut deps = mock_dependencies();
+        setup_challenge(deps.as_mut(), CoinSide::Heads);
+        HOUSE_FEE_BPS.save(&mut deps.storage, &0).unwrap();
+
+        // Only the prize is sent.
+        let res = resolve_matched(&mut deps.as_mut(), 100);
+        assert_eq!(vec![prize_payout(200)], res.messages);
+        crate::solvency::assert_tracked(deps.as_ref());
+    }
+
+    #[test]
+    fn test_resolve_tiny_stakes() {
+        let mut deps = mock_dependencies();
+        setup_challenge(deps.as_mut(), CoinSide::Heads);
+
+        // 400 bps of 24 rounds down to no fee.
+        let res = resolve_matched(&mut deps.as_mut(), 12);
+        assert_eq!(vec![prize_payout(24)], res.messages);
+        crate::solvency::assert_tracked(deps.as_ref());
+    }
+}
@@ -3,14 +3,35 @@
 
 // #[cfg(not(feature = "library"))]
 use cosmwasm_std::{
-    coin, entry_point, Addr, BankMsg, CosmosMsg, DepsMut, Env, MessageInfo, Response,
+    coin, entry_point, to_json_binary, Addr, BankMsg, Coin, CosmosMsg, DepsMut, Env, MessageInfo,
+    Reply, Response, StdError, StdResult, Storage, SubMsg, SubMsgResult, Uint128, WasmMsg,
 };
 
 use cw2::set_contract_version;
 
-use crate::msg::UserBetInfo;
+use crate::allowlist;
+use crate::bonus;
+use crate::coinflip;
+use crate::denoms;
+use crate::events;
+use crate::governance;
+use crate::hooks::{self, HookMsg};
+use crate::limits;
+use crate::loyalty;
+use crate::msg::{PoolId, RandomnessMode, TicketConfig, TicketRange, UserBetInfo};
+use crate::nft::{self, TicketMetadata};
+use crate::oracle;
+use crate::payout;
+use crate::prediction;
 use crate::query::query_config;
-use crate::state::{State, CONFIG};
+use crate::randomness;
+use crate::solvency;
+use crate::staking;
+use crate::state::{
+    NftPrize, RoundRecord, SettledPot, State, CLOSE_TIMES, CONFIG, NFT_CONFIG, NFT_PRIZES,
+    NFT_TICKET_COUNTS, POOL_DENOMS, POT_BONUSES, RANDOMNESS_MODES, ROUND_FEES, ROUND_HISTORY,
+    ROUND_IDS, TICKET_CONFIGS, WITHDRAWAL_PENALTIES,
+};
 use crate::{
     msg::{ExecuteMsg, InstantiateMsg},
     ContractError,
@@ -59,225 +80,523 @@ pub fn execute(
     info: MessageInfo,
     msg: ExecuteMsg,
 ) -> Result<Response, ContractError> {
+    if governance::stakes_funds(&msg) && governance::is_paused(deps.storage)? {
+        return Err(ContractError::Paused);
+    }
     match msg {
-        ExecuteMsg::AddBetUserInfinite {} => add_bet_user_infinte(deps, env, info),
-        ExecuteMsg::AddBetUserWhale {} => add_bet_user_whale(deps, env, info),
-        ExecuteMsg::AddBetUserShrimp {} => add_bet_user_shrimp(deps, env, info),
-        ExecuteMsg::ToggleGameInfinte {} => toggle_game_infinte(deps, env, info),
-        ExecuteMsg::ToggleGameWhale {} => toggle_game_whale(deps, env, info),
-        ExecuteMsg::ToggleGameShrimp {} => toggle_game_shrimp(deps, env, info),
-        ExecuteMsg::EndGameInfinte { seed } => end_game_infinite(deps, env, info, seed),
-        ExecuteMsg::EndGameShrimp { seed } => end_game_shrimp(deps, env, info, seed),
-        ExecuteMsg::EndGameWhale { seed } => end_game_whale(deps, env, info, seed),
+        ExecuteMsg::AddBetUserInfinite { client_seed } => {
+            add_bet_user(deps, env, info, PoolId::Infinite, client_seed)
+        }
+        ExecuteMsg::AddBetUserWhale { client_seed } => {
+            add_bet_user(deps, env, info, PoolId::Whale, client_seed)
+        }
+        ExecuteMsg::AddBetUserShrimp { client_seed } => {
+            add_bet_user(deps, env, info, PoolId::Shrimp, client_seed)
+        }
+        ExecuteMsg::ToggleGameInfinte {} => toggle_game(deps, env, info, PoolId::Infinite),
+        ExecuteMsg::ToggleGameWhale {} => toggle_game(deps, env, info, PoolId::Whale),
+        ExecuteMsg::ToggleGameShrimp {} => toggle_game(deps, env, info, PoolId::Shrimp),
+        ExecuteMsg::EndGameInfinte { seed } => end_game(deps, env, info, PoolId::Infinite, seed),
+        ExecuteMsg::EndGameShrimp { seed } => end_game(deps, env, info, PoolId::Shrimp, seed),
+        ExecuteMsg::EndGameWhale { seed } => end_game(deps, env, info, PoolId::Whale, seed),
+        ExecuteMsg::SetTicketConfig {
+            pool_id,
+            ticket_config,
+        } => set_ticket_config(deps, env, info, pool_id, ticket_config),
+        ExecuteMsg::SetCloseTime { pool_id, closes_at } => {
+            set_close_time(deps, env, info, pool_id, closes_at)
+        }
+        ExecuteMsg::EndDueRounds { seed, limit } => end_due_rounds(deps, env, info, seed, limit),
+        ExecuteMsg::SetPoolDenoms { pool_id, denoms } => {
+            denoms::set_pool_denoms(deps, env, info, pool_id, denoms)
+        }
+        ExecuteMsg::SetOracleConfig { oracle_config } => {
+            oracle::set_oracle_config(deps, env, info, oracle_config)
+        }
+        ExecuteMsg::SetRandomnessMode { pool_id, mode } => {
+            randomness::set_randomness_mode(deps, env, info, pool_id, mode)
+        }
+        ExecuteMsg::Pause {} => {
+            governance::assert_owner(deps.as_ref(), &info)?;
+            governance::set_paused(deps, true)
+        }
+        ExecuteMsg::Unpause {} => {
+            governance::assert_owner(deps.as_ref(), &info)?;
+            governance::set_paused(deps, false)
+        }
+        ExecuteMsg::SetHouseFee { fee_bps } => {
+            governance::assert_owner(deps.as_ref(), &info)?;
+            governance::set_house_fee(deps, fee_bps)
+        }
+        ExecuteMsg::TransferOwnership { new_owner } => {
+            governance::assert_owner(deps.as_ref(), &info)?;
+            governance::transfer_ownership(deps, new_owner)
+        }
+        ExecuteMsg::CancelRound { pool_id } => {
+            governance::assert_owner(deps.as_ref(), &info)?;
+            governance::cancel_round(deps, env, pool_id)
+        }
+        ExecuteMsg::SetWithdrawalPenalty {
+            pool_id,
+            penalty_bps,
+        } => set_withdrawal_penalty(deps, env, info, pool_id, penalty_bps),
+        ExecuteMsg::WithdrawBet { pool_id } => withdraw_bet(deps, env, info, pool_id),
+        ExecuteMsg::SetNftConfig { nft_config } => nft::set_nft_config(deps, env, info, nft_config),
+        ExecuteMsg::ClaimNftPrize { token_id } => nft::claim_nft_prize(deps, env, info, token_id),
+        ExecuteMsg::SetAllowlistConfig { allowlist_config } => {
+            allowlist::set_allowlist_config(deps, env, info, allowlist_config)
+        }
+        ExecuteMsg::UpdateAllowlist { add, remove } => {
+            allowlist::update_allowlist(deps, env, info, add, remove)
+        }
+        ExecuteMsg::UpdateHooks { add, remove } => {
+            hooks::update_hooks(deps, env, info, add, remove)
+        }
+        ExecuteMsg::SetLoyaltyConfig { loyalty_config } => {
+            loyalty::set_loyalty_config(deps, env, info, loyalty_config)
+        }
+        ExecuteMsg::RedeemPoints { points, denom } => {
+            loyalty::redeem_points(deps, env, info, points, denom)
+        }
+        ExecuteMsg::PlaceFreeBet {
+            pool_id,
+            amount,
+            client_seed,
+        } => loyalty::place_free_bet(deps, env, info, pool_id, amount, client_seed),
+        ExecuteMsg::FundPot { pool_id } => bonus::fund_pot(deps, env, info, pool_id),
+        ExecuteMsg::ClaimEscrow {} => payout::claim_escrow(deps, env, info),
+        ExecuteMsg::SetWagerLimits { limits } => limits::set_wager_limits(deps, env, info, limits),
+        ExecuteMsg::SelfExclude { duration } => limits::self_exclude(deps, env, info, duration),
+        ExecuteMsg::SweepSurplus { denom } => solvency::sweep_surplus(deps, env, info, denom),
+        ExecuteMsg::ConfigureStakingPool { validator } => {
+            staking::configure_staking_pool(deps, env, info, validator)
+        }
+        ExecuteMsg::StakingDeposit {} => staking::staking_deposit(deps, env, info),
+        ExecuteMsg::StakingWithdraw { amount } => {
+            staking::staking_withdraw(deps, env, info, amount)
+        }
+        ExecuteMsg::SubmitUnbonding {} => staking::submit_unbonding(deps, env, info),
+        ExecuteMsg::ClaimUnbonded {} => staking::claim_unbonded(deps, env, info),
+        ExecuteMsg::HarvestStakingRewards {} => staking::harvest_staking_rewards(deps, env, info),
+        ExecuteMsg::EndGameStaking { seed } => staking::end_game_staking(deps, env, info, seed),
+        ExecuteMsg::OpenChallenge { side } => coinflip::open_challenge(deps, env, info, side),
+        ExecuteMsg::AcceptChallenge { challenge_id } => {
+            coinflip::accept_challenge(deps, env, info, challenge_id)
+        }
+        ExecuteMsg::CancelChallenge { challenge_id } => {
+            coinflip::cancel_challenge(deps, env, info, challenge_id)
+        }
+        ExecuteMsg::ResolveChallenge { challenge_id, seed } => {
+            coinflip::resolve_challenge(deps, env, info, challenge_id, seed)
+        }
+        ExecuteMsg::CreateMarket {
+            question,
+            outcomes,
+            resolver,
+            denom,
+            closes_at,
+            dispute_window,
+        } => prediction::create_market(
+            deps,
+            env,
+            info,
+            question,
+            outcomes,
+            resolver,
+            denom,
+            closes_at,
+            dispute_window,
+        ),
+        ExecuteMsg::PlaceMarketBet { market_id, outcome } => {
+            prediction::place_market_bet(deps, env, info, market_id, outcome)
+        }
+        ExecuteMsg::ResolveMarket { market_id, outcome } => {
+            prediction::resolve_market(deps, env, info, market_id, outcome)
+        }
+        ExecuteMsg::DisputeMarket { market_id } => {
+            prediction::dispute_market(deps, env, info, market_id)
+        }
+        ExecuteMsg::ClaimMarket { market_id } => {
+            prediction::claim_market(deps, env, info, market_id)
+        }
     }
 }
 
-fn add_bet_user_infinte(
+fn add_bet_user(
     deps: DepsMut,
     env: Env,
     info: MessageInfo,
+    pool: PoolId,
+    client_seed: Option<String>,
+) -> Result<Response, ContractError> {
+    let stake_coin = one_coin(&info)?;
+    place_bet(deps, env, info.sender, pool, stake_coin, client_seed, false)
+}
+
+/// Books a bet of `stake_coin` in the open round of `pool`. A free bet is
+/// paid with free-bet credits instead of attached funds.
+pub(crate) fn place_bet(
+    deps: DepsMut,
+    env: Env,
+    bettor: Addr,
+    pool: PoolId,
+    stake_coin: Coin,
+    client_seed: Option<String>,
+    free_bet: bool,
 ) -> Result<Response, ContractError> {
     let mut config = query_config(deps.as_ref())?;
 
     //missing the game start or not.
-    if config.start_flag_infinte == false {
-        return Err(ContractError::CustomError {
-            val: String::from("Bet is already started!"),
-        });
+    if !is_open(deps.storage, &config, pool, &env)? {
+        return Err(ContractError::PoolClosed { pool });
     }
+    allowlist::assert_allowed(deps.as_ref(), &bettor)?;
     //add pot total amount
 
-    let mut funds_amount = 0;
-    let mut coin_type: String = String::new();
+    let funds_amount = stake_coin.amount.u128();
+    let coin_type = stake_coin.denom.clone();
 
-    for element in info.funds.iter() {
-        funds_amount = element.amount.u128();
-        coin_type = element.denom.clone();
+    bonus::assert_round_denom(deps.storage, &config, pool, &coin_type)?;
+    let normalized_value = if POOL_DENOMS.has(deps.storage, pool.as_str()) {
+        oracle::normalized_value(deps.as_ref(), &env, &stake_coin)?
+    } else {
+        None
+    };
+    if let Some(max_bet) = pool.max_bet() {
+        if funds_amount > max_bet {
+            return Err(ContractError::BetLimitExceeded {
+                max: max_bet,
+                amount: funds_amount,
+            });
+        }
     }
 
-    if funds_amount == 0 {
-        return Err(ContractError::CustomError {
-            val: String::from("Not enough funds"),
-        });
+    let ticket_config = TICKET_CONFIGS.may_load(deps.storage, pool.as_str())?;
+    let tickets = match &ticket_config {
+        Some(ticket_config) => Some(issue_tickets(
+            config.user_list(pool),
+            &bettor,
+            ticket_config,
+            funds_amount,
+            &coin_type,
+        )?),
+        None => None,
+    };
+
+    limits::record_wager(
+        deps.storage,
+        env.block.time.seconds(),
+        &bettor,
+        funds_amount,
+    )?;
+    governance::fix_round_fee(deps.storage, pool.as_str())?;
+
+    // The stake arrived with the message or was paid in credits, no
+    // transfer needed.
+    let mut res = Response::new().add_attribute("action", "add_bet");
+    if free_bet {
+        res = res.add_attribute("free_bet", "true");
     }
 
-    let transfer_amount_infinte = BankMsg::Send {
-        to_address: env.contract.address.to_string(),
-        amount: vec![coin(funds_amount as u128, coin_type.clone())],
+    let round_id = current_round(deps.storage, pool)?;
+    let mut bet_placed = events::bet_placed(pool, round_id, &bettor, &stake_coin);
+    let order_id: usize = config.user_list(pool).len();
+    let (nft_token_id, nft_collection) = match NFT_CONFIG.may_load(deps.storage)? {
+        Some(nft_config) => {
+            let metadata = TicketMetadata {
+                pool_id: pool,
+                round_id,
+                stake: coin(funds_amount, coin_type.clone()),
+            };
+            // Numbered in mint order, as withdrawals renumber `user_order`.
+            let minted = NFT_TICKET_COUNTS
+                .may_load(deps.storage, (pool.as_str(), round_id))?
+                .unwrap_or_default()
+                + 1;
+            NFT_TICKET_COUNTS.save(deps.storage, (pool.as_str(), round_id), &minted)?;
+            let token_id = nft::ticket_token_id(&metadata, minted);
+            res = res.add_message(nft::mint_ticket_msg(
+                &nft_config,
+                &token_id,
+                &bettor,
+                metadata,
+            )?);
+            bet_placed = bet_placed.add_attribute("token_id", token_id.as_str());
+            (Some(token_id), Some(nft_config.collection))
+        }
+        None => (None, None),
     };
 
-    config.pot_total_infinte = config.pot_total_infinte + funds_amount;
-    let order_id: usize = config.user_list_infinte.len();
-    config.user_list_infinte.push(UserBetInfo {
-        user_address: info.sender.clone(),
+    *config.pot_total_mut(pool) += funds_amount;
+    solvency::owe(deps.storage, &coin_type, funds_amount)?;
+    config.user_list_mut(pool).push(UserBetInfo {
+        user_address: bettor.clone(),
         user_amount: funds_amount,
         user_order: order_id as u128 + 1,
-        coin_type: coin_type,
+        coin_type,
+        tickets: tickets.clone(),
+        nft_token_id,
+        nft_collection,
+        client_seed,
+        normalized_value,
+        free_bet,
     });
+
+    let mut sold_out = false;
+    if let (Some(tickets), Some(ticket_config)) = (tickets, ticket_config) {
+        bet_placed = bet_placed
+            .add_attribute("first_ticket", tickets.first.to_string())
+            .add_attribute("last_ticket", tickets.last.to_string());
+        // A ticket pool closes its round as soon as the last ticket is sold.
+        sold_out = tickets.last == ticket_config.supply;
+    }
+    res = res.add_event(bet_placed).add_submessages(hooks::hook_msgs(
+        deps.storage,
+        &HookMsg::BetPlacedHook {
+            pool_id: pool.to_string(),
+            round_id,
+            bettor: bettor.clone(),
+            amount: stake_coin.clone(),
+        },
+    )?);
+    if sold_out {
+        *config.start_flag_mut(pool) = false;
+        res = res.add_event(events::pool_toggled(pool, round_id, false));
+    }
     CONFIG.save(deps.storage, &config)?;
-    Ok(Response::new()
-        .add_message(CosmosMsg::Bank(transfer_amount_infinte))
-        .add_attribute("action", "bet_infinte")
-        .add_attribute("bet_user_address", info.sender.to_string())
-        .add_attribute("bet_user_funds", funds_amount.to_string()))
+    solvency::assert_solvent(deps.as_ref(), &env, &stake_coin.denom, 0)?;
+    Ok(res)
 }
 
-fn add_bet_user_whale(
-    deps: DepsMut,
-    _env: Env,
-    info: MessageInfo,
-) -> Result<Response, ContractError> {
-    let mut config = query_config(deps.as_ref())?;
-
-    //missing the game start or not.
-    if config.start_flag_whale == false {
-        return Err(ContractError::CustomError {
-            val: String::from("Bet is already started!"),
+/// Numbers the tickets bought with `funds_amount`, continuing after the
+/// tickets already sold in the round.
+pub(crate) fn issue_tickets(
+    bets: &[UserBetInfo],
+    sender: &Addr,
+    ticket_config: &TicketConfig,
+    funds_amount: u128,
+    coin_type: &str,
+) -> Result<TicketRange, ContractError> {
+    let count = (funds_amount / ticket_config.price) as u64;
+    if coin_type != ticket_config.denom || count as u128 * ticket_config.price != funds_amount {
+        return Err(ContractError::InvalidTicketPayment {
+            price: ticket_config.price,
+            denom: ticket_config.denom.clone(),
         });
     }
-    //add pot total amountf
 
-    let mut funds_amount = 0;
-    let mut coin_type: String = String::new();
+    let held = tickets_held(bets, sender);
+    if held + count > ticket_config.max_per_user {
+        return Err(ContractError::TicketLimitExceeded {
+            max_per_user: ticket_config.max_per_user,
+        });
+    }
 
-    for element in info.funds.iter() {
-        funds_amount = element.amount.u128();
-        coin_type = element.denom.clone();
+    let sold = tickets_sold(bets);
+    if sold + count > ticket_config.supply {
+        return Err(ContractError::TicketsSoldOut {
+            remaining: ticket_config.supply - sold,
+        });
     }
 
-    let transfer_amount_whale = BankMsg::Send {
-        to_address: _env.contract.address.to_string(),
-        amount: vec![coin(funds_amount as u128, coin_type.clone())],
-    };
-    //check the balance
+    Ok(TicketRange {
+        first: sold + 1,
+        last: sold + count,
+    })
+}
 
-    if funds_amount > 500 {
-        return Err(ContractError::CustomError {
-            val: String::from("Bet amounts are less than 500 SEI"),
-        });
+/// The single, non-zero coin attached to a bet or stake.
+pub(crate) fn one_coin(info: &MessageInfo) -> Result<Coin, ContractError> {
+    match info.funds.as_slice() {
+        [stake] if !stake.amount.is_zero() => Ok(stake.clone()),
+        [] | [_] => Err(ContractError::NoFunds),
+        _ => Err(ContractError::MultipleDenoms),
     }
+}
 
-    config.pot_total_whale = config.pot_total_whale + funds_amount;
-    let order_id: usize = config.user_list_whale.len();
-    config.user_list_whale.push(UserBetInfo {
-        user_address: info.sender.clone(),
-        user_amount: funds_amount,
-        user_order: order_id as u128 + 1,
-        coin_type: coin_type,
-    });
-    CONFIG.save(deps.storage, &config)?;
-    Ok(Response::new()
-        .add_message(CosmosMsg::Bank(transfer_amount_whale))
-        .add_attribute("action", "bet_whale")
-        .add_attribute("bet_user_address", info.sender.to_string())
-        .add_attribute("bet_user_funds", funds_amount.to_string()))
+pub(crate) fn tickets_held(bets: &[UserBetInfo], address: &Addr) -> u64 {
+    bets.iter()
+        .filter(|bet| bet.user_address == *address)
+        .filter_map(|bet| bet.tickets.as_ref())
+        .map(|tickets| tickets.last - tickets.first + 1)
+        .sum()
+}
+
+pub(crate) fn tickets_sold(bets: &[UserBetInfo]) -> u64 {
+    bets.last()
+        .and_then(|bet| bet.tickets.as_ref())
+        .map_or(0, |tickets| tickets.last)
 }
 
-fn add_bet_user_shrimp(
+/// Switches a pool to ticket sales, or back to free stakes with `None`.
+/// Only allowed between rounds.
+fn set_ticket_config(
     deps: DepsMut,
     _env: Env,
     info: MessageInfo,
+    pool: PoolId,
+    ticket_config: Option<TicketConfig>,
 ) -> Result<Response, ContractError> {
-    let mut config = query_config(deps.as_ref())?;
-
-    //missing the game start or not.
-    if config.start_flag_shrimp == false {
-        return Err(ContractError::CustomError {
-            val: String::from("Bet is already started!"),
-        });
+    let config = query_config(deps.as_ref())?;
+    if info.sender != config.fee_address {
+        return Err(ContractError::Unauthorized);
     }
-    //add pot total amountf
-
-    let mut funds_amount = 0;
-    let mut coin_type: String = String::new();
-
-    for element in info.funds.iter() {
-        funds_amount = element.amount.u128();
-        coin_type = element.denom.clone();
+    if bonus::round_started(deps.storage, &config, pool)? {
+        return Err(ContractError::RoundInProgress { pool });
     }
 
-    if funds_amount > 50 {
-        return Err(ContractError::CustomError {
-            val: String::from("Bet amounts are less than 50 SEI"),
-        });
+    match &ticket_config {
+        Some(ticket_config) => {
+            if ticket_config.price == 0
+                || ticket_config.supply == 0
+                || ticket_config.max_per_user == 0
+            {
+                return Err(ContractError::InvalidTicketConfig);
+            }
+            TICKET_CONFIGS.save(deps.storage, pool.as_str(), ticket_config)?;
+        }
+        None => TICKET_CONFIGS.remove(deps.storage, pool.as_str()),
     }
 
-    let transfer_amount_shrimp = BankMsg::Send {
-        to_address: _env.contract.address.to_string(),
-        amount: vec![coin(funds_amount as u128, coin_type.clone())],
-    };
-
-    config.pot_total_shrimp = config.pot_total_shrimp + funds_amount;
-    let order_id: usize = config.user_list_shrimp.len();
-    config.user_list_shrimp.push(UserBetInfo {
-        user_address: info.sender.clone(),
-        user_amount: funds_amount,
-        user_order: order_id as u128 + 1,
-        coin_type: coin_type,
-    });
-    CONFIG.save(deps.storage, &config)?;
     Ok(Response::new()
-        .add_message(CosmosMsg::Bank(transfer_amount_shrimp))
-        .add_attribute("action", "bet_shrimp")
-        .add_attribute("bet_user_address", info.sender.to_string())
-        .add_attribute("bet_user_funds", funds_amount.to_string()))
+        .add_attribute("action", "set_ticket_config")
+        .add_attribute("pool_id", pool.to_string())
+        .add_attribute("tickets", ticket_config.is_some().to_string()))
 }
 
-fn toggle_game_infinte(
+fn set_withdrawal_penalty(
     deps: DepsMut,
     _env: Env,
     info: MessageInfo,
+    pool: PoolId,
+    penalty_bps: u64,
 ) -> Result<Response, ContractError> {
-    let mut config = query_config(deps.as_ref())?;
+    let config = query_config(deps.as_ref())?;
     if info.sender != config.fee_address {
-        return Err(ContractError::CustomError {
-            val: String::from("Authorization Error"),
-        });
+        return Err(ContractError::Unauthorized);
     }
-    config.start_flag_infinte = !config.start_flag_infinte;
-    CONFIG.save(deps.storage, &config)?;
+    if penalty_bps > 10000 {
+        return Err(ContractError::InvalidPenalty { penalty_bps });
+    }
+    WITHDRAWAL_PENALTIES.save(deps.storage, pool.as_str(), &penalty_bps)?;
 
     Ok(Response::new()
-        .add_attribute("action", "bet")
-        .add_attribute("bet_user_address", info.sender.to_string()))
+        .add_attribute("action", "set_withdrawal_penalty")
+        .add_attribute("pool_id", pool.to_string())
+        .add_attribute("penalty_bps", penalty_bps.to_string()))
 }
 
-fn toggle_game_whale(
+/// Removes the sender's bets from the open round. The remaining bets are
+/// renumbered, and in ticket pools their tickets too, so the round looks as
+/// if the withdrawn bets were never placed.
+fn withdraw_bet(
     deps: DepsMut,
-    _env: Env,
+    env: Env,
     info: MessageInfo,
+    pool: PoolId,
 ) -> Result<Response, ContractError> {
     let mut config = query_config(deps.as_ref())?;
-    if info.sender != config.fee_address {
-        return Err(ContractError::CustomError {
-            val: String::from("Authorization Error"),
-        });
+    if !is_open(deps.storage, &config, pool, &env)? {
+        return Err(ContractError::PoolClosed { pool });
+    }
+
+    let (withdrawn, mut kept): (Vec<UserBetInfo>, Vec<UserBetInfo>) = config
+        .user_list(pool)
+        .iter()
+        .cloned()
+        .partition(|bet| bet.user_address == info.sender);
+    if withdrawn.is_empty() {
+        return Err(ContractError::NoBetToWithdraw { pool });
+    }
+    if let Some(token_id) = withdrawn.iter().find_map(|bet| bet.nft_token_id.clone()) {
+        return Err(ContractError::TicketNftMinted { token_id });
+    }
+
+    let mut next_ticket = 1;
+    for (index, bet) in kept.iter_mut().enumerate() {
+        bet.user_order = index as u128 + 1;
+        if let Some(tickets) = bet.tickets.as_mut() {
+            let count = tickets.last - tickets.first + 1;
+            tickets.first = next_ticket;
+            tickets.last = next_ticket + count - 1;
+            next_ticket += count;
+        }
     }
-    config.start_flag_whale = !config.start_flag_whale;
+
+    // Stakes are refunded and penalised per denom. Free bets go back to the
+    // bettor's credits, in full.
+    let (free, paid): (Vec<UserBetInfo>, Vec<UserBetInfo>) =
+        withdrawn.into_iter().partition(|bet| bet.free_bet);
+    let withdrawn_total: u128 = free.iter().chain(&paid).map(|bet| bet.user_amount).sum();
+    *config.user_list_mut(pool) = kept;
+    *config.pot_total_mut(pool) -= withdrawn_total;
     CONFIG.save(deps.storage, &config)?;
+    solvency::discharge_coins(deps.storage, &denoms::pot_totals(&free))?;
+    solvency::discharge_coins(deps.storage, &denoms::pot_totals(&paid))?;
+    for credit in denoms::pot_totals(&free) {
+        loyalty::add_credit(deps.storage, &info.sender, credit)?;
+    }
 
-    Ok(Response::new()
-        .add_attribute("action", "bet")
-        .add_attribute("bet_user_address", info.sender.to_string()))
+    let penalty_bps = WITHDRAWAL_PENALTIES
+        .may_load(deps.storage, pool.as_str())?
+        .unwrap_or_default();
+    let mut refunds = vec![];
+    let mut penalties = vec![];
+    for stake in denoms::pot_totals(&paid) {
+        let penalty = stake.amount.multiply_ratio(penalty_bps, 10000u128);
+        if !penalty.is_zero() {
+            penalties.push(Coin::new(penalty.u128(), stake.denom.clone()));
+        }
+        if penalty < stake.amount {
+            refunds.push(Coin::new((stake.amount - penalty).u128(), stake.denom));
+        }
+    }
+    // The bank module wants the coins of a send sorted by denom.
+    refunds.sort_by(|a, b| a.denom.cmp(&b.denom));
+    penalties.sort_by(|a, b| a.denom.cmp(&b.denom));
+
+    let round_id = current_round(deps.storage, pool)?;
+    let mut res = Response::new().add_attribute("action", "withdraw_bet");
+    for refund in &refunds {
+        res = res.add_event(events::refund(pool, round_id, &info.sender, refund));
+    }
+    if !refunds.is_empty() {
+        res = res.add_message(CosmosMsg::Bank(BankMsg::Send {
+            to_address: info.sender.to_string(),
+            amount: refunds,
+        }));
+    }
+    if !penalties.is_empty() {
+        let penalty: Vec<String> = penalties.iter().map(Coin::to_string).collect();
+        res = res
+            .add_attribute("penalty", penalty.join(","))
+            .add_message(CosmosMsg::Bank(BankMsg::Send {
+                to_address: config.fee_address.to_string(),
+                amount: penalties,
+            }));
+    }
+    Ok(res)
 }
 
-fn toggle_game_shrimp(
+fn toggle_game(
     deps: DepsMut,
     _env: Env,
     info: MessageInfo,
+    pool: PoolId,
 ) -> Result<Response, ContractError> {
     let mut config = query_config(deps.as_ref())?;
     if info.sender != config.fee_address {
-        return Err(ContractError::CustomError {
-            val: String::from("Authorization Error"),
-        });
+        return Err(ContractError::Unauthorized);
     }
-    config.start_flag_shrimp = !config.start_flag_shrimp;
+    let start_flag = config.start_flag_mut(pool);
+    *start_flag = !*start_flag;
+    let open = *start_flag;
     CONFIG.save(deps.storage, &config)?;
 
+    let round_id = current_round(deps.storage, pool)?;
     Ok(Response::new()
-        .add_attribute("action", "bet")
-        .add_attribute("bet_user_address", info.sender.to_string()))
+        .add_attribute("action", "toggle_game")
+        .add_event(events::pool_toggled(pool, round_id, open)))
 }
 struct Lcg {
     state: u64,
@@ -304,242 +623,356 @@ pub fn generate_random_value(seed: u64) -> u128 {
 
     let timestamp = lcg.next(); // Get the current block's timestamp
     let random_value = timestamp % 10000; // Generate a random number between 1 and 100
-    println!("randome value {}", random_value);
     random_value as u128
 }
 
-fn end_game_infinite(
+/// Splits a settled pot into the house fee, at `fee_bps` basis points, and
+/// the prize.
+pub(crate) fn split_pot(pot_total: u128, fee_bps: u64) -> (u128, u128) {
+    let fees = Uint128::new(pot_total)
+        .multiply_ratio(fee_bps, 10000u128)
+        .u128();
+    (fees, pot_total - fees)
+}
+
+/// Stake-weighted winner selection shared by the draws.
+/// Each bet covers its stake of `0..pot_total`, laid out in bet order, and
+/// the bet covering the basis-point `random_number` scaled onto the pot
+/// wins. Only `None` if the bets add up to less than `pot_total`.
+pub(crate) fn select_winner(
+    bets: &[UserBetInfo],
+    pot_total: u128,
+    random_number: u128,
+) -> Option<&UserBetInfo> {
+    let target = Uint128::new(pot_total)
+        .multiply_ratio(random_number, 10000u128)
+        .u128();
+    let mut covered = 0u128;
+    bets.iter().find(|bet| {
+        covered += bet.user_amount;
+        covered > target
+    })
+}
+
+/// Chance in basis points that a stake of `stake` out of `total` wins the draw.
+pub(crate) fn win_chance(stake: u128, total: u128) -> u128 {
+    Uint128::new(stake).multiply_ratio(10000u128, total).u128()
+}
+
+/// Picks the winning ticket, mapping the basis-point random number onto the
+/// tickets sold, and returns its holder.
+pub(crate) fn select_winning_ticket(
+    bets: &[UserBetInfo],
+    random_number: u128,
+) -> Option<&UserBetInfo> {
+    let winning_ticket = (random_number * tickets_sold(bets) as u128 / 10000) as u64 + 1;
+    bets.iter().find(|bet| {
+        matches!(&bet.tickets, Some(tickets)
+            if tickets.first <= winning_ticket && winning_ticket <= tickets.last)
+    })
+}
+
+fn end_game(
     deps: DepsMut,
-    _env: Env,
-    _info: MessageInfo,
+    env: Env,
+    info: MessageInfo,
+    pool: PoolId,
     seed: u64,
 ) -> Result<Response, ContractError> {
     let mut config = query_config(deps.as_ref())?;
 
-    if _info.sender != config.fee_address {
-        return Err(ContractError::CustomError {
-            val: String::from("Authorization Error"),
-        });
+    // The contract settles rounds itself in `EndDueRounds`.
+    if info.sender != config.fee_address && info.sender != env.contract.address {
+        return Err(ContractError::Unauthorized);
     }
-    let infinte_random_number = generate_random_value(seed);
-
-    if config.start_flag_infinte == true {
-        return Err(ContractError::CustomError {
-            val: String::from("Game is still open"),
-        });
+    if is_open(deps.storage, &config, pool, &env)? {
+        return Err(ContractError::PoolStillOpen { pool });
     }
-    //for infinte
-    let mut previous_percentage_infinte = 0;
-    let mut winner_address_infinte: Addr = Addr::unchecked("");
-    let transfer_fees_infinte: BankMsg;
-    let transfer_prize_infinte: BankMsg;
 
-    let mut coin_type: String = String::from("usei");
-
-    if config.pot_total_infinte == 0 {
-        return Err(ContractError::CustomError {
-            val: String::from("pot total infinte is zero so can't end game"),
+    let pot_total = config.pot_total(pool);
+    if pot_total == 0 {
+        return Err(ContractError::EmptyPot {
+            pool: pool.to_string(),
         });
     }
 
-    for infinite_user in &config.user_list_infinte {
-        coin_type = infinite_user.coin_type.clone();
-        let percentage: u128 = previous_percentage_infinte
-            + infinite_user.user_amount * 100 * 100 / config.pot_total_infinte;
-
-        if previous_percentage_infinte < infinte_random_number && percentage > infinte_random_number
-        {
-            winner_address_infinte = infinite_user.user_address.clone();
-            println!("winner Address, {}", winner_address_infinte.to_string());
-            break;
-        }
-
-        previous_percentage_infinte = percentage;
+    let bets = config.user_list(pool);
+    let round_id = current_round(deps.storage, pool)?;
+    let random_number = match RANDOMNESS_MODES
+        .may_load(deps.storage, pool.as_str())?
+        .unwrap_or_default()
+    {
+        RandomnessMode::Seed => generate_random_value(seed),
+        RandomnessMode::Entropy => randomness::entropy_random_value(pool, round_id, bets, &env),
+    };
+    let winner = if TICKET_CONFIGS.has(deps.storage, pool.as_str()) {
+        select_winning_ticket(bets, random_number).cloned()
+    } else if let Some(pool_denoms) = POOL_DENOMS.may_load(deps.storage, pool.as_str())? {
+        let weighted = denoms::weighted_bets(bets, &pool_denoms)?;
+        let total_weight = denoms::total_weight(&weighted)?;
+        select_winner(&weighted, total_weight, random_number).cloned()
+    } else {
+        select_winner(bets, pot_total, random_number).cloned()
     }
-
-    // for infinte
-    let fees = config.pot_total_infinte * 4 / 100;
-
-    let prize = config.pot_total_infinte - fees;
-
-    transfer_fees_infinte = BankMsg::Send {
-        to_address: config.fee_address.to_string(),
-        amount: vec![coin(fees as u128, coin_type.clone())],
+    .ok_or(ContractError::NoWinner)?;
+    let winner_address = winner.user_address;
+    // Points are earned by the paid bets that stayed in until the draw, so
+    // a bet withdrawn or cancelled earns none.
+    for bet in bets.iter().filter(|bet| !bet.free_bet) {
+        loyalty::accrue_points(deps.storage, &bet.user_address, bet.user_amount)?;
+    }
+    // The prize of a winning NFT ticket waits for whoever holds the ticket.
+    let winner_token_id = match NFT_CONFIG.may_load(deps.storage)? {
+        Some(nft_config) if nft_config.claim_by_holder => winner.nft_token_id,
+        _ => None,
     };
-
-    transfer_prize_infinte = BankMsg::Send {
-        to_address: winner_address_infinte.to_string(),
-        amount: vec![coin(prize as u128, coin_type.clone())],
+    let winner_collection = winner.nft_collection;
+
+    // Every denom's pot is split and paid on its own, at the fee the round
+    // opened with.
+    let fee_bps = governance::round_fee_bps(deps.storage, pool.as_str())?;
+    // The bonus is paid on top of the prize of its denom, without a fee.
+    let bonus = bonus::pot_bonus(deps.storage, pool)?;
+    let bonus_in = |denom: &str| {
+        bonus
+            .iter()
+            .find(|coin| coin.denom == denom)
+            .map_or(0, |coin| coin.amount.u128())
     };
+    let mut pots = denoms::pot_totals(bets);
+    for coin in &bonus {
+        if !pots.iter().any(|pot| pot.denom == coin.denom) {
+            pots.push(Coin::new(0, coin.denom.clone()));
+        }
+    }
 
-    config.user_list_infinte = vec![];
-    config.pot_total_infinte = 0;
-    config.start_flag_infinte = !config.start_flag_infinte;
+    let mut res = Response::new();
+    let mut settled = vec![];
+    let mut nft_prize = vec![];
+    let mut outgoing = vec![];
+    for (index, pot) in pots.into_iter().enumerate() {
+        let (fee, prize) = split_pot(pot.amount.u128(), fee_bps);
+        let won = prize + bonus_in(&pot.denom);
+        let (fee_reply_id, prize_reply_id) = payout::reply_ids(index);
+        res = res.add_submessages(payout::payout_msg(
+            deps.storage,
+            fee_reply_id,
+            &config.fee_address,
+            coin(fee, pot.denom.clone()),
+            pool.as_str(),
+            round_id,
+        )?);
+        match winner_token_id {
+            Some(_) => {
+                nft_prize.push(coin(won, pot.denom.clone()));
+                outgoing.push((pot.denom.clone(), fee));
+            }
+            None => {
+                res = res.add_submessages(payout::payout_msg(
+                    deps.storage,
+                    prize_reply_id,
+                    &winner_address,
+                    coin(won, pot.denom.clone()),
+                    pool.as_str(),
+                    round_id,
+                )?);
+                outgoing.push((pot.denom.clone(), fee + won));
+            }
+        }
+        // Denoms only in the bonus have no pot to settle.
+        if !pot.amount.is_zero() {
+            settled.push(SettledPot { pot, prize, fee });
+        }
+    }
+    if let Some(token_id) = &winner_token_id {
+        // Tickets minted before bets recorded their collection fall back to
+        // the current one.
+        let collection = match winner_collection {
+            Some(collection) => collection,
+            None => NFT_CONFIG.load(deps.storage)?.collection,
+        };
+        let nft_prize = NftPrize {
+            collection,
+            prize: nft_prize,
+        };
+        solvency::owe_coins(deps.storage, &nft_prize.prize)?;
+        NFT_PRIZES.save(deps.storage, token_id, &nft_prize)?;
+        res = res.add_attribute("winner_token_id", token_id);
+    }
 
-    CONFIG.save(deps.storage, &config)?;
+    solvency::discharge_coins(deps.storage, &denoms::pot_totals(config.user_list(pool)))?;
+    solvency::discharge_coins(deps.storage, &bonus)?;
+    config.user_list_mut(pool).clear();
+    *config.pot_total_mut(pool) = 0;
+    *config.start_flag_mut(pool) = true;
 
-    Ok(Response::new()
-        .add_message(CosmosMsg::Bank(transfer_fees_infinte))
-        .add_message(CosmosMsg::Bank(transfer_prize_infinte))
+    CONFIG.save(deps.storage, &config)?;
+    CLOSE_TIMES.remove(deps.storage, pool.as_str());
+    POT_BONUSES.remove(deps.storage, pool.as_str());
+    ROUND_FEES.remove(deps.storage, pool.as_str());
+    ROUND_IDS.save(deps.storage, pool.as_str(), &(round_id + 1))?;
+    let mut other_pots = settled.clone();
+    let main_pot = other_pots.remove(0);
+    ROUND_HISTORY.save(
+        deps.storage,
+        (pool.as_str(), round_id),
+        &RoundRecord {
+            winner: winner_address.clone(),
+            pot: main_pot.pot,
+            prize: main_pot.prize,
+            fee: main_pot.fee,
+            settled_at: env.block.time.seconds(),
+            failed_payouts: vec![],
+            other_pots,
+            bonus,
+        },
+    )?;
+
+    res = res
         .add_attribute("action", "end_game")
-        .add_attribute("winner_infinte", winner_address_infinte.to_string())
-        .add_attribute("method", "reset_for_next_round"))
+        .add_submessages(hooks::hook_msgs(
+            deps.storage,
+            &HookMsg::RoundSettledHook {
+                pool_id: pool.to_string(),
+                round_id,
+                winner: winner_address.clone(),
+                pots: settled.iter().map(|settled| settled.pot.clone()).collect(),
+            },
+        )?);
+    for (denom, outgoing) in outgoing {
+        solvency::assert_solvent(deps.as_ref(), &env, &denom, outgoing)?;
+    }
+    for settled_pot in settled {
+        res = res.add_event(events::round_settled(
+            pool.as_str(),
+            round_id,
+            &winner_address,
+            &settled_pot.pot,
+            settled_pot.prize,
+            settled_pot.fee,
+        ));
+    }
+    Ok(res)
 }
 
-fn end_game_whale(
+fn set_close_time(
     deps: DepsMut,
     _env: Env,
-    _info: MessageInfo,
-    seed: u64,
+    info: MessageInfo,
+    pool: PoolId,
+    closes_at: Option<u64>,
 ) -> Result<Response, ContractError> {
-    let mut config = query_config(deps.as_ref())?;
-    if _info.sender != config.fee_address {
-        return Err(ContractError::CustomError {
-            val: String::from("Authorization Error"),
-        });
-    }
-    let whale_random_number = generate_random_value(seed);
-
-    //for whale
-    let mut previous_percentage_whale = 0;
-    let mut winner_address_whale: Addr = Addr::unchecked("");
-    let transfer_fees_whale: BankMsg;
-    let transfer_prize_whale: BankMsg;
-    let mut coin_type: String = String::from("usei");
-
-    if config.start_flag_whale == true {
-        return Err(ContractError::CustomError {
-            val: String::from("Game is still open"),
-        });
-    }
-
-    if config.pot_total_whale == 0 {
-        return Err(ContractError::CustomError {
-            val: String::from("pot total whale is zero so can't end game"),
-        });
+    let config = query_config(deps.as_ref())?;
+    if info.sender != config.fee_address {
+        return Err(ContractError::Unauthorized);
     }
-
-    //for whale
-    for whale_user in &config.user_list_whale {
-        let percentage = previous_percentage_whale
-            + whale_user.user_amount * 100 * 100 / config.pot_total_infinte;
-        coin_type = whale_user.coin_type.clone();
-        if previous_percentage_whale < whale_random_number && percentage > whale_random_number {
-            winner_address_whale = whale_user.user_address.clone();
-            break;
-        }
-
-        previous_percentage_whale = percentage;
+    match closes_at {
+        Some(closes_at) => CLOSE_TIMES.save(deps.storage, pool.as_str(), &closes_at)?,
+        None => CLOSE_TIMES.remove(deps.storage, pool.as_str()),
     }
-    // for wale send
-    let fees = config.pot_total_infinte * 4 / 100;
-
-    let prize = config.pot_total_infinte - fees;
-
-    transfer_fees_whale = BankMsg::Send {
-        to_address: config.fee_address.to_string(),
-        amount: vec![coin(fees as u128, coin_type.clone())],
-    };
-
-    transfer_prize_whale = BankMsg::Send {
-        to_address: winner_address_whale.to_string(),
-        amount: vec![coin(prize as u128, coin_type.clone())],
-    };
-
-    config.user_list_infinte = vec![];
-    config.pot_total_infinte = 0;
-    config.start_flag_infinte = !config.start_flag_infinte;
-
-    CONFIG.save(deps.storage, &config)?;
 
     Ok(Response::new()
-        .add_message(CosmosMsg::Bank(transfer_fees_whale))
-        .add_message(CosmosMsg::Bank(transfer_prize_whale))
-        .add_attribute("action", "end_game")
-        .add_attribute("winner_infinte", winner_address_whale.to_string())
-        .add_attribute("method", "reset_for_next_round"))
+        .add_attribute("action", "set_close_time")
+        .add_attribute("pool_id", pool.to_string())
+        .add_attribute(
+            "closes_at",
+            closes_at.map_or(String::new(), |t| t.to_string()),
+        ))
 }
 
-fn end_game_shrimp(
+/// Sends an end game message to the contract itself for every due pool with
+/// bets. The reply id is the pool's index in `PoolId::ALL`.
+fn end_due_rounds(
     deps: DepsMut,
-    _env: Env,
-    _info: MessageInfo,
+    env: Env,
+    info: MessageInfo,
     seed: u64,
+    limit: Option<u32>,
 ) -> Result<Response, ContractError> {
-    let mut config = query_config(deps.as_ref())?;
-    if _info.sender != config.fee_address {
-        return Err(ContractError::CustomError {
-            val: String::from("Authorization Error"),
-        });
-    }
-    let shrimp_random_number = generate_random_value(seed);
-
-    //for shrimp
-    let mut previous_percentage_shrimp = 0;
-    let mut winner_address_shrimp: Addr = Addr::unchecked("");
-    let transfer_fees_shrimp: BankMsg;
-    let transfer_prize_shrimp: BankMsg;
-    let mut coin_type: String = String::from("usei");
-    if config.start_flag_shrimp == true {
-        return Err(ContractError::CustomError {
-            val: String::from("Game is still open"),
-        });
-    }
-
-    if config.pot_total_shrimp == 0 {
-        return Err(ContractError::CustomError {
-            val: String::from("pot total infinte is zero so can't end game"),
-        });
+    let config = query_config(deps.as_ref())?;
+    if info.sender != config.fee_address {
+        return Err(ContractError::Unauthorized);
     }
 
-    //for shrimp
-    for shrimp_user in &config.user_list_shrimp {
-        let percentage = previous_percentage_shrimp
-            + shrimp_user.user_amount * 100 * 100 / config.pot_total_infinte;
-        coin_type = shrimp_user.coin_type.clone();
-        if previous_percentage_shrimp < shrimp_random_number && percentage > shrimp_random_number {
-            winner_address_shrimp = shrimp_user.user_address.clone();
-            print!("winner address {}", winner_address_shrimp.to_string());
+    let limit = limit.map_or(PoolId::ALL.len(), |limit| limit as usize);
+    let mut submessages = vec![];
+    for (index, &pool) in PoolId::ALL.iter().enumerate() {
+        if submessages.len() == limit {
             break;
         }
-
-        previous_percentage_shrimp = percentage;
+        let closes_at = CLOSE_TIMES.may_load(deps.storage, pool.as_str())?;
+        let due = matches!(closes_at, Some(closes_at) if closes_at <= env.block.time.seconds());
+        if !due || config.pot_total(pool) == 0 {
+            continue;
+        }
+        // Every pool draws from its own seed.
+        let seed = seed.wrapping_add(index as u64);
+        let end_game = WasmMsg::Execute {
+            contract_addr: env.contract.address.to_string(),
+            msg: to_json_binary(&end_game_msg(pool, seed))?,
+            funds: vec![],
+        };
+        submessages.push(SubMsg::reply_on_error(end_game, index as u64));
     }
 
-    // for wale send
-    let fees = config.pot_total_infinte * 4 / 100;
-
-    let prize = config.pot_total_infinte - fees;
-
-    transfer_fees_shrimp = BankMsg::Send {
-        to_address: config.fee_address.to_string(),
-        amount: vec![coin(fees as u128, coin_type.clone())],
-    };
+    Ok(Response::new()
+        .add_attribute("action", "end_due_rounds")
+        .add_attribute("rounds", submessages.len().to_string())
+        .add_submessages(submessages))
+}
 
-    transfer_prize_shrimp = BankMsg::Send {
-        to_address: winner_address_shrimp.to_string(),
-        amount: vec![coin(prize as u128, coin_type.clone())],
-    };
+fn end_game_msg(pool: PoolId, seed: u64) -> ExecuteMsg {
+    match pool {
+        PoolId::Infinite => ExecuteMsg::EndGameInfinte { seed },
+        PoolId::Whale => ExecuteMsg::EndGameWhale { seed },
+        PoolId::Shrimp => ExecuteMsg::EndGameShrimp { seed },
+    }
+}
 
-    config.user_list_infinte = vec![];
-    config.pot_total_infinte = 0;
-    config.start_flag_infinte = !config.start_flag_infinte;
+/// Reports pools that failed to settle in `EndDueRounds`. Their round stays
+/// as it was, so the settlement can be retried. Draw payouts are cleared
+/// once they have run, and the bounced ones handed to the escrow.
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn reply(deps: DepsMut, _env: Env, msg: Reply) -> Result<Response, ContractError> {
+    if msg.id == hooks::HOOK_REPLY_ID {
+        return hooks::hook_failed(msg);
+    }
+    if payout::is_payout_reply(msg.id) {
+        return payout::payout_replied(deps, msg);
+    }
+    if msg.id == staking::UNBONDING_REPLY_ID {
+        return staking::unbonding_submitted(deps, msg);
+    }
+    let pool = *PoolId::ALL
+        .get(msg.id as usize)
+        .ok_or_else(|| StdError::generic_err(format!("Unknown reply id {}", msg.id)))?;
+    let mut res = Response::new().add_attribute("action", "end_due_rounds_reply");
+    if let SubMsgResult::Err(error) = msg.result {
+        let round_id = current_round(deps.storage, pool)?;
+        res = res.add_event(events::settlement_failed(pool, round_id, &error));
+    }
+    Ok(res)
+}
 
-    CONFIG.save(deps.storage, &config)?;
+/// Whether `pool` takes bets: it has not been toggled closed and its close
+/// time, if any, has not passed.
+fn is_open(storage: &dyn Storage, config: &State, pool: PoolId, env: &Env) -> StdResult<bool> {
+    let closes_at = CLOSE_TIMES.may_load(storage, pool.as_str())?;
+    Ok(config.start_flag(pool)
+        && !matches!(closes_at, Some(closes_at) if closes_at <= env.block.time.seconds()))
+}
 
-    Ok(Response::new()
-        .add_message(CosmosMsg::Bank(transfer_fees_shrimp))
-        .add_message(CosmosMsg::Bank(transfer_prize_shrimp))
-        .add_attribute("action", "end_game")
-        .add_attribute("winner_infinte", winner_address_shrimp.to_string())
-        .add_attribute("method", "reset_for_next_round"))
+/// Round of `pool` currently taking bets, counting from 1.
+pub(crate) fn current_round(storage: &dyn Storage, pool: PoolId) -> StdResult<u64> {
+    Ok(ROUND_IDS.may_load(storage, pool.as_str())?.unwrap_or(1))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use cosmwasm_std::testing::{mock_dependencies, mock_env, mock_info};
-    use cosmwasm_std::{coins, Addr};
+    use crate::query::query_tickets;
+    use cosmwasm_std::testing::{mock_dependencies_with_balance, mock_env, mock_info};
+    use cosmwasm_std::{coins, Addr, Event};
 
     const DENOM: &str = "sei";
 
@@ -553,8 +986,8 @@ mod tests {
     #[test]
     fn proper_initialization() {
         // Create mock dependencies and environment
-        let mut deps = mock_dependencies();
-        let info = mock_info("creator", &coins(0, &DENOM.to_string()));
+        let mut deps = mock_dependencies_with_balance(&coins(10_000, DENOM));
+        let info = mock_info("creator", &coins(0, DENOM));
 
         // Successful instantiation
         let msg = init_msg(Addr::unchecked("fee_address"), true);
@@ -571,15 +1004,24 @@ mod tests {
     }
     #[test]
     fn test_add_bet_user_infinte() {
-        let mut deps = mock_dependencies();
-        let info = mock_info("first_bet_user", &coins(10, &DENOM.to_string()));
+        let mut deps = mock_dependencies_with_balance(&coins(10_000, DENOM));
+        let info = mock_info("first_bet_user", &coins(10, DENOM));
         let msg = init_msg(Addr::unchecked("fee_address"), true);
         instantiate(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
-        let res = add_bet_user_infinte(deps.as_mut(), mock_env(), info).unwrap();
+        let res = add_bet_user(deps.as_mut(), mock_env(), info, PoolId::Infinite, None).unwrap();
 
         //check message len
-        assert_eq!(3, res.attributes.len());
-        assert_eq!(1, res.messages.len());
+        assert_eq!(1, res.attributes.len());
+        assert_eq!(0, res.messages.len());
+        assert_eq!(
+            res.events,
+            vec![Event::new("bet_placed")
+                .add_attribute("pool_id", "infinite")
+                .add_attribute("round_id", "1")
+                .add_attribute("bettor", "first_bet_user")
+                .add_attribute("amount", "10")
+                .add_attribute("denom", DENOM)]
+        );
 
         let stored_state = query_config(deps.as_ref()).unwrap();
 
@@ -593,15 +1035,16 @@ mod tests {
 
     #[test]
     fn test_add_bet_user_whale() {
-        let mut deps = mock_dependencies();
-        let info = mock_info("first_bet_user", &coins(10, &DENOM.to_string()));
+        let mut deps = mock_dependencies_with_balance(&coins(10_000, DENOM));
+        let info = mock_info("first_bet_user", &coins(10, DENOM));
         let msg = init_msg(Addr::unchecked("fee_address"), true);
         instantiate(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
-        let res = add_bet_user_whale(deps.as_mut(), mock_env(), info).unwrap();
+        let res = add_bet_user(deps.as_mut(), mock_env(), info, PoolId::Whale, None).unwrap();
 
         //check message len
-        assert_eq!(3, res.attributes.len());
-        assert_eq!(1, res.messages.len());
+        assert_eq!(1, res.attributes.len());
+        assert_eq!(0, res.messages.len());
+        assert_eq!("bet_placed", res.events[0].ty);
 
         let stored_state = query_config(deps.as_ref()).unwrap();
 
@@ -615,15 +1058,16 @@ mod tests {
 
     #[test]
     fn test_add_bet_user_shrimp() {
-        let mut deps = mock_dependencies();
-        let info = mock_info("first_bet_user", &coins(10, &DENOM.to_string()));
+        let mut deps = mock_dependencies_with_balance(&coins(10_000, DENOM));
+        let info = mock_info("first_bet_user", &coins(10, DENOM));
         let msg = init_msg(Addr::unchecked("fee_address"), true);
         instantiate(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
-        let res = add_bet_user_shrimp(deps.as_mut(), mock_env(), info).unwrap();
+        let res = add_bet_user(deps.as_mut(), mock_env(), info, PoolId::Shrimp, None).unwrap();
 
         //check message len
-        assert_eq!(3, res.attributes.len());
-        assert_eq!(1, res.messages.len());
+        assert_eq!(1, res.attributes.len());
+        assert_eq!(0, res.messages.len());
+        assert_eq!("bet_placed", res.events[0].ty);
 
         let stored_state = query_config(deps.as_ref()).unwrap();
 
@@ -637,25 +1081,486 @@ mod tests {
 
     #[test]
     fn test_end_game() {
-        let mut deps = mock_dependencies();
-        let info = mock_info("fee_address", &coins(10, &DENOM.to_string()));
+        let mut deps = mock_dependencies_with_balance(&coins(10_000, DENOM));
+        let info = mock_info("fee_address", &coins(10, DENOM));
         let msg = init_msg(Addr::unchecked("fee_address"), true);
         instantiate(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
 
-        let first_bet_user = mock_info("first_bet_user", &coins(100, &DENOM.to_string()));
-        let _res: Response =
-            add_bet_user_infinte(deps.as_mut(), mock_env(), first_bet_user.clone()).unwrap();
-
-        let second_bet_user = mock_info("second_bet_user", &coins(50, &DENOM.to_string()));
-        let _res =
-            add_bet_user_infinte(deps.as_mut(), mock_env(), second_bet_user.clone()).unwrap();
-
-        let last_bet_user = mock_info("last_bet_user", &coins(50, &DENOM.to_string()));
-        let _res = add_bet_user_infinte(deps.as_mut(), mock_env(), last_bet_user.clone()).unwrap();
-        let res = end_game_infinite(deps.as_mut(), mock_env(), info, 1231231809300).unwrap();
+        let first_bet_user = mock_info("first_bet_user", &coins(100, DENOM));
+        let _res: Response = add_bet_user(
+            deps.as_mut(),
+            mock_env(),
+            first_bet_user.clone(),
+            PoolId::Infinite,
+            None,
+        )
+        .unwrap();
+
+        let second_bet_user = mock_info("second_bet_user", &coins(50, DENOM));
+        let _res = add_bet_user(
+            deps.as_mut(),
+            mock_env(),
+            second_bet_user.clone(),
+            PoolId::Infinite,
+            None,
+        )
+        .unwrap();
+
+        let last_bet_user = mock_info("last_bet_user", &coins(50, DENOM));
+        let _res = add_bet_user(
+            deps.as_mut(),
+            mock_env(),
+            last_bet_user.clone(),
+            PoolId::Infinite,
+            None,
+        )
+        .unwrap();
+        toggle_game(deps.as_mut(), mock_env(), info.clone(), PoolId::Infinite).unwrap();
+        let res = end_game(
+            deps.as_mut(),
+            mock_env(),
+            info,
+            PoolId::Infinite,
+            1231231809300,
+        )
+        .unwrap();
 
         //check message len
-        assert_eq!(3, res.attributes.len());
+        assert_eq!(1, res.attributes.len());
         assert_eq!(2, res.messages.len());
+        assert_eq!(
+            res.events,
+            vec![Event::new("round_settled")
+                .add_attribute("pool_id", "infinite")
+                .add_attribute("round_id", "1")
+                .add_attribute("winner", "last_bet_user")
+                .add_attribute("pot", "200")
+                .add_attribute("prize", "192")
+                .add_attribute("fee", "8")
+                .add_attribute("denom", DENOM)]
+        );
+        crate::solvency::assert_tracked(deps.as_ref());
+    }
+
+    #[test]
+    fn test_end_game_settles_own_pool() {
+        let mut deps = mock_dependencies_with_balance(&coins(10_000, DENOM));
+        let info = mock_info("fee_address", &[]);
+        let msg = init_msg(Addr::unchecked("fee_address"), true);
+        instantiate(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
+
+        let infinite_bet_user = mock_info("infinite_bet_user", &coins(1000, DENOM));
+        add_bet_user(
+            deps.as_mut(),
+            mock_env(),
+            infinite_bet_user,
+            PoolId::Infinite,
+            None,
+        )
+        .unwrap();
+        let whale_bet_user = mock_info("whale_bet_user", &coins(100, DENOM));
+        add_bet_user(
+            deps.as_mut(),
+            mock_env(),
+            whale_bet_user,
+            PoolId::Whale,
+            None,
+        )
+        .unwrap();
+
+        toggle_game(deps.as_mut(), mock_env(), info.clone(), PoolId::Whale).unwrap();
+        let res = end_game(
+            deps.as_mut(),
+            mock_env(),
+            info,
+            PoolId::Whale,
+            1231231809300,
+        )
+        .unwrap();
+        assert_eq!(
+            res.messages[1].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: String::from("whale_bet_user"),
+                amount: coins(96, DENOM),
+            })
+        );
+
+        let stored_state = query_config(deps.as_ref()).unwrap();
+        assert_eq!(0, stored_state.pot_total_whale);
+        assert!(stored_state.start_flag_whale);
+        assert_eq!(1000, stored_state.pot_total_infinte);
+    }
+
+    fn ticket_config(supply: u64, max_per_user: u64) -> TicketConfig {
+        TicketConfig {
+            price: 10,
+            denom: DENOM.to_string(),
+            max_per_user,
+            supply,
+        }
+    }
+
+    #[test]
+    fn test_ticket_sales() {
+        let mut deps = mock_dependencies_with_balance(&coins(10_000, DENOM));
+        let info = mock_info("fee_address", &[]);
+        let msg = init_msg(Addr::unchecked("fee_address"), true);
+        instantiate(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
+        set_ticket_config(
+            deps.as_mut(),
+            mock_env(),
+            info,
+            PoolId::Infinite,
+            Some(ticket_config(5, 3)),
+        )
+        .unwrap();
+
+        // Stakes must buy whole tickets.
+        let odd_amount = mock_info("first_bet_user", &coins(15, DENOM));
+        add_bet_user(
+            deps.as_mut(),
+            mock_env(),
+            odd_amount,
+            PoolId::Infinite,
+            None,
+        )
+        .unwrap_err();
+
+        let first_bet_user = mock_info("first_bet_user", &coins(20, DENOM));
+        add_bet_user(
+            deps.as_mut(),
+            mock_env(),
+            first_bet_user.clone(),
+            PoolId::Infinite,
+            None,
+        )
+        .unwrap();
+        // A third ticket is fine, a fourth exceeds the per-user limit.
+        let too_many = mock_info("first_bet_user", &coins(20, DENOM));
+        add_bet_user(deps.as_mut(), mock_env(), too_many, PoolId::Infinite, None).unwrap_err();
+
+        let second_bet_user = mock_info("second_bet_user", &coins(30, DENOM));
+        let res = add_bet_user(
+            deps.as_mut(),
+            mock_env(),
+            second_bet_user,
+            PoolId::Infinite,
+            None,
+        )
+        .unwrap();
+        assert!(res
+            .events
+            .contains(&events::pool_toggled(PoolId::Infinite, 1, false)));
+
+        let stored_state = query_config(deps.as_ref()).unwrap();
+        assert!(!stored_state.start_flag_infinte);
+        assert_eq!(
+            vec![TicketRange { first: 3, last: 5 }],
+            query_tickets(
+                deps.as_ref(),
+                PoolId::Infinite,
+                Addr::unchecked("second_bet_user")
+            )
+            .unwrap()
+        );
+    }
+
+    #[test]
+    fn test_bet_errors() {
+        let mut deps = mock_dependencies_with_balance(&coins(10_000, DENOM));
+        let admin = mock_info("fee_address", &[]);
+        let msg = init_msg(Addr::unchecked("fee_address"), true);
+        instantiate(deps.as_mut(), mock_env(), admin.clone(), msg).unwrap();
+
+        let bet = |funds: &[Coin]| mock_info("bettor", funds);
+        let err =
+            add_bet_user(deps.as_mut(), mock_env(), bet(&[]), PoolId::Whale, None).unwrap_err();
+        assert!(matches!(err, ContractError::NoFunds));
+        let err = add_bet_user(
+            deps.as_mut(),
+            mock_env(),
+            bet(&[coin(10, DENOM), coin(10, "uatom")]),
+            PoolId::Whale,
+            None,
+        )
+        .unwrap_err();
+        assert!(matches!(err, ContractError::MultipleDenoms));
+        let err = add_bet_user(
+            deps.as_mut(),
+            mock_env(),
+            bet(&coins(501, DENOM)),
+            PoolId::Whale,
+            None,
+        )
+        .unwrap_err();
+        assert!(matches!(
+            err,
+            ContractError::BetLimitExceeded {
+                max: 500,
+                amount: 501
+            }
+        ));
+
+        add_bet_user(
+            deps.as_mut(),
+            mock_env(),
+            bet(&coins(10, DENOM)),
+            PoolId::Whale,
+            None,
+        )
+        .unwrap();
+        let err = add_bet_user(
+            deps.as_mut(),
+            mock_env(),
+            bet(&coins(10, "uatom")),
+            PoolId::Whale,
+            None,
+        )
+        .unwrap_err();
+        assert!(matches!(err, ContractError::InvalidDenom { .. }));
+
+        let err = end_game(deps.as_mut(), mock_env(), admin.clone(), PoolId::Whale, 1).unwrap_err();
+        assert!(matches!(
+            err,
+            ContractError::PoolStillOpen {
+                pool: PoolId::Whale
+            }
+        ));
+        toggle_game(deps.as_mut(), mock_env(), admin.clone(), PoolId::Whale).unwrap();
+        let err = add_bet_user(
+            deps.as_mut(),
+            mock_env(),
+            bet(&coins(10, DENOM)),
+            PoolId::Whale,
+            None,
+        )
+        .unwrap_err();
+        assert!(matches!(
+            err,
+            ContractError::PoolClosed {
+                pool: PoolId::Whale
+            }
+        ));
+
+        toggle_game(deps.as_mut(), mock_env(), admin.clone(), PoolId::Shrimp).unwrap();
+        let err = end_game(deps.as_mut(), mock_env(), admin, PoolId::Shrimp, 1).unwrap_err();
+        assert!(matches!(err, ContractError::EmptyPot { .. }));
+    }
+
+    #[test]
+    fn test_withdraw_bet() {
+        let mut deps = mock_dependencies_with_balance(&coins(10_000, DENOM));
+        let admin = mock_info("fee_address", &[]);
+        let msg = init_msg(Addr::unchecked("fee_address"), true);
+        instantiate(deps.as_mut(), mock_env(), admin.clone(), msg).unwrap();
+        set_withdrawal_penalty(deps.as_mut(), mock_env(), admin.clone(), PoolId::Whale, 500)
+            .unwrap();
+
+        for &(bettor, amount) in &[("alice", 100), ("bob", 200), ("alice", 100), ("carol", 50)] {
+            let info = mock_info(bettor, &coins(amount, DENOM));
+            add_bet_user(deps.as_mut(), mock_env(), info, PoolId::Whale, None).unwrap();
+        }
+
+        let res = withdraw_bet(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("alice", &[]),
+            PoolId::Whale,
+        )
+        .unwrap();
+        assert_eq!(
+            res.messages
+                .iter()
+                .map(|msg| msg.msg.clone())
+                .collect::<Vec<_>>(),
+            vec![
+                CosmosMsg::Bank(BankMsg::Send {
+                    to_address: String::from("alice"),
+                    amount: coins(190, DENOM),
+                }),
+                CosmosMsg::Bank(BankMsg::Send {
+                    to_address: String::from("fee_address"),
+                    amount: coins(10, DENOM),
+                }),
+            ]
+        );
+
+        let stored_state = query_config(deps.as_ref()).unwrap();
+        assert_eq!(250, stored_state.pot_total_whale);
+        let orders: Vec<_> = stored_state
+            .user_list_whale
+            .iter()
+            .map(|bet| (bet.user_address.to_string(), bet.user_order))
+            .collect();
+        assert_eq!(
+            vec![(String::from("bob"), 1), (String::from("carol"), 2)],
+            orders
+        );
+
+        let err = withdraw_bet(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("alice", &[]),
+            PoolId::Whale,
+        )
+        .unwrap_err();
+        assert!(matches!(err, ContractError::NoBetToWithdraw { .. }));
+
+        toggle_game(deps.as_mut(), mock_env(), admin, PoolId::Whale).unwrap();
+        let err = withdraw_bet(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("bob", &[]),
+            PoolId::Whale,
+        )
+        .unwrap_err();
+        assert!(matches!(err, ContractError::PoolClosed { .. }));
+        crate::solvency::assert_tracked(deps.as_ref());
+    }
+
+    #[test]
+    fn test_withdraw_renumbers_tickets() {
+        let mut deps = mock_dependencies_with_balance(&coins(10_000, DENOM));
+        let admin = mock_info("fee_address", &[]);
+        let msg = init_msg(Addr::unchecked("fee_address"), true);
+        instantiate(deps.as_mut(), mock_env(), admin.clone(), msg).unwrap();
+        let ticket_config = TicketConfig {
+            price: 10,
+            denom: DENOM.to_string(),
+            max_per_user: 5,
+            supply: 10,
+        };
+        set_ticket_config(
+            deps.as_mut(),
+            mock_env(),
+            admin,
+            PoolId::Infinite,
+            Some(ticket_config),
+        )
+        .unwrap();
+        for &(bettor, amount) in &[("alice", 20), ("bob", 30)] {
+            let info = mock_info(bettor, &coins(amount, DENOM));
+            add_bet_user(deps.as_mut(), mock_env(), info, PoolId::Infinite, None).unwrap();
+        }
+
+        withdraw_bet(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("alice", &[]),
+            PoolId::Infinite,
+        )
+        .unwrap();
+        assert_eq!(
+            vec![TicketRange { first: 1, last: 3 }],
+            query_tickets(deps.as_ref(), PoolId::Infinite, Addr::unchecked("bob")).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_end_due_rounds() {
+        let mut deps = mock_dependencies_with_balance(&coins(10_000, DENOM));
+        let env = mock_env();
+        let now = env.block.time.seconds();
+        let admin = mock_info("fee_address", &[]);
+        let msg = init_msg(Addr::unchecked("fee_address"), true);
+        instantiate(deps.as_mut(), env.clone(), admin.clone(), msg).unwrap();
+        for &pool in &PoolId::ALL {
+            let info = mock_info("bettor", &coins(10, DENOM));
+            add_bet_user(deps.as_mut(), env.clone(), info, pool, None).unwrap();
+        }
+        // Infinite and shrimp are due, whale still takes bets.
+        for &(pool, closes_at) in &[
+            (PoolId::Infinite, now),
+            (PoolId::Whale, now + 100),
+            (PoolId::Shrimp, now - 1),
+        ] {
+            set_close_time(
+                deps.as_mut(),
+                env.clone(),
+                admin.clone(),
+                pool,
+                Some(closes_at),
+            )
+            .unwrap();
+        }
+
+        let info = mock_info("bettor", &coins(10, DENOM));
+        let err =
+            add_bet_user(deps.as_mut(), env.clone(), info, PoolId::Infinite, None).unwrap_err();
+        assert!(matches!(err, ContractError::PoolClosed { .. }));
+
+        let res = end_due_rounds(deps.as_mut(), env.clone(), admin.clone(), 7, Some(1)).unwrap();
+        assert_eq!(
+            res.messages,
+            vec![SubMsg::reply_on_error(
+                WasmMsg::Execute {
+                    contract_addr: env.contract.address.to_string(),
+                    msg: to_json_binary(&ExecuteMsg::EndGameInfinte { seed: 7 }).unwrap(),
+                    funds: vec![],
+                },
+                0
+            )]
+        );
+        let res = end_due_rounds(deps.as_mut(), env.clone(), admin, 7, None).unwrap();
+        assert_eq!(
+            vec![0, 2],
+            res.messages.iter().map(|m| m.id).collect::<Vec<_>>()
+        );
+
+        // The submessage runs as the contract itself.
+        let contract = mock_info(env.contract.address.as_str(), &[]);
+        end_game(deps.as_mut(), env.clone(), contract, PoolId::Infinite, 7).unwrap();
+        assert_eq!(
+            None,
+            CLOSE_TIMES.may_load(&deps.storage, "infinite").unwrap()
+        );
+
+        let failed = Reply {
+            id: 2,
+            result: SubMsgResult::Err(String::from("No winner selected")),
+        };
+        let res = reply(deps.as_mut(), env, failed).unwrap();
+        assert_eq!(
+            res.events,
+            vec![events::settlement_failed(
+                PoolId::Shrimp,
+                1,
+                "No winner selected"
+            )]
+        );
+    }
+
+    #[test]
+    fn test_select_winning_ticket() {
+        let bet = |address: &str, first: u64, last: u64| UserBetInfo {
+            user_address: Addr::unchecked(address),
+            user_amount: (last - first + 1) as u128 * 10,
+            user_order: first as u128,
+            coin_type: DENOM.to_string(),
+            tickets: Some(TicketRange { first, last }),
+            nft_token_id: None,
+            nft_collection: None,
+            client_seed: None,
+            normalized_value: None,
+            free_bet: false,
+        };
+        let bets = vec![bet("first", 1, 2), bet("second", 3, 4)];
+
+        assert_eq!(
+            Some(Addr::unchecked("first")),
+            select_winning_ticket(&bets, 0).map(|bet| bet.user_address.clone())
+        );
+        assert_eq!(
+            Some(Addr::unchecked("first")),
+            select_winning_ticket(&bets, 4999).map(|bet| bet.user_address.clone())
+        );
+        assert_eq!(
+            Some(Addr::unchecked("second")),
+            select_winning_ticket(&bets, 5000).map(|bet| bet.user_address.clone())
+        );
+        assert_eq!(
+            Some(Addr::unchecked("second")),
+            select_winning_ticket(&bets, 9999).map(|bet| bet.user_address.clone())
+        );
     }
 }
@@ -0,0 +1,388 @@
+//! Multi-denom pools.
+//! By default a round is held in the denom of its first bet. A pool with
+//! accepted denoms instead takes bets in any of them and keeps a pot per
+//! denom. The draw weighs each stake at its denom's admin-set rate, or at
+//! its oracle price when one is configured (see `oracle`), and the winner
+//! gets every pot, less the house fee taken from each.
+
+use cosmwasm_std::{Coin, DepsMut, Env, MessageInfo, Response, StdResult, Uint128};
+
+use crate::bonus;
+use crate::msg::{DenomRate, PoolId, UserBetInfo};
+use crate::query::query_config;
+use crate::state::POOL_DENOMS;
+use crate::ContractError;
+
+pub fn set_pool_denoms(
+    deps: DepsMut,
+    _env: Env,
+    info: MessageInfo,
+    pool: PoolId,
+    denoms: Option<Vec<DenomRate>>,
+) -> Result<Response, ContractError> {
+    let config = query_config(deps.as_ref())?;
+    if info.sender != config.fee_address {
+        return Err(ContractError::Unauthorized);
+    }
+    if bonus::round_started(deps.storage, &config, pool)? {
+        return Err(ContractError::RoundInProgress { pool });
+    }
+
+    match &denoms {
+        Some(denoms) => {
+            let duplicate = |i: usize| denoms[..i].iter().any(|d| d.denom == denoms[i].denom);
+            if denoms.is_empty()
+                || (0..denoms.len()).any(|i| denoms[i].rate.is_zero() || duplicate(i))
+            {
+                return Err(ContractError::InvalidPoolDenoms);
+            }
+            POOL_DENOMS.save(deps.storage, pool.as_str(), denoms)?;
+        }
+        None => POOL_DENOMS.remove(deps.storage, pool.as_str()),
+    }
+
+    Ok(Response::new()
+        .add_attribute("action", "set_pool_denoms")
+        .add_attribute("pool_id", pool.to_string())
+        .add_attribute(
+            "denoms",
+            denoms.map_or(0, |denoms| denoms.len()).to_string(),
+        ))
+}
+
+/// Pot of each denom staked in `bets`, in the order the denoms were first bet.
+pub fn pot_totals(bets: &[UserBetInfo]) -> Vec<Coin> {
+    let mut pots: Vec<Coin> = vec![];
+    for bet in bets {
+        match pots.iter_mut().find(|pot| pot.denom == bet.coin_type) {
+            Some(pot) => pot.amount += Uint128::new(bet.user_amount),
+            None => pots.push(Coin::new(bet.user_amount, bet.coin_type.clone())),
+        }
+    }
+    pots
+}
+
+/// `bets` with their stakes converted to draw weights: their oracle value
+/// if they were priced, their denom's rate otherwise. Weights keep the 18
+/// decimals of `Decimal`, so a small stake at a small rate still weighs.
+pub(crate) fn weighted_bets(
+    bets: &[UserBetInfo],
+    rates: &[DenomRate],
+) -> StdResult<Vec<UserBetInfo>> {
+    bets.iter()
+        .map(|bet| {
+            let weight = match bet.normalized_value {
+                Some(value) => value.atomics(),
+                None => {
+                    let rate = rates
+                        .iter()
+                        .find(|rate| rate.denom == bet.coin_type)
+                        .map(|rate| rate.rate)
+                        .unwrap_or_default();
+                    Uint128::new(bet.user_amount).checked_mul(rate.atomics())?
+                }
+            };
+            Ok(UserBetInfo {
+                user_amount: weight.u128(),
+                ..bet.clone()
+            })
+        })
+        .collect()
+}
+
+/// Sum of the draw weights of `weighted`.
+pub(crate) fn total_weight(weighted: &[UserBetInfo]) -> StdResult<u128> {
+    let total = weighted.iter().try_fold(Uint128::zero(), |total, bet| {
+        total.checked_add(Uint128::new(bet.user_amount))
+    })?;
+    Ok(total.u128())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::contract::{execute, instantiate};
+    use crate::msg::SimulateBetResponse;
+    use crate::msg::{ExecuteMsg, InstantiateMsg, QueryMsg};
+    use crate::query::{query, query_simulate_bet};
+    use crate::state::RoundRecord;
+    use cosmwasm_std::testing::{mock_dependencies_with_balance, mock_env, mock_info};
+    use cosmwasm_std::{coin, coins, from_json, Addr, BankMsg, CosmosMsg, Decimal};
+
+    #[test]
+    fn test_multi_denom_pool() {
+        let mut deps =
+            mock_dependencies_with_balance(&[coin(10_000, "usei"), coin(10_000, "uatom")]);
+        let admin = mock_info("fee_address", &[]);
+        let msg = InstantiateMsg {
+            fee_address: Addr::unchecked("fee_address"),
+            start_flag: true,
+        };
+        instantiate(deps.as_mut(), mock_env(), admin.clone(), msg).unwrap();
+
+        let rate = |denom: &str, rate: u64| DenomRate {
+            denom: denom.to_string(),
+            rate: Decimal::from_ratio(rate, 1u128),
+        };
+        let err = set_pool_denoms(
+            deps.as_mut(),
+            mock_env(),
+            admin.clone(),
+            PoolId::Infinite,
+            Some(vec![rate("usei", 1), rate("usei", 2)]),
+        )
+        .unwrap_err();
+        assert!(matches!(err, ContractError::InvalidPoolDenoms));
+        // One uatom weighs as much as ten usei.
+        set_pool_denoms(
+            deps.as_mut(),
+            mock_env(),
+            admin.clone(),
+            PoolId::Infinite,
+            Some(vec![rate("usei", 1), rate("uatom", 10)]),
+        )
+        .unwrap();
+
+        let bet = |bettor: &str, amount: u128, denom: &str| {
+            (
+                mock_info(bettor, &coins(amount, denom)),
+                ExecuteMsg::AddBetUserInfinite { client_seed: None },
+            )
+        };
+        for (info, msg) in [
+            bet("alice", 500, "usei"),
+            bet("bob", 50, "uatom"),
+            bet("alice", 100, "usei"),
+        ] {
+            execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+        }
+        let (info, msg) = bet("carol", 10, "uosmo");
+        let err = execute(deps.as_mut(), mock_env(), info, msg).unwrap_err();
+        assert!(matches!(err, ContractError::DenomNotAccepted { .. }));
+
+        let totals: Vec<Coin> = from_json(
+            query(
+                deps.as_ref(),
+                mock_env(),
+                QueryMsg::PotTotals {
+                    pool_id: PoolId::Infinite,
+                },
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(vec![coin(600, "usei"), coin(50, "uatom")], totals);
+
+        // A simulated bet of 10 uatom weighs 100 of the 1200: the odds come
+        // from the weights, the prizes from every pot.
+        let simulate = |denom: Option<&str>| {
+            query_simulate_bet(
+                deps.as_ref(),
+                &mock_env(),
+                PoolId::Infinite,
+                Addr::unchecked("carol"),
+                10,
+                denom.map(str::to_string),
+            )
+        };
+        assert_eq!(
+            SimulateBetResponse {
+                stake: 10,
+                pot_total: 60,
+                win_probability: 833,
+                gross_prize: 60,
+                fee: 2,
+                net_payout: 58,
+                other_prizes: vec![coin(576, "usei")],
+            },
+            simulate(Some("uatom")).unwrap()
+        );
+        simulate(None).unwrap_err();
+        simulate(Some("uosmo")).unwrap_err();
+
+        // Alice holds 600 of the 1100 weight. Seed 7 draws 5898 bps, so
+        // the target 648 falls in Bob's 500..1000.
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            admin.clone(),
+            ExecuteMsg::ToggleGameInfinte {},
+        )
+        .unwrap();
+        let res = execute(
+            deps.as_mut(),
+            mock_env(),
+            admin,
+            ExecuteMsg::EndGameInfinte { seed: 7 },
+        )
+        .unwrap();
+        let sends: Vec<CosmosMsg> = res.messages.iter().map(|sub| sub.msg.clone()).collect();
+        let send = |to_address: &str, amount: u128, denom: &str| -> CosmosMsg {
+            BankMsg::Send {
+                to_address: to_address.to_string(),
+                amount: coins(amount, denom),
+            }
+            .into()
+        };
+        assert_eq!(
+            vec![
+                send("fee_address", 24, "usei"),
+                send("bob", 576, "usei"),
+                send("fee_address", 2, "uatom"),
+                send("bob", 48, "uatom"),
+            ],
+            sends
+        );
+
+        let record: Option<RoundRecord> = from_json(
+            query(
+                deps.as_ref(),
+                mock_env(),
+                QueryMsg::Round {
+                    pool_id: "infinite".to_string(),
+                    round_id: 1,
+                },
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        let record = record.unwrap();
+        assert_eq!(coin(600, "usei"), record.pot);
+        assert_eq!(coin(50, "uatom"), record.other_pots[0].pot);
+        assert_eq!(48, record.other_pots[0].prize);
+    }
+
+    #[test]
+    fn test_withdraw_from_several_denoms() {
+        let mut deps =
+            mock_dependencies_with_balance(&[coin(10_000, "usei"), coin(10_000, "uatom")]);
+        let admin = mock_info("fee_address", &[]);
+        let msg = InstantiateMsg {
+            fee_address: Addr::unchecked("fee_address"),
+            start_flag: true,
+        };
+        instantiate(deps.as_mut(), mock_env(), admin.clone(), msg).unwrap();
+        let rate = |denom: &str| DenomRate {
+            denom: denom.to_string(),
+            rate: Decimal::one(),
+        };
+        for msg in [
+            ExecuteMsg::SetPoolDenoms {
+                pool_id: PoolId::Infinite,
+                denoms: Some(vec![rate("usei"), rate("uatom")]),
+            },
+            ExecuteMsg::SetWithdrawalPenalty {
+                pool_id: PoolId::Infinite,
+                penalty_bps: 1000,
+            },
+        ] {
+            execute(deps.as_mut(), mock_env(), admin.clone(), msg).unwrap();
+        }
+        crate::loyalty::add_credit(
+            &mut deps.storage,
+            &Addr::unchecked("alice"),
+            coin(20, "uatom"),
+        )
+        .unwrap();
+
+        let bet = |amount: u128, denom: &str| {
+            (
+                mock_info("alice", &coins(amount, denom)),
+                ExecuteMsg::AddBetUserInfinite { client_seed: None },
+            )
+        };
+        let free_bet = (
+            mock_info("alice", &[]),
+            ExecuteMsg::PlaceFreeBet {
+                pool_id: PoolId::Infinite,
+                amount: coin(20, "uatom"),
+                client_seed: None,
+            },
+        );
+        for (info, msg) in [bet(500, "usei"), bet(50, "uatom"), free_bet] {
+            execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+        }
+
+        // Each denom is refunded in kind, less its own penalty, and the free
+        // bet goes back to the credits.
+        let res = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("alice", &[]),
+            ExecuteMsg::WithdrawBet {
+                pool_id: PoolId::Infinite,
+            },
+        )
+        .unwrap();
+        let sends: Vec<CosmosMsg> = res.messages.iter().map(|sub| sub.msg.clone()).collect();
+        assert_eq!(
+            vec![
+                CosmosMsg::Bank(BankMsg::Send {
+                    to_address: "alice".to_string(),
+                    amount: vec![coin(45, "uatom"), coin(450, "usei")],
+                }),
+                CosmosMsg::Bank(BankMsg::Send {
+                    to_address: "fee_address".to_string(),
+                    amount: vec![coin(5, "uatom"), coin(50, "usei")],
+                }),
+            ],
+            sends
+        );
+        assert_eq!(
+            vec![coin(20, "uatom")],
+            crate::loyalty::query_loyalty(deps.as_ref(), Addr::unchecked("alice"))
+                .unwrap()
+                .credits
+        );
+        assert_eq!(
+            0,
+            query_config(deps.as_ref())
+                .unwrap()
+                .pot_total(PoolId::Infinite)
+        );
+        crate::solvency::assert_tracked(deps.as_ref());
+    }
+
+    #[test]
+    fn test_weights_keep_precision() {
+        let bet = |amount: u128, denom: &str| UserBetInfo {
+            user_address: Addr::unchecked("alice"),
+            user_amount: amount,
+            user_order: 1,
+            coin_type: denom.to_string(),
+            tickets: None,
+            nft_token_id: None,
+            nft_collection: None,
+            client_seed: None,
+            normalized_value: None,
+            free_bet: false,
+        };
+        let rates = [
+            DenomRate {
+                denom: "ubtc".to_string(),
+                rate: Decimal::permille(1),
+            },
+            DenomRate {
+                denom: "usei".to_string(),
+                rate: Decimal::percent(200),
+            },
+        ];
+
+        // A stake worth a thousandth of a unit still weighs.
+        let weighted = weighted_bets(&[bet(1, "ubtc"), bet(3, "usei")], &rates).unwrap();
+        let weights: Vec<u128> = weighted.iter().map(|bet| bet.user_amount).collect();
+        assert_eq!(vec![10u128.pow(15), 6 * 10u128.pow(18)], weights);
+        assert_eq!(
+            10u128.pow(15) + 6 * 10u128.pow(18),
+            total_weight(&weighted).unwrap()
+        );
+
+        // Weights too large to count fail instead of wrapping.
+        weighted_bets(&[bet(u128::MAX, "usei")], &rates).unwrap_err();
+        let max = UserBetInfo {
+            user_amount: u128::MAX,
+            ..bet(0, "usei")
+        };
+        total_weight(&[max.clone(), max]).unwrap_err();
+    }
+}
@@ -1,6 +1,8 @@
 use cosmwasm_std::{Coin, StdError};
 use thiserror::Error;
 
+use crate::msg::PoolId;
+
 #[derive(Error, Debug)]
 pub enum ContractError {
     #[error("{0}")]
@@ -10,29 +12,211 @@ pub enum ContractError {
     #[error("Unauthorized")]
     Unauthorized,
 
-    /// The approvals has expired.
-    #[error("Cannot set approval that is already expired")]
-    Expired,
+    /// The contract is paused and takes no new bets or deposits.
+    #[error("Contract is paused")]
+    Paused,
+
+    #[error("Loyalty program is not configured")]
+    LoyaltyNotConfigured,
+
+    /// Tiers must be in ascending order, and rates unique and positive.
+    #[error("Invalid loyalty config")]
+    InvalidLoyaltyConfig,
+
+    #[error("Points can't be redeemed for {denom}")]
+    NotRedeemable { denom: String },
+
+    #[error("Only {available} loyalty points available")]
+    InsufficientPoints { available: u128 },
+
+    /// The points redeem for less than one unit of credit.
+    #[error("Redemption too small")]
+    RedemptionTooSmall,
+
+    #[error("Only {available} {denom} of free-bet credits available")]
+    InsufficientCredits { denom: String, available: u128 },
+
+    #[error("At most {max} hooks can be registered")]
+    TooManyHooks { max: usize },
+
+    #[error("House fee of {fee_bps} basis points is above the maximum of {max_bps}")]
+    InvalidHouseFee { fee_bps: u64, max_bps: u64 },
+
+    /// No coin, or a zero amount, was attached to the message.
+    #[error("No funds sent")]
+    NoFunds,
+
+    /// More than one denom was attached where a single coin is expected.
+    #[error("Send a single coin denom")]
+    MultipleDenoms,
+
+    /// The attached coin is not in the denom the pot or market is held in.
+    #[error("Invalid denom. Expected {expected} received {received}")]
+    InvalidDenom { expected: String, received: String },
+
+    /// The pool takes several denoms, but not this one.
+    #[error("Denom {denom} is not accepted by this pool")]
+    DenomNotAccepted { denom: String },
+
+    #[error("Pool denoms must be unique with positive rates")]
+    InvalidPoolDenoms,
+
+    /// The oracle has no price for the denom, or quotes it at zero.
+    #[error("No oracle price for {denom}")]
+    MissingPrice { denom: String },
+
+    #[error("Oracle price for {denom} is stale, last updated at {updated_at}")]
+    StalePrice { denom: String, updated_at: u64 },
+
+    /// The contract's balance would not cover what it owes in the denom.
+    #[error("Insolvent in {denom}: balance {balance}, liabilities {liabilities}")]
+    Insolvent {
+        denom: String,
+        balance: u128,
+        liabilities: u128,
+    },
+
+    /// The balance of the denom doesn't exceed what the contract owes in it.
+    #[error("No surplus to sweep in {denom}")]
+    NoSurplus { denom: String },
+
+    /// The pool is not taking bets right now.
+    #[error("{pool} pool is closed for bets")]
+    PoolClosed { pool: PoolId },
+
+    /// The pool has to be closed before the round can be settled.
+    #[error("{pool} pool is still open")]
+    PoolStillOpen { pool: PoolId },
+
+    /// The pool's configuration can only change between rounds.
+    #[error("{pool} pool has bets or a bonus in the current round")]
+    RoundInProgress { pool: PoolId },
+
+    #[error("No bet to withdraw from the {pool} pool")]
+    NoBetToWithdraw { pool: PoolId },
+
+    /// Bets with a minted ticket NFT stay in the round, the ticket may
+    /// have changed hands.
+    #[error("Bet with ticket {token_id} can't be withdrawn")]
+    TicketNftMinted { token_id: String },
+
+    #[error("Penalty of {penalty_bps} basis points is above 100%")]
+    InvalidPenalty { penalty_bps: u64 },
+
+    /// There is nothing to pay out.
+    #[error("{pool} pot is empty")]
+    EmptyPot { pool: String },
+
+    /// The bet is above the tier's limit.
+    #[error("Bet of {amount} exceeds the limit of {max}")]
+    BetLimitExceeded { max: u128, amount: u128 },
+
+    /// Betting is gated and the address is not verified.
+    #[error("{address} is not allowed to bet")]
+    NotAllowed { address: String },
+
+    /// The address excluded itself from betting.
+    #[error("Self-excluded from betting until {until}")]
+    SelfExcluded { until: u64 },
+
+    /// The bet would exceed one of the address's own wager limits.
+    #[error("Bet exceeds the {period} wager limit of {limit}")]
+    WagerLimitExceeded { period: String, limit: u128 },
+
+    /// The random number fell outside every bet, the draw has to be retried.
+    #[error("No winner selected, retry with another seed")]
+    NoWinner,
+
+    #[error("Ticket price, supply and limit must be positive")]
+    InvalidTicketConfig,
+
+    /// Ticket pools only accept whole tickets in the ticket denom.
+    #[error("Tickets cost {price}{denom} each")]
+    InvalidTicketPayment { price: u128, denom: String },
+
+    #[error("At most {max_per_user} tickets per user")]
+    TicketLimitExceeded { max_per_user: u64 },
+
+    #[error("Only {remaining} tickets left")]
+    TicketsSoldOut { remaining: u64 },
+
+    #[error("No prize for ticket {token_id}")]
+    NoNftPrize { token_id: String },
+
+    #[error("Staking pool is not configured")]
+    StakingNotConfigured,
+
+    #[error("No unbonding batch is due")]
+    NoUnbondingDue,
+
+    /// The undelegation is reverted rather than left without a release time.
+    #[error("Undelegation did not report a valid completion time")]
+    UnknownCompletionTime,
+
+    #[error("Unknown validator {validator}")]
+    UnknownValidator { validator: String },
+
+    /// The validator can't be switched while deposits or withdrawals are
+    /// delegated to it or unbonding from it.
+    #[error("Can't change validator while deposits are staked or unbonding")]
+    ValidatorLocked,
+
+    #[error("Can withdraw at most {available}{denom}")]
+    InvalidWithdrawal { available: u128, denom: String },
+
+    #[error("Nothing to claim")]
+    NothingToClaim,
+
+    #[error("Challenge {challenge_id} does not exist")]
+    ChallengeNotFound { challenge_id: u64 },
+
+    #[error("Challenge {challenge_id} is already matched")]
+    ChallengeMatched { challenge_id: u64 },
+
+    #[error("Challenge {challenge_id} is not matched yet")]
+    ChallengeNotMatched { challenge_id: u64 },
+
+    #[error("Challenge {challenge_id} has expired")]
+    ChallengeExpired { challenge_id: u64 },
+
+    #[error("Challenge {challenge_id} has not expired yet")]
+    ChallengeNotExpired { challenge_id: u64 },
+
+    #[error("Can't accept your own challenge")]
+    OwnChallenge,
+
+    /// Matching a challenge requires exactly the creator's stake.
+    #[error("Invalid stake. Expected {expected}")]
+    StakeMismatch { expected: Coin },
+
+    #[error("A market needs at least two outcomes")]
+    TooFewOutcomes,
+
+    #[error("Market must close in the future")]
+    InvalidCloseTime,
+
+    #[error("Market {market_id} does not exist")]
+    MarketNotFound { market_id: u64 },
+
+    #[error("Market {market_id} has no outcome {outcome}")]
+    InvalidOutcome { market_id: u64, outcome: u32 },
+
+    #[error("Market {market_id} is closed")]
+    MarketClosed { market_id: u64 },
 
-    /// operator has already been approved previously.
-    #[error("{operator} has already been approved")]
-    OperatorApproved { operator: String },
+    #[error("Market {market_id} is still open")]
+    MarketStillOpen { market_id: u64 },
 
-    /// No approval found for given address.
-    #[error("Approval not found for: {operator}")]
-    ApprovalNotFound { operator: String },
+    #[error("Market {market_id} is already resolved")]
+    MarketResolved { market_id: u64 },
 
-    /// Supplied token id is invalid.
-    #[error("token_id: {token_id} does not exist")]
-    InvalidToken { token_id: u64 },
+    #[error("Market {market_id} is not resolved yet")]
+    MarketNotResolved { market_id: u64 },
 
-    /// Amount sent in not equal to the price of the NFT.
-    #[error("Invalid amount. Expected {val:?} received {funds:?}")]
-    InvalidAmount { val: Coin, funds: Coin },
+    #[error("Dispute window of market {market_id} has passed")]
+    DisputeWindowPassed { market_id: u64 },
 
-    /// Any other error not which has not been covered.
-    #[error("Following error occured: {val:?}")]
-    CustomError { val: String },
-    // Add any other custom errors you like here.
-    // Look at https://docs.rs/thiserror/1.0.21/thiserror/ for details.
+    /// The result can still be disputed, or the market is not resolved.
+    #[error("Result of market {market_id} is not final yet")]
+    MarketNotFinal { market_id: u64 },
 }
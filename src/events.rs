@@ -0,0 +1,67 @@
+//! Custom events for indexers, emitted as `wasm-<name>`.
+//! Every event names the `pool_id` and `round_id` it belongs to, and reports
+//! amounts as plain integers next to their `denom`. Coin flips and markets
+//! use their challenge or market id as the round id.
+
+use cosmwasm_std::{Addr, Coin, Event};
+
+use crate::msg::PoolId;
+
+pub fn bet_placed(pool: PoolId, round_id: u64, bettor: &Addr, stake: &Coin) -> Event {
+    Event::new("bet_placed")
+        .add_attribute("pool_id", pool.as_str())
+        .add_attribute("round_id", round_id.to_string())
+        .add_attribute("bettor", bettor.as_str())
+        .add_attribute("amount", stake.amount.to_string())
+        .add_attribute("denom", stake.denom.as_str())
+}
+
+pub fn pool_toggled(pool: PoolId, round_id: u64, open: bool) -> Event {
+    Event::new("pool_toggled")
+        .add_attribute("pool_id", pool.as_str())
+        .add_attribute("round_id", round_id.to_string())
+        .add_attribute("open", open.to_string())
+}
+
+/// `pot` is split into the winner's `prize` and the house `fee`.
+pub fn round_settled(
+    pool_id: &str,
+    round_id: u64,
+    winner: &Addr,
+    pot: &Coin,
+    prize: u128,
+    fee: u128,
+) -> Event {
+    Event::new("round_settled")
+        .add_attribute("pool_id", pool_id)
+        .add_attribute("round_id", round_id.to_string())
+        .add_attribute("winner", winner.as_str())
+        .add_attribute("pot", pot.amount.to_string())
+        .add_attribute("prize", prize.to_string())
+        .add_attribute("fee", fee.to_string())
+        .add_attribute("denom", pot.denom.as_str())
+}
+
+/// A pool that failed to settle in `EndDueRounds`, with the reason.
+pub fn settlement_failed(pool: PoolId, round_id: u64, error: &str) -> Event {
+    Event::new("settlement_failed")
+        .add_attribute("pool_id", pool.as_str())
+        .add_attribute("round_id", round_id.to_string())
+        .add_attribute("error", error)
+}
+
+/// A stake handed back from the open round of `pool`.
+pub fn refund(pool: PoolId, round_id: u64, recipient: &Addr, amount: &Coin) -> Event {
+    game_refund(pool.as_str(), round_id, recipient, amount)
+}
+
+/// A stake handed back from a coin flip or a market, `"coinflip"` or
+/// `"market"` as `game`.
+pub fn game_refund(game: &str, round_id: u64, recipient: &Addr, amount: &Coin) -> Event {
+    Event::new("refund")
+        .add_attribute("pool_id", game)
+        .add_attribute("round_id", round_id.to_string())
+        .add_attribute("recipient", recipient.as_str())
+        .add_attribute("amount", amount.amount.to_string())
+        .add_attribute("denom", amount.denom.as_str())
+}
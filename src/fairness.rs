@@ -0,0 +1,475 @@
+//! Statistical fairness harness for the draw.
+//! Runs simulated draws through the random number generators and each way a
+//! winner is picked: by stake with `select_winner`, by ticket with
+//! `select_winning_ticket`, and by rate or oracle value in multi-denom pools.
+//! The outcomes are checked with chi-square goodness-of-fit tests, so a
+//! biased generator or selection fails the test suite. The default suite runs
+//! `QUICK_DRAWS` per check; the million-draw runs are ignored. Run with
+//! `cargo test fairness -- --nocapture` to see the statistics.
+
+use cosmwasm_std::testing::mock_env;
+use cosmwasm_std::{Addr, Decimal};
+
+use crate::contract::{generate_random_value, select_winner, select_winning_ticket};
+use crate::denoms::{total_weight, weighted_bets};
+use crate::msg::{DenomRate, PoolId, TicketRange, UserBetInfo};
+use crate::randomness::entropy_random_value;
+
+/// Draws of the full runs, gated behind `--ignored`.
+const DRAWS: u64 = 1_000_000;
+/// Draws of the runs in the default test suite.
+const QUICK_DRAWS: u64 = 100_000;
+/// One-sided normal quantile of the significance level, p = 0.001.
+const Z_CRITICAL: f64 = 3.09;
+const BUCKETS: usize = 100;
+
+/// Seeds as an admin would pick them, one after the other.
+fn sequential_seeds(draws: u64) -> impl Iterator<Item = u64> {
+    0..draws
+}
+
+/// Seeds no one controls, from SplitMix64.
+fn scattered_seeds(draws: u64) -> impl Iterator<Item = u64> {
+    let mut state = 0x9e37_79b9_7f4a_7c15u64;
+    (0..draws).map(move |_| {
+        state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    })
+}
+
+fn chi_square(observed: &[u64], expected: &[f64]) -> f64 {
+    observed
+        .iter()
+        .zip(expected)
+        .map(|(&observed, &expected)| (observed as f64 - expected).powi(2) / expected)
+        .sum()
+}
+
+/// Chi-square critical value at `Z_CRITICAL`, by the Wilson-Hilferty
+/// approximation.
+fn critical_value(degrees: usize) -> f64 {
+    let k = degrees as f64;
+    let h = 2.0 / (9.0 * k);
+    k * (1.0 - h + Z_CRITICAL * h.sqrt()).powi(3)
+}
+
+/// Reports the statistic and whether it passes.
+fn check(name: &str, observed: &[u64], expected: &[f64]) -> bool {
+    let statistic = chi_square(observed, expected);
+    let critical = critical_value(observed.len() - 1);
+    println!(
+        "{}: chi-square {:.2} with {} degrees of freedom, critical {:.2}",
+        name,
+        statistic,
+        observed.len() - 1,
+        critical
+    );
+    statistic < critical
+}
+
+/// Whether the basis-point values of `rng` are uniform over `BUCKETS`.
+fn rng_is_uniform(name: &str, rng: fn(u64) -> u128, seeds: impl Iterator<Item = u64>) -> bool {
+    let mut observed = vec![0u64; BUCKETS];
+    for seed in seeds {
+        let value = rng(seed);
+        assert!(value < 10000, "{} out of range", value);
+        observed[value as usize * BUCKETS / 10000] += 1;
+    }
+    let draws: u64 = observed.iter().sum();
+    let expected = vec![draws as f64 / BUCKETS as f64; BUCKETS];
+    check(name, &observed, &expected)
+}
+
+fn bet(i: usize, stake: u128, denom: &str) -> UserBetInfo {
+    UserBetInfo {
+        user_address: Addr::unchecked(format!("bettor{}", i)),
+        user_amount: stake,
+        user_order: i as u128 + 1,
+        coin_type: denom.to_string(),
+        tickets: None,
+        nft_token_id: None,
+        nft_collection: None,
+        client_seed: None,
+        normalized_value: None,
+        free_bet: false,
+    }
+}
+
+fn bets(stakes: &[u128]) -> Vec<UserBetInfo> {
+    stakes
+        .iter()
+        .enumerate()
+        .map(|(i, &stake)| bet(i, stake, "usei"))
+        .collect()
+}
+
+/// Bets of a ticket pool, each buying `counts[i]` tickets in turn.
+fn ticket_bets(counts: &[u64]) -> Vec<UserBetInfo> {
+    let mut next_ticket = 1;
+    counts
+        .iter()
+        .enumerate()
+        .map(|(i, &count)| {
+            let tickets = TicketRange {
+                first: next_ticket,
+                last: next_ticket + count - 1,
+            };
+            next_ticket += count;
+            UserBetInfo {
+                tickets: Some(tickets),
+                ..bet(i, count as u128, "usei")
+            }
+        })
+        .collect()
+}
+
+/// Bets of a multi-denom pool, as (stake, denom, oracle value), with the
+/// draw weights they should get at `WEIGHT_RATES`.
+type WeightedSet = (
+    &'static [(u128, &'static str, Option<u128>)],
+    &'static [u128],
+);
+
+/// Rates of the multi-denom pools: an atom weighs four sei.
+const WEIGHT_RATES: [(&str, u64); 2] = [("uatom", 4), ("usei", 1)];
+
+fn weighted_set_bets(stakes: &[(u128, &str, Option<u128>)]) -> Vec<UserBetInfo> {
+    stakes
+        .iter()
+        .enumerate()
+        .map(|(i, &(stake, denom, normalized_value))| UserBetInfo {
+            normalized_value: normalized_value.map(|value| Decimal::from_ratio(value, 1u128)),
+            ..bet(i, stake, denom)
+        })
+        .collect()
+}
+
+fn weight_rates() -> Vec<DenomRate> {
+    WEIGHT_RATES
+        .iter()
+        .map(|&(denom, rate)| DenomRate {
+            denom: denom.to_string(),
+            rate: Decimal::from_atomics(rate, 0).unwrap(),
+        })
+        .collect()
+}
+
+/// Index of the winning bet.
+fn winner_index(winner: Option<&UserBetInfo>) -> usize {
+    winner.expect("no winner").user_order as usize - 1
+}
+
+/// Whether the bettors win in proportion to `shares`, when `draw` picks the
+/// index of the winning bet from a random number.
+fn wins_match_shares(
+    name: &str,
+    shares: &[u128],
+    draw: impl Fn(u128) -> usize,
+    rng: fn(u64) -> u128,
+    seeds: impl Iterator<Item = u64>,
+) -> bool {
+    let total: u128 = shares.iter().sum();
+    let mut observed = vec![0u64; shares.len()];
+    for seed in seeds {
+        observed[draw(rng(seed))] += 1;
+    }
+    let draws: u64 = observed.iter().sum();
+    let expected: Vec<f64> = shares
+        .iter()
+        .map(|&share| draws as f64 * share as f64 / total as f64)
+        .collect();
+    check(name, &observed, &expected)
+}
+
+/// Whether the bettors win in proportion to their stakes.
+fn wins_match_stakes(
+    name: &str,
+    stakes: &[u128],
+    rng: fn(u64) -> u128,
+    seeds: impl Iterator<Item = u64>,
+) -> bool {
+    let bets = bets(stakes);
+    let pot_total: u128 = stakes.iter().sum();
+    let draw = |random_number| winner_index(select_winner(&bets, pot_total, random_number));
+    wins_match_shares(name, stakes, draw, rng, seeds)
+}
+
+/// Whether the holders win in proportion to the tickets they bought.
+fn wins_match_tickets(
+    name: &str,
+    counts: &[u64],
+    rng: fn(u64) -> u128,
+    seeds: impl Iterator<Item = u64>,
+) -> bool {
+    let bets = ticket_bets(counts);
+    let shares: Vec<u128> = counts.iter().map(|&count| count as u128).collect();
+    let draw = |random_number| winner_index(select_winning_ticket(&bets, random_number));
+    wins_match_shares(name, &shares, draw, rng, seeds)
+}
+
+/// Whether the bettors of a multi-denom pool win in proportion to the value
+/// of their stakes, at their oracle price or their denom's rate.
+fn wins_match_weights(
+    name: &str,
+    set: WeightedSet,
+    rng: fn(u64) -> u128,
+    seeds: impl Iterator<Item = u64>,
+) -> bool {
+    let (stakes, weights) = set;
+    let weighted = weighted_bets(&weighted_set_bets(stakes), &weight_rates()).unwrap();
+    let total_weight = total_weight(&weighted).unwrap();
+    let draw = |random_number| winner_index(select_winner(&weighted, total_weight, random_number));
+    wins_match_shares(name, weights, draw, rng, seeds)
+}
+
+/// Stakes whose shares are whole basis points, so the draw can be exact.
+const STAKE_SETS: [&[u128]; 5] = [
+    &[1, 1],
+    &[1, 9],
+    &[37, 63],
+    &[1, 2, 3, 4],
+    &[500, 250, 125, 125, 8000, 1000],
+];
+
+/// Ticket counts whose shares are whole basis points.
+const TICKET_SETS: [&[u64]; 5] = [&[1, 1], &[1, 9], &[37, 63], &[1, 2, 3, 4], &[5, 20, 25, 50]];
+
+/// Multi-denom rounds, with rate-weighted and oracle-priced stakes.
+const WEIGHTED_SETS: [WeightedSet; 3] = [
+    (&[(1, "uatom", None), (4, "usei", None)], &[4, 4]),
+    (
+        &[
+            (100, "uatom", None),
+            (300, "usei", None),
+            (50, "uatom", None),
+        ],
+        &[400, 300, 200],
+    ),
+    (
+        &[
+            (10, "uatom", Some(250)),
+            (500, "usei", None),
+            (25, "uatom", None),
+            (75, "usei", Some(150)),
+        ],
+        &[250, 500, 100, 150],
+    ),
+];
+
+fn rng_checks(draws: u64) {
+    assert!(rng_is_uniform(
+        "sequential seeds",
+        generate_random_value,
+        sequential_seeds(draws)
+    ));
+    assert!(rng_is_uniform(
+        "scattered seeds",
+        generate_random_value,
+        scattered_seeds(draws)
+    ));
+}
+
+fn stake_checks(draws: u64) {
+    for stakes in &STAKE_SETS {
+        let name = format!("stakes {:?}", stakes);
+        assert!(wins_match_stakes(
+            &name,
+            stakes,
+            generate_random_value,
+            sequential_seeds(draws)
+        ));
+        assert!(wins_match_stakes(
+            &name,
+            stakes,
+            generate_random_value,
+            scattered_seeds(draws)
+        ));
+    }
+}
+
+fn ticket_checks(draws: u64) {
+    for counts in &TICKET_SETS {
+        let name = format!("tickets {:?}", counts);
+        assert!(wins_match_tickets(
+            &name,
+            counts,
+            generate_random_value,
+            sequential_seeds(draws)
+        ));
+        assert!(wins_match_tickets(
+            &name,
+            counts,
+            generate_random_value,
+            scattered_seeds(draws)
+        ));
+    }
+}
+
+fn weighted_checks(draws: u64) {
+    for &set in &WEIGHTED_SETS {
+        let name = format!("weights {:?}", set.1);
+        assert!(wins_match_weights(
+            &name,
+            set,
+            generate_random_value,
+            sequential_seeds(draws)
+        ));
+        assert!(wins_match_weights(
+            &name,
+            set,
+            generate_random_value,
+            scattered_seeds(draws)
+        ));
+    }
+}
+
+/// The entropy mode over successive rounds of a pool without client seeds.
+fn entropy_checks(draws: u64) {
+    fn entropy(round_id: u64) -> u128 {
+        entropy_random_value(PoolId::Infinite, round_id, &[], &mock_env())
+    }
+    assert!(rng_is_uniform("entropy", entropy, 0..draws));
+    assert!(wins_match_stakes(
+        "entropy stakes [1, 2, 3, 4]",
+        &[1, 2, 3, 4],
+        entropy,
+        0..draws
+    ));
+    assert!(wins_match_tickets(
+        "entropy tickets [1, 2, 3, 4]",
+        &[1, 2, 3, 4],
+        entropy,
+        0..draws
+    ));
+}
+
+#[test]
+fn rng_is_uniform_over_basis_points() {
+    rng_checks(QUICK_DRAWS);
+}
+
+#[test]
+fn win_rates_match_stake_shares() {
+    stake_checks(QUICK_DRAWS);
+}
+
+#[test]
+fn win_rates_match_ticket_shares() {
+    ticket_checks(QUICK_DRAWS);
+}
+
+#[test]
+fn win_rates_match_weighted_values() {
+    weighted_checks(QUICK_DRAWS);
+}
+
+/// Hashing is slow in debug builds, so the entropy mode runs a tenth of the
+/// draws.
+#[test]
+fn entropy_is_uniform_over_basis_points() {
+    entropy_checks(QUICK_DRAWS / 10);
+}
+
+/// Every check at a million draws. Run with
+/// `cargo test --release fairness -- --ignored --nocapture`.
+#[test]
+#[ignore]
+fn fairness_over_a_million_draws() {
+    rng_checks(DRAWS);
+    stake_checks(DRAWS);
+    ticket_checks(DRAWS);
+    weighted_checks(DRAWS);
+    entropy_checks(DRAWS / 10);
+}
+
+/// Over every possible random number, each bettor wins within one basis
+/// point of their stake share, whatever the stakes.
+#[test]
+fn selection_is_exact_to_a_basis_point() {
+    let stake_sets: [&[u128]; 4] = [
+        &[1, 1, 1],
+        &[1, 1_000_000],
+        &[7, 11, 13, 17, 19],
+        &[u128::MAX / 4, 1, u128::MAX / 3],
+    ];
+    for stakes in &stake_sets {
+        let bets = bets(stakes);
+        let pot_total: u128 = stakes.iter().sum();
+        let mut wins = vec![0u128; stakes.len()];
+        for random_number in 0..10000 {
+            let winner = select_winner(&bets, pot_total, random_number).expect("no winner");
+            wins[winner.user_order as usize - 1] += 1;
+        }
+        for (&stake, &won) in stakes.iter().zip(&wins) {
+            let share = stake as f64 * 10000.0 / pot_total as f64;
+            assert!(
+                (won as f64 - share).abs() <= 1.0,
+                "stakes {:?} won {:?}",
+                stakes,
+                wins
+            );
+        }
+    }
+}
+
+/// Likewise for tickets: each holder wins within one basis point of their
+/// share of the tickets sold, whether or not it divides the basis points.
+#[test]
+fn ticket_selection_is_exact_to_a_basis_point() {
+    let ticket_sets: [&[u64]; 4] = [
+        &[1, 1, 1],
+        &[1, 20_000],
+        &[7, 11, 13, 17, 19],
+        &[3, 9_997, 6],
+    ];
+    for counts in &ticket_sets {
+        let bets = ticket_bets(counts);
+        let sold: u64 = counts.iter().sum();
+        let mut wins = vec![0u128; counts.len()];
+        for random_number in 0..10000 {
+            wins[winner_index(select_winning_ticket(&bets, random_number))] += 1;
+        }
+        for (&count, &won) in counts.iter().zip(&wins) {
+            let share = count as f64 * 10000.0 / sold as f64;
+            assert!(
+                (won as f64 - share).abs() <= 1.0,
+                "tickets {:?} won {:?}",
+                counts,
+                wins
+            );
+        }
+    }
+}
+
+/// The harness has to catch a generator that favours low values.
+#[test]
+fn harness_detects_bias() {
+    fn biased(seed: u64) -> u128 {
+        let value = generate_random_value(seed);
+        if value >= 9900 {
+            value - 9900
+        } else {
+            value
+        }
+    }
+    assert!(!rng_is_uniform(
+        "biased",
+        biased,
+        scattered_seeds(QUICK_DRAWS)
+    ));
+    assert!(!wins_match_stakes(
+        "biased",
+        &[1, 1],
+        biased,
+        scattered_seeds(QUICK_DRAWS)
+    ));
+    assert!(!wins_match_tickets(
+        "biased",
+        &[1, 1],
+        biased,
+        scattered_seeds(QUICK_DRAWS)
+    ));
+}
@@ -0,0 +1,344 @@
+//! Contract-wide controls: pausing, the house fee, the owner and cancelling
+//! rounds. The owner (the fee address) reaches them through `execute`, and
+//! on permissioned chains the governance module through `sudo`. Both paths
+//! run the same functions, only `execute` checks the sender first.
+//!
+//! The house fee is capped, and every round, challenge and market keeps the
+//! fee it opened with, so a fee change never applies to funds already at
+//! stake.
+
+use cosmwasm_std::{
+    coin, entry_point, Addr, Deps, DepsMut, Env, MessageInfo, Response, StdResult, Storage,
+};
+
+use crate::bonus;
+use crate::contract::current_round;
+use crate::denoms;
+use crate::events;
+use crate::loyalty;
+use crate::msg::{ExecuteMsg, HouseSettingsResponse, PoolId, SudoMsg};
+use crate::payout;
+use crate::query::query_config;
+use crate::solvency;
+use crate::state::{
+    CLOSE_TIMES, CONFIG, HOUSE_FEE_BPS, PAUSED, POT_BONUSES, ROUND_FEES, ROUND_IDS,
+};
+use crate::ContractError;
+
+/// House fee of a contract that never set one, in basis points.
+pub const DEFAULT_HOUSE_FEE_BPS: u64 = 400;
+/// Highest house fee that can be set, in basis points.
+pub const MAX_HOUSE_FEE_BPS: u64 = 1000;
+
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn sudo(deps: DepsMut, env: Env, msg: SudoMsg) -> Result<Response, ContractError> {
+    match msg {
+        SudoMsg::Pause {} => set_paused(deps, true),
+        SudoMsg::Unpause {} => set_paused(deps, false),
+        SudoMsg::SetHouseFee { fee_bps } => set_house_fee(deps, fee_bps),
+        SudoMsg::TransferOwnership { new_owner } => transfer_ownership(deps, new_owner),
+        SudoMsg::CancelRound { pool_id } => cancel_round(deps, env, pool_id),
+    }
+}
+
+pub(crate) fn assert_owner(deps: Deps, info: &MessageInfo) -> Result<(), ContractError> {
+    if info.sender != query_config(deps)?.fee_address {
+        return Err(ContractError::Unauthorized);
+    }
+    Ok(())
+}
+
+pub(crate) fn is_paused(storage: &dyn Storage) -> StdResult<bool> {
+    Ok(PAUSED.may_load(storage)?.unwrap_or_default())
+}
+
+/// Whether `msg` puts new funds at stake. Only these are refused while the
+/// contract is paused, so bettors can still withdraw and claim.
+pub(crate) fn stakes_funds(msg: &ExecuteMsg) -> bool {
+    matches!(
+        msg,
+        ExecuteMsg::AddBetUserInfinite { .. }
+            | ExecuteMsg::AddBetUserWhale { .. }
+            | ExecuteMsg::AddBetUserShrimp { .. }
+            | ExecuteMsg::StakingDeposit {}
+            | ExecuteMsg::OpenChallenge { .. }
+            | ExecuteMsg::AcceptChallenge { .. }
+            | ExecuteMsg::PlaceMarketBet { .. }
+            | ExecuteMsg::PlaceFreeBet { .. }
+    )
+}
+
+pub(crate) fn house_fee_bps(storage: &dyn Storage) -> StdResult<u64> {
+    Ok(HOUSE_FEE_BPS
+        .may_load(storage)?
+        .unwrap_or(DEFAULT_HOUSE_FEE_BPS))
+}
+
+/// House fee of the open round of `round_key`, a pool id or `"staking"`.
+pub(crate) fn round_fee_bps(storage: &dyn Storage, round_key: &str) -> StdResult<u64> {
+    match ROUND_FEES.may_load(storage, round_key)? {
+        Some(fee_bps) => Ok(fee_bps),
+        None => house_fee_bps(storage),
+    }
+}
+
+/// Fixes the house fee of the open round of `round_key` at the current fee,
+/// unless the round already has one.
+pub(crate) fn fix_round_fee(storage: &mut dyn Storage, round_key: &str) -> StdResult<()> {
+    if !ROUND_FEES.has(storage, round_key) {
+        let fee_bps = house_fee_bps(storage)?;
+        ROUND_FEES.save(storage, round_key, &fee_bps)?;
+    }
+    Ok(())
+}
+
+pub fn set_paused(deps: DepsMut, paused: bool) -> Result<Response, ContractError> {
+    PAUSED.save(deps.storage, &paused)?;
+    Ok(Response::new().add_attribute("action", if paused { "pause" } else { "unpause" }))
+}
+
+pub fn set_house_fee(deps: DepsMut, fee_bps: u64) -> Result<Response, ContractError> {
+    if fee_bps > MAX_HOUSE_FEE_BPS {
+        return Err(ContractError::InvalidHouseFee {
+            fee_bps,
+            max_bps: MAX_HOUSE_FEE_BPS,
+        });
+    }
+    HOUSE_FEE_BPS.save(deps.storage, &fee_bps)?;
+    Ok(Response::new()
+        .add_attribute("action", "set_house_fee")
+        .add_attribute("fee_bps", fee_bps.to_string()))
+}
+
+/// Hands the admin rights, and the fees, to `new_owner`.
+pub fn transfer_ownership(deps: DepsMut, new_owner: Addr) -> Result<Response, ContractError> {
+    let new_owner = deps.api.addr_validate(new_owner.as_str())?;
+    let mut config = query_config(deps.as_ref())?;
+    config.fee_address = new_owner.clone();
+    CONFIG.save(deps.storage, &config)?;
+    Ok(Response::new()
+        .add_attribute("action", "transfer_ownership")
+        .add_attribute("new_owner", new_owner))
+}
+
+/// Ends the open round of `pool` without a draw and refunds every stake in
+/// full, free bets as credits, and returns the bonus to the fee address. A
+/// refund that bounces is moved to the escrow, so no bettor can block the
+/// cancellation.
+pub fn cancel_round(deps: DepsMut, _env: Env, pool: PoolId) -> Result<Response, ContractError> {
+    let mut config = query_config(deps.as_ref())?;
+    let bets = std::mem::take(config.user_list_mut(pool));
+    let bonus = bonus::pot_bonus(deps.storage, pool)?;
+    if bets.is_empty() && bonus.is_empty() {
+        return Err(ContractError::EmptyPot {
+            pool: pool.to_string(),
+        });
+    }
+    let round_id = current_round(deps.storage, pool)?;
+    solvency::discharge_coins(deps.storage, &denoms::pot_totals(&bets))?;
+    solvency::discharge_coins(deps.storage, &bonus)?;
+
+    // One refund per bettor and denom. Free bets go back to the credits.
+    let mut refunds: Vec<(Addr, String, u128)> = vec![];
+    for bet in bets {
+        if bet.free_bet {
+            let credit = coin(bet.user_amount, bet.coin_type);
+            loyalty::add_credit(deps.storage, &bet.user_address, credit)?;
+            continue;
+        }
+        match refunds
+            .iter_mut()
+            .find(|(bettor, denom, _)| *bettor == bet.user_address && *denom == bet.coin_type)
+        {
+            Some((_, _, amount)) => *amount += bet.user_amount,
+            None => refunds.push((bet.user_address, bet.coin_type, bet.user_amount)),
+        }
+    }
+    // The bonus is house money and goes back to the fee address.
+    for coin in bonus {
+        refunds.push((config.fee_address.clone(), coin.denom, coin.amount.u128()));
+    }
+    let mut res = Response::new()
+        .add_attribute("action", "cancel_round")
+        .add_attribute("pool_id", pool.to_string())
+        .add_attribute("round_id", round_id.to_string());
+    for (index, (bettor, denom, amount)) in refunds.into_iter().enumerate() {
+        let refund = coin(amount, denom);
+        let (reply_id, _) = payout::reply_ids(index);
+        res = res
+            .add_submessages(payout::payout_msg(
+                deps.storage,
+                reply_id,
+                &bettor,
+                refund.clone(),
+                pool.as_str(),
+                round_id,
+            )?)
+            .add_event(events::refund(pool, round_id, &bettor, &refund));
+    }
+
+    *config.pot_total_mut(pool) = 0;
+    *config.start_flag_mut(pool) = true;
+    CONFIG.save(deps.storage, &config)?;
+    CLOSE_TIMES.remove(deps.storage, pool.as_str());
+    POT_BONUSES.remove(deps.storage, pool.as_str());
+    ROUND_FEES.remove(deps.storage, pool.as_str());
+    ROUND_IDS.save(deps.storage, pool.as_str(), &(round_id + 1))?;
+    Ok(res)
+}
+
+pub fn query_house_settings(deps: Deps) -> StdResult<HouseSettingsResponse> {
+    Ok(HouseSettingsResponse {
+        owner: query_config(deps)?.fee_address,
+        house_fee_bps: house_fee_bps(deps.storage)?,
+        paused: is_paused(deps.storage)?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::contract::{execute, instantiate};
+    use crate::msg::InstantiateMsg;
+    use crate::state::{RoundRecord, ROUND_HISTORY};
+    use cosmwasm_std::testing::{mock_dependencies_with_balance, mock_env, mock_info};
+    use cosmwasm_std::{coins, BankMsg, CosmosMsg};
+
+    const DENOM: &str = "usei";
+
+    fn bet(deps: DepsMut, bettor: &str, amount: u128) -> Result<Response, ContractError> {
+        execute(
+            deps,
+            mock_env(),
+            mock_info(bettor, &coins(amount, DENOM)),
+            ExecuteMsg::AddBetUserInfinite { client_seed: None },
+        )
+    }
+
+    #[test]
+    fn test_sudo_controls() {
+        let mut deps = mock_dependencies_with_balance(&coins(10_000, DENOM));
+        let msg = InstantiateMsg {
+            fee_address: Addr::unchecked("fee_address"),
+            start_flag: true,
+        };
+        instantiate(deps.as_mut(), mock_env(), mock_info("creator", &[]), msg).unwrap();
+
+        let err = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("alice", &[]),
+            ExecuteMsg::Pause {},
+        )
+        .unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized));
+        sudo(deps.as_mut(), mock_env(), SudoMsg::Pause {}).unwrap();
+        let err = bet(deps.as_mut(), "alice", 100).unwrap_err();
+        assert!(matches!(err, ContractError::Paused));
+        sudo(deps.as_mut(), mock_env(), SudoMsg::Unpause {}).unwrap();
+        // A round opened at the default fee.
+        bet(deps.as_mut(), "alice", 1000).unwrap();
+
+        let err = sudo(
+            deps.as_mut(),
+            mock_env(),
+            SudoMsg::SetHouseFee {
+                fee_bps: MAX_HOUSE_FEE_BPS + 1,
+            },
+        )
+        .unwrap_err();
+        assert!(matches!(err, ContractError::InvalidHouseFee { .. }));
+        sudo(
+            deps.as_mut(),
+            mock_env(),
+            SudoMsg::SetHouseFee { fee_bps: 1000 },
+        )
+        .unwrap();
+        sudo(
+            deps.as_mut(),
+            mock_env(),
+            SudoMsg::TransferOwnership {
+                new_owner: Addr::unchecked("dao"),
+            },
+        )
+        .unwrap();
+        assert_eq!(
+            HouseSettingsResponse {
+                owner: Addr::unchecked("dao"),
+                house_fee_bps: 1000,
+                paused: false,
+            },
+            query_house_settings(deps.as_ref()).unwrap()
+        );
+
+        // The new owner settles the open round at the fee it opened with,
+        // and the next one at the new fee.
+        let owner = mock_info("dao", &[]);
+        let settle = [
+            ExecuteMsg::ToggleGameInfinte {},
+            ExecuteMsg::EndGameInfinte { seed: 7 },
+        ];
+        for msg in settle.clone() {
+            execute(deps.as_mut(), mock_env(), owner.clone(), msg).unwrap();
+        }
+        bet(deps.as_mut(), "alice", 1000).unwrap();
+        for msg in settle {
+            execute(deps.as_mut(), mock_env(), owner.clone(), msg).unwrap();
+        }
+        let fee_and_prize = |round_id: u64| {
+            let record: RoundRecord = ROUND_HISTORY
+                .load(&deps.storage, ("infinite", round_id))
+                .unwrap();
+            (record.fee, record.prize)
+        };
+        assert_eq!((40, 960), fee_and_prize(1));
+        assert_eq!((100, 900), fee_and_prize(2));
+    }
+
+    #[test]
+    fn test_cancel_round() {
+        let mut deps = mock_dependencies_with_balance(&coins(10_000, DENOM));
+        let msg = InstantiateMsg {
+            fee_address: Addr::unchecked("fee_address"),
+            start_flag: true,
+        };
+        instantiate(deps.as_mut(), mock_env(), mock_info("creator", &[]), msg).unwrap();
+        for (bettor, amount) in [("alice", 100), ("bob", 50), ("alice", 30)] {
+            bet(deps.as_mut(), bettor, amount).unwrap();
+        }
+
+        let res = sudo(
+            deps.as_mut(),
+            mock_env(),
+            SudoMsg::CancelRound {
+                pool_id: PoolId::Infinite,
+            },
+        )
+        .unwrap();
+        let refunds: Vec<CosmosMsg> = res.messages.iter().map(|sub| sub.msg.clone()).collect();
+        let refund = |to_address: &str, amount: u128| -> CosmosMsg {
+            BankMsg::Send {
+                to_address: to_address.to_string(),
+                amount: coins(amount, DENOM),
+            }
+            .into()
+        };
+        assert_eq!(vec![refund("alice", 130), refund("bob", 50)], refunds);
+
+        let config = query_config(deps.as_ref()).unwrap();
+        assert!(config.user_list(PoolId::Infinite).is_empty());
+        assert_eq!(0, config.pot_total(PoolId::Infinite));
+        assert_eq!(2, current_round(&deps.storage, PoolId::Infinite).unwrap());
+        let err = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("fee_address", &[]),
+            ExecuteMsg::CancelRound {
+                pool_id: PoolId::Infinite,
+            },
+        )
+        .unwrap_err();
+        assert!(matches!(err, ContractError::EmptyPot { .. }));
+        crate::solvency::assert_tracked(deps.as_ref());
+    }
+}
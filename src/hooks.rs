@@ -0,0 +1,246 @@
+//! Hooks for external contracts.
+//! Every registered contract is notified when a bet is placed in a pool and
+//! when a pool or the staking pool settles a round, with:
+//!
+//! ```json
+//! { "bet_placed_hook": { "pool_id": "infinite", "round_id": 1, "bettor": "sei1...", "amount": { "denom": "usei", "amount": "100" } } }
+//! { "round_settled_hook": { "pool_id": "infinite", "round_id": 1, "winner": "sei1...", "pots": [{ "denom": "usei", "amount": "1000" }] } }
+//! ```
+//!
+//! Hooks are gas-limited submessages that reply on error, so a hook that
+//! fails is reverted on its own and reported, and never blocks the bet or
+//! the draw.
+
+use cosmwasm_std::{
+    to_json_binary, Addr, Coin, Deps, DepsMut, Env, MessageInfo, Order, Reply, Response, StdResult,
+    Storage, SubMsg, SubMsgResult, WasmMsg,
+};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::query::query_config;
+use crate::state::HOOKS;
+use crate::ContractError;
+
+/// Reply id of every hook, below the payout ids.
+pub const HOOK_REPLY_ID: u64 = 10;
+/// Gas each hook may use. A hook running out fails alone instead of taking
+/// the whole transaction down.
+pub const HOOK_GAS_LIMIT: u64 = 300_000;
+/// Most hooks that can be registered, to bound the gas of a bet.
+pub const MAX_HOOKS: usize = 10;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum HookMsg {
+    BetPlacedHook {
+        pool_id: String,
+        round_id: u64,
+        bettor: Addr,
+        amount: Coin,
+    },
+    /// `pots` are the gross pots of the round, one per denom.
+    RoundSettledHook {
+        pool_id: String,
+        round_id: u64,
+        winner: Addr,
+        pots: Vec<Coin>,
+    },
+}
+
+pub fn update_hooks(
+    deps: DepsMut,
+    _env: Env,
+    info: MessageInfo,
+    add: Vec<Addr>,
+    remove: Vec<Addr>,
+) -> Result<Response, ContractError> {
+    let config = query_config(deps.as_ref())?;
+    if info.sender != config.fee_address {
+        return Err(ContractError::Unauthorized);
+    }
+
+    for contract in &remove {
+        HOOKS.remove(deps.storage, contract);
+    }
+    for contract in &add {
+        let contract = deps.api.addr_validate(contract.as_str())?;
+        HOOKS.save(deps.storage, &contract, &())?;
+    }
+    let hooks = query_hooks(deps.as_ref())?;
+    if hooks.len() > MAX_HOOKS {
+        return Err(ContractError::TooManyHooks { max: MAX_HOOKS });
+    }
+
+    Ok(Response::new()
+        .add_attribute("action", "update_hooks")
+        .add_attribute("hooks", hooks.len().to_string()))
+}
+
+/// `msg` for every registered hook.
+pub(crate) fn hook_msgs(storage: &dyn Storage, msg: &HookMsg) -> StdResult<Vec<SubMsg>> {
+    let msg = to_json_binary(msg)?;
+    HOOKS
+        .keys(storage, None, None, Order::Ascending)
+        .map(|contract| {
+            let execute = WasmMsg::Execute {
+                contract_addr: contract?.to_string(),
+                msg: msg.clone(),
+                funds: vec![],
+            };
+            Ok(SubMsg::reply_on_error(execute, HOOK_REPLY_ID).with_gas_limit(HOOK_GAS_LIMIT))
+        })
+        .collect()
+}
+
+/// Reports a failed hook. Its changes are already reverted.
+pub(crate) fn hook_failed(msg: Reply) -> Result<Response, ContractError> {
+    let mut res = Response::new().add_attribute("action", "hook_failed");
+    if let SubMsgResult::Err(error) = msg.result {
+        res = res.add_attribute("error", error);
+    }
+    Ok(res)
+}
+
+pub fn query_hooks(deps: Deps) -> StdResult<Vec<Addr>> {
+    HOOKS
+        .keys(deps.storage, None, None, Order::Ascending)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::contract::{execute, instantiate, reply};
+    use crate::msg::{ExecuteMsg, InstantiateMsg};
+    use cosmwasm_std::testing::{mock_dependencies_with_balance, mock_env, mock_info};
+    use cosmwasm_std::{coin, coins, from_json, CosmosMsg};
+
+    const DENOM: &str = "usei";
+
+    #[test]
+    fn test_hooks() {
+        let mut deps = mock_dependencies_with_balance(&coins(10_000, DENOM));
+        let admin = mock_info("fee_address", &[]);
+        let msg = InstantiateMsg {
+            fee_address: Addr::unchecked("fee_address"),
+            start_flag: true,
+        };
+        instantiate(deps.as_mut(), mock_env(), admin.clone(), msg).unwrap();
+
+        let err = update_hooks(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("alice", &[]),
+            vec![Addr::unchecked("loyalty")],
+            vec![],
+        )
+        .unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized));
+        let too_many: Vec<Addr> = (0..=MAX_HOOKS)
+            .map(|i| Addr::unchecked(format!("hook{}", i)))
+            .collect();
+        let err = update_hooks(
+            deps.as_mut(),
+            mock_env(),
+            admin.clone(),
+            too_many.clone(),
+            vec![],
+        )
+        .unwrap_err();
+        assert!(matches!(err, ContractError::TooManyHooks { .. }));
+        // Unit tests keep what a failed message saved, so clear it here.
+        update_hooks(
+            deps.as_mut(),
+            mock_env(),
+            admin.clone(),
+            vec![Addr::unchecked("loyalty"), Addr::unchecked("rewards")],
+            too_many,
+        )
+        .unwrap();
+        assert_eq!(
+            vec![Addr::unchecked("loyalty"), Addr::unchecked("rewards")],
+            query_hooks(deps.as_ref()).unwrap()
+        );
+
+        let hook_targets = |res: &Response| -> Vec<(String, HookMsg)> {
+            res.messages
+                .iter()
+                .filter_map(|sub| match &sub.msg {
+                    CosmosMsg::Wasm(WasmMsg::Execute {
+                        contract_addr, msg, ..
+                    }) => {
+                        assert_eq!(HOOK_REPLY_ID, sub.id);
+                        assert_eq!(Some(HOOK_GAS_LIMIT), sub.gas_limit);
+                        Some((contract_addr.clone(), from_json(msg).unwrap()))
+                    }
+                    _ => None,
+                })
+                .collect()
+        };
+
+        let res = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("alice", &coins(100, DENOM)),
+            ExecuteMsg::AddBetUserInfinite { client_seed: None },
+        )
+        .unwrap();
+        let bet_placed = HookMsg::BetPlacedHook {
+            pool_id: "infinite".to_string(),
+            round_id: 1,
+            bettor: Addr::unchecked("alice"),
+            amount: coin(100, DENOM),
+        };
+        assert_eq!(
+            vec![
+                ("loyalty".to_string(), bet_placed.clone()),
+                ("rewards".to_string(), bet_placed),
+            ],
+            hook_targets(&res)
+        );
+
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            admin.clone(),
+            ExecuteMsg::ToggleGameInfinte {},
+        )
+        .unwrap();
+        let res = execute(
+            deps.as_mut(),
+            mock_env(),
+            admin,
+            ExecuteMsg::EndGameInfinte { seed: 7 },
+        )
+        .unwrap();
+        let round_settled = HookMsg::RoundSettledHook {
+            pool_id: "infinite".to_string(),
+            round_id: 1,
+            winner: Addr::unchecked("alice"),
+            pots: vec![coin(100, DENOM)],
+        };
+        assert_eq!(
+            vec![
+                ("loyalty".to_string(), round_settled.clone()),
+                ("rewards".to_string(), round_settled),
+            ],
+            hook_targets(&res)
+        );
+
+        // A failing hook is only reported.
+        let res = reply(
+            deps.as_mut(),
+            mock_env(),
+            Reply {
+                id: HOOK_REPLY_ID,
+                result: SubMsgResult::Err("loyalty is broken".to_string()),
+            },
+        )
+        .unwrap();
+        assert!(res
+            .attributes
+            .iter()
+            .any(|attr| attr.key == "error" && attr.value == "loyalty is broken"));
+    }
+}
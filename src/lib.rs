@@ -1,15 +1,35 @@
-//! This is a simple implementation of CosmWasm based Non-Fungible Token(NFT).
-//! This mod does implement the CW721 spec. However, it is different than the
-//! base implementation shown in [cw721-base]. All the functions
-//! are implemented as indivisual functions in contract.rs, rather than
-//! implementing it as a Cw721 trait.
+//! A CosmWasm betting contract.
+//! Bettors join the infinite, whale and shrimp pots and the admin draws a
+//! stake-weighted winner of each round. Next to the pots the contract runs a
+//! no-loss staking pool, 1v1 coin flips and parimutuel prediction markets.
 //!
-//! [cw721-base]: https://github.com/CosmWasm/cw-nfts/tree/main/contracts/cw721-base
+//! Bets can optionally mint a ticket NFT from a [CW721] collection, which
+//! makes entries tradable before the draw.
+//!
+//! [CW721]: https://github.com/CosmWasm/cw-nfts/tree/main/contracts/cw721-base
 
+mod allowlist;
+mod bonus;
+mod coinflip;
 pub mod contract;
+mod denoms;
 mod error;
+mod events;
+#[cfg(test)]
+mod fairness;
+pub mod governance;
+mod hooks;
+mod limits;
+mod loyalty;
 pub mod msg;
+mod nft;
+mod oracle;
+mod payout;
+mod prediction;
 pub mod query;
+mod randomness;
+mod solvency;
+pub mod staking;
 pub mod state;
 
 pub use crate::error::ContractError;
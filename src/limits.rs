@@ -0,0 +1,325 @@
+//! Responsible-gambling limits.
+//! Every address can cap what it wagers in the pools, coin flips and
+//! prediction markets per day, week and 30 days, and exclude itself from
+//! betting for a while. Lowering a limit takes
+//! effect at once, raising one only after `LIMIT_RAISE_COOLDOWN`. Limits
+//! count raw amounts, whatever the denom.
+
+use cosmwasm_std::{Addr, DepsMut, Env, MessageInfo, Response, StdResult, Storage};
+
+use crate::msg::WagerLimits;
+use crate::state::{PendingLimits, PlayerLimits, PLAYER_LIMITS};
+use crate::ContractError;
+
+/// How long raised limits wait before they apply, in seconds.
+pub const LIMIT_RAISE_COOLDOWN: u64 = 24 * 60 * 60;
+
+const DAY: u64 = 24 * 60 * 60;
+const WEEK: u64 = 7 * DAY;
+const MONTH: u64 = 30 * DAY;
+
+pub fn set_wager_limits(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    limits: WagerLimits,
+) -> Result<Response, ContractError> {
+    let now = env.block.time.seconds();
+    let mut player = load_player_limits(deps.storage, &info.sender, now)?;
+
+    let lowered = WagerLimits {
+        daily: lower(player.limits.daily, limits.daily),
+        weekly: lower(player.limits.weekly, limits.weekly),
+        monthly: lower(player.limits.monthly, limits.monthly),
+    };
+    player.pending = if lowered == limits {
+        None
+    } else {
+        Some(PendingLimits {
+            limits,
+            effective_at: now + LIMIT_RAISE_COOLDOWN,
+        })
+    };
+    player.limits = lowered;
+    PLAYER_LIMITS.save(deps.storage, &info.sender, &player)?;
+
+    let mut res = Response::new()
+        .add_attribute("action", "set_wager_limits")
+        .add_attribute("player", info.sender.to_string());
+    if let Some(pending) = &player.pending {
+        res = res.add_attribute("raise_effective_at", pending.effective_at.to_string());
+    }
+    Ok(res)
+}
+
+pub fn self_exclude(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    duration: u64,
+) -> Result<Response, ContractError> {
+    let now = env.block.time.seconds();
+    let mut player = load_player_limits(deps.storage, &info.sender, now)?;
+    player.excluded_until = player.excluded_until.max(now.saturating_add(duration));
+    PLAYER_LIMITS.save(deps.storage, &info.sender, &player)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "self_exclude")
+        .add_attribute("player", info.sender.to_string())
+        .add_attribute("excluded_until", player.excluded_until.to_string()))
+}
+
+/// Settings of `address` as of `now`, with a due raise applied and the
+/// totals of past periods cleared.
+pub fn load_player_limits(
+    storage: &dyn Storage,
+    address: &Addr,
+    now: u64,
+) -> StdResult<PlayerLimits> {
+    let mut player = PLAYER_LIMITS
+        .may_load(storage, address)?
+        .unwrap_or_default();
+
+    if let Some(pending) = player.pending.take() {
+        if pending.effective_at <= now {
+            player.limits = pending.limits;
+        } else {
+            player.pending = Some(pending);
+        }
+    }
+
+    let wagered = &mut player.wagered;
+    if wagered.day != now / DAY {
+        wagered.day = now / DAY;
+        wagered.daily = 0;
+    }
+    if wagered.week != now / WEEK {
+        wagered.week = now / WEEK;
+        wagered.weekly = 0;
+    }
+    if wagered.month != now / MONTH {
+        wagered.month = now / MONTH;
+        wagered.monthly = 0;
+    }
+    Ok(player)
+}
+
+/// Checks a wager of `amount` against the bettor's exclusion and limits,
+/// and books it.
+pub(crate) fn record_wager(
+    storage: &mut dyn Storage,
+    now: u64,
+    address: &Addr,
+    amount: u128,
+) -> Result<(), ContractError> {
+    let mut player = load_player_limits(storage, address, now)?;
+    if now < player.excluded_until {
+        return Err(ContractError::SelfExcluded {
+            until: player.excluded_until,
+        });
+    }
+
+    let wagered = &mut player.wagered;
+    wagered.daily = add_wager(player.limits.daily, wagered.daily, amount, "daily")?;
+    wagered.weekly = add_wager(player.limits.weekly, wagered.weekly, amount, "weekly")?;
+    wagered.monthly = add_wager(player.limits.monthly, wagered.monthly, amount, "monthly")?;
+
+    PLAYER_LIMITS.save(storage, address, &player)?;
+    Ok(())
+}
+
+fn add_wager(
+    limit: Option<u128>,
+    total: u128,
+    amount: u128,
+    period: &str,
+) -> Result<u128, ContractError> {
+    let total = total.saturating_add(amount);
+    match limit {
+        Some(limit) if total > limit => Err(ContractError::WagerLimitExceeded {
+            period: period.to_string(),
+            limit,
+        }),
+        _ => Ok(total),
+    }
+}
+
+/// The stricter of two limits, where `None` is no limit.
+fn lower(current: Option<u128>, requested: Option<u128>) -> Option<u128> {
+    match (current, requested) {
+        (Some(current), Some(requested)) => Some(current.min(requested)),
+        (current, None) => current,
+        (None, requested) => requested,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::contract::{execute, instantiate};
+    use crate::msg::{CoinSide, ExecuteMsg, InstantiateMsg};
+    use cosmwasm_std::coins;
+    use cosmwasm_std::testing::{mock_dependencies_with_balance, mock_env, mock_info};
+
+    const DENOM: &str = "usei";
+
+    fn env_at(offset: u64) -> Env {
+        let mut env = mock_env();
+        env.block.time = env.block.time.plus_seconds(offset);
+        env
+    }
+
+    fn bet(deps: DepsMut, env: Env, amount: u128) -> Result<Response, ContractError> {
+        execute(
+            deps,
+            env,
+            mock_info("alice", &coins(amount, DENOM)),
+            ExecuteMsg::AddBetUserInfinite { client_seed: None },
+        )
+    }
+
+    #[test]
+    fn test_wager_limits() {
+        let mut deps = mock_dependencies_with_balance(&coins(10_000, DENOM));
+        let msg = InstantiateMsg {
+            fee_address: Addr::unchecked("fee_address"),
+            start_flag: true,
+        };
+        instantiate(deps.as_mut(), mock_env(), mock_info("creator", &[]), msg).unwrap();
+
+        let limits = WagerLimits {
+            daily: Some(100),
+            ..WagerLimits::default()
+        };
+        // Setting a first limit lowers "no limit", so it applies at once.
+        set_wager_limits(deps.as_mut(), env_at(0), mock_info("alice", &[]), limits).unwrap();
+        bet(deps.as_mut(), env_at(0), 60).unwrap();
+        let err = bet(deps.as_mut(), env_at(0), 50).unwrap_err();
+        assert!(matches!(
+            err,
+            ContractError::WagerLimitExceeded { limit: 100, .. }
+        ));
+
+        // Raising waits for the cooldown.
+        let limits = WagerLimits {
+            daily: Some(200),
+            ..WagerLimits::default()
+        };
+        set_wager_limits(deps.as_mut(), env_at(0), mock_info("alice", &[]), limits).unwrap();
+        bet(deps.as_mut(), env_at(0), 50).unwrap_err();
+
+        let player = load_player_limits(
+            &deps.storage,
+            &Addr::unchecked("alice"),
+            env_at(LIMIT_RAISE_COOLDOWN).block.time.seconds(),
+        )
+        .unwrap();
+        assert_eq!(Some(200), player.limits.daily);
+        assert_eq!(None, player.pending);
+        assert_eq!(0, player.wagered.daily);
+        bet(deps.as_mut(), env_at(LIMIT_RAISE_COOLDOWN), 150).unwrap();
+    }
+
+    #[test]
+    fn test_self_exclusion() {
+        let mut deps = mock_dependencies_with_balance(&coins(10_000, DENOM));
+        let msg = InstantiateMsg {
+            fee_address: Addr::unchecked("fee_address"),
+            start_flag: true,
+        };
+        instantiate(deps.as_mut(), mock_env(), mock_info("creator", &[]), msg).unwrap();
+
+        self_exclude(deps.as_mut(), env_at(0), mock_info("alice", &[]), 1000).unwrap();
+        // A shorter exclusion doesn't lift the longer one.
+        self_exclude(deps.as_mut(), env_at(0), mock_info("alice", &[]), 10).unwrap();
+
+        let err = bet(deps.as_mut(), env_at(999), 10).unwrap_err();
+        assert!(matches!(err, ContractError::SelfExcluded { .. }));
+        bet(deps.as_mut(), env_at(1000), 10).unwrap();
+
+        // Excluding oneself for good.
+        self_exclude(deps.as_mut(), env_at(0), mock_info("alice", &[]), u64::MAX).unwrap();
+        let err = bet(deps.as_mut(), env_at(1000), 10).unwrap_err();
+        assert!(matches!(
+            err,
+            ContractError::SelfExcluded { until: u64::MAX }
+        ));
+    }
+
+    #[test]
+    fn test_limits_cover_every_game() {
+        let mut deps = mock_dependencies_with_balance(&coins(10_000, DENOM));
+        let msg = InstantiateMsg {
+            fee_address: Addr::unchecked("fee_address"),
+            start_flag: true,
+        };
+        instantiate(deps.as_mut(), mock_env(), mock_info("creator", &[]), msg).unwrap();
+        let create_market = ExecuteMsg::CreateMarket {
+            question: String::from("Will it rain?"),
+            outcomes: vec![String::from("yes"), String::from("no")],
+            resolver: Addr::unchecked("resolver"),
+            denom: String::from(DENOM),
+            closes_at: env_at(100).block.time.seconds(),
+            dispute_window: 50,
+        };
+        execute(
+            deps.as_mut(),
+            env_at(0),
+            mock_info("fee_address", &[]),
+            create_market,
+        )
+        .unwrap();
+        let open_challenge = |sender: &str, amount| {
+            (
+                mock_info(sender, &coins(amount, DENOM)),
+                ExecuteMsg::OpenChallenge {
+                    side: CoinSide::Heads,
+                },
+            )
+        };
+        let market_bet = |amount| {
+            (
+                mock_info("alice", &coins(amount, DENOM)),
+                ExecuteMsg::PlaceMarketBet {
+                    market_id: 1,
+                    outcome: 0,
+                },
+            )
+        };
+
+        // Challenges and market bets count towards the limits.
+        let limits = WagerLimits {
+            daily: Some(100),
+            ..WagerLimits::default()
+        };
+        set_wager_limits(deps.as_mut(), env_at(0), mock_info("alice", &[]), limits).unwrap();
+        for (info, msg) in [open_challenge("alice", 40), market_bet(40)] {
+            execute(deps.as_mut(), env_at(0), info, msg).unwrap();
+        }
+        let err = bet(deps.as_mut(), env_at(0), 30).unwrap_err();
+        assert!(matches!(
+            err,
+            ContractError::WagerLimitExceeded { limit: 100, .. }
+        ));
+
+        // An excluded player can neither open, accept nor bet on a market.
+        execute(
+            deps.as_mut(),
+            env_at(0),
+            mock_info("bob", &coins(10, DENOM)),
+            ExecuteMsg::OpenChallenge {
+                side: CoinSide::Tails,
+            },
+        )
+        .unwrap();
+        self_exclude(deps.as_mut(), env_at(0), mock_info("alice", &[]), 1000).unwrap();
+        let accept = (
+            mock_info("alice", &coins(10, DENOM)),
+            ExecuteMsg::AcceptChallenge { challenge_id: 2 },
+        );
+        for (info, msg) in [open_challenge("alice", 10), accept, market_bet(10)] {
+            let err = execute(deps.as_mut(), env_at(1), info, msg).unwrap_err();
+            assert!(matches!(err, ContractError::SelfExcluded { .. }));
+        }
+    }
+}
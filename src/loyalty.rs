@@ -0,0 +1,405 @@
+//! Loyalty points and free bets.
+//! Pool bets earn points in proportion to the stake, times the multiplier
+//! of the bettor's tier, once their round is drawn. Free bets and bets
+//! withdrawn or cancelled earn none. Points can't be transferred, only redeemed for
+//! free-bet credits, which are bet with `PlaceFreeBet` in place of funds.
+//! Credits are backed by the house: they count as liabilities from the
+//! moment they are redeemed, so a redemption the contract's surplus can't
+//! cover fails, and a free bet only turns a credit into a stake.
+
+use cosmwasm_std::{
+    Addr, Coin, Decimal, Deps, DepsMut, Env, MessageInfo, Response, StdResult, Storage, Uint128,
+};
+
+use crate::contract::place_bet;
+use crate::msg::{LoyaltyConfig, LoyaltyResponse, PoolId};
+use crate::query::query_config;
+use crate::solvency;
+use crate::state::{LoyaltyAccount, FREE_BET_CREDITS, LOYALTY_ACCOUNTS, LOYALTY_CONFIG};
+use crate::ContractError;
+
+pub fn set_loyalty_config(
+    deps: DepsMut,
+    _env: Env,
+    info: MessageInfo,
+    loyalty_config: Option<LoyaltyConfig>,
+) -> Result<Response, ContractError> {
+    let config = query_config(deps.as_ref())?;
+    if info.sender != config.fee_address {
+        return Err(ContractError::Unauthorized);
+    }
+
+    match &loyalty_config {
+        Some(loyalty_config) => {
+            let tiers = &loyalty_config.tiers;
+            let rates = &loyalty_config.redemption_rates;
+            let duplicate = |i: usize| rates[..i].iter().any(|r| r.denom == rates[i].denom);
+            if tiers.windows(2).any(|t| t[0].min_points >= t[1].min_points)
+                || (0..rates.len()).any(|i| rates[i].credit_per_point.is_zero() || duplicate(i))
+            {
+                return Err(ContractError::InvalidLoyaltyConfig);
+            }
+            LOYALTY_CONFIG.save(deps.storage, loyalty_config)?;
+        }
+        None => LOYALTY_CONFIG.remove(deps.storage),
+    }
+
+    Ok(Response::new()
+        .add_attribute("action", "set_loyalty_config")
+        .add_attribute("loyalty", loyalty_config.is_some().to_string()))
+}
+
+pub fn redeem_points(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    points: u128,
+    denom: String,
+) -> Result<Response, ContractError> {
+    let loyalty_config = LOYALTY_CONFIG
+        .may_load(deps.storage)?
+        .ok_or(ContractError::LoyaltyNotConfigured)?;
+    let rate = loyalty_config
+        .redemption_rates
+        .iter()
+        .find(|rate| rate.denom == denom)
+        .ok_or_else(|| ContractError::NotRedeemable {
+            denom: denom.clone(),
+        })?;
+
+    let mut account = load_account(deps.storage, &info.sender)?;
+    if points > account.points {
+        return Err(ContractError::InsufficientPoints {
+            available: account.points,
+        });
+    }
+    let credit = Uint128::new(points) * rate.credit_per_point;
+    if credit.is_zero() {
+        return Err(ContractError::RedemptionTooSmall);
+    }
+    account.points -= points;
+    LOYALTY_ACCOUNTS.save(deps.storage, &info.sender, &account)?;
+    add_credit(deps.storage, &info.sender, Coin::new(credit.u128(), &denom))?;
+    solvency::assert_solvent(deps.as_ref(), &env, &denom, 0)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "redeem_points")
+        .add_attribute("points", points.to_string())
+        .add_attribute("credit", Coin::new(credit.u128(), denom).to_string()))
+}
+
+pub fn place_free_bet(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    pool: PoolId,
+    amount: Coin,
+    client_seed: Option<String>,
+) -> Result<Response, ContractError> {
+    if amount.amount.is_zero() {
+        return Err(ContractError::NoFunds);
+    }
+    let mut credits = FREE_BET_CREDITS
+        .may_load(deps.storage, &info.sender)?
+        .unwrap_or_default();
+    let available = credits
+        .iter()
+        .find(|credit| credit.denom == amount.denom)
+        .map_or(0, |credit| credit.amount.u128());
+    if amount.amount.u128() > available {
+        return Err(ContractError::InsufficientCredits {
+            denom: amount.denom,
+            available,
+        });
+    }
+    for credit in credits.iter_mut() {
+        if credit.denom == amount.denom {
+            credit.amount -= amount.amount;
+        }
+    }
+    credits.retain(|credit| !credit.amount.is_zero());
+    if credits.is_empty() {
+        FREE_BET_CREDITS.remove(deps.storage, &info.sender);
+    } else {
+        FREE_BET_CREDITS.save(deps.storage, &info.sender, &credits)?;
+    }
+    // The credits become the stake of the bet.
+    solvency::discharge(deps.storage, &amount.denom, amount.amount.u128())?;
+
+    place_bet(deps, env, info.sender, pool, amount, client_seed, true)
+}
+
+/// Books the points earned by a drawn pool bet of `amount`.
+pub(crate) fn accrue_points(
+    storage: &mut dyn Storage,
+    bettor: &Addr,
+    amount: u128,
+) -> StdResult<()> {
+    let loyalty_config = match LOYALTY_CONFIG.may_load(storage)? {
+        Some(loyalty_config) => loyalty_config,
+        None => return Ok(()),
+    };
+    let mut account = load_account(storage, bettor)?;
+    let multiplier = tier_multiplier(&loyalty_config, account.lifetime_points);
+    let points = (Uint128::new(amount) * loyalty_config.points_per_unit * multiplier).u128();
+    if points > 0 {
+        account.points += points;
+        account.lifetime_points += points;
+        LOYALTY_ACCOUNTS.save(storage, bettor, &account)?;
+    }
+    Ok(())
+}
+
+/// Adds `credit` to the free-bet credits of `address`.
+pub(crate) fn add_credit(storage: &mut dyn Storage, address: &Addr, credit: Coin) -> StdResult<()> {
+    solvency::owe(storage, &credit.denom, credit.amount.u128())?;
+    FREE_BET_CREDITS.update(storage, address, |credits| -> StdResult<_> {
+        let mut credits = credits.unwrap_or_default();
+        match credits.iter_mut().find(|coin| coin.denom == credit.denom) {
+            Some(coin) => coin.amount += credit.amount,
+            None => credits.push(credit),
+        }
+        Ok(credits)
+    })?;
+    Ok(())
+}
+
+fn load_account(storage: &dyn Storage, address: &Addr) -> StdResult<LoyaltyAccount> {
+    Ok(LOYALTY_ACCOUNTS
+        .may_load(storage, address)?
+        .unwrap_or_default())
+}
+
+fn tier_multiplier(loyalty_config: &LoyaltyConfig, lifetime_points: u128) -> Decimal {
+    loyalty_config
+        .tiers
+        .iter()
+        .rev()
+        .find(|tier| tier.min_points <= lifetime_points)
+        .map_or(Decimal::one(), |tier| tier.multiplier)
+}
+
+pub fn query_loyalty(deps: Deps, address: Addr) -> StdResult<LoyaltyResponse> {
+    let account = load_account(deps.storage, &address)?;
+    let multiplier = match LOYALTY_CONFIG.may_load(deps.storage)? {
+        Some(loyalty_config) => tier_multiplier(&loyalty_config, account.lifetime_points),
+        None => Decimal::one(),
+    };
+    Ok(LoyaltyResponse {
+        points: account.points,
+        lifetime_points: account.lifetime_points,
+        multiplier,
+        credits: FREE_BET_CREDITS
+            .may_load(deps.storage, &address)?
+            .unwrap_or_default(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::contract::{execute, instantiate};
+    use crate::msg::{ExecuteMsg, InstantiateMsg, LoyaltyTier, RedemptionRate};
+    use cosmwasm_std::testing::{mock_dependencies_with_balance, mock_env, mock_info};
+    use cosmwasm_std::{coin, coins, BankMsg, CosmosMsg};
+
+    const DENOM: &str = "usei";
+
+    #[test]
+    fn test_points_and_free_bets() {
+        let mut deps = mock_dependencies_with_balance(&coins(10_000, DENOM));
+        let admin = mock_info("fee_address", &[]);
+        let msg = InstantiateMsg {
+            fee_address: Addr::unchecked("fee_address"),
+            start_flag: true,
+        };
+        instantiate(deps.as_mut(), mock_env(), admin.clone(), msg).unwrap();
+
+        // A point per 10 usei, doubled from 50 lifetime points. 10 points
+        // redeem for 1 usei.
+        let tier = |min_points: u128, multiplier: u64| LoyaltyTier {
+            min_points,
+            multiplier: Decimal::from_ratio(multiplier, 1u128),
+        };
+        let mut loyalty_config = LoyaltyConfig {
+            points_per_unit: Decimal::percent(10),
+            tiers: vec![tier(50, 2), tier(50, 3)],
+            redemption_rates: vec![RedemptionRate {
+                denom: DENOM.to_string(),
+                credit_per_point: Decimal::percent(10),
+            }],
+        };
+        let err = set_loyalty_config(
+            deps.as_mut(),
+            mock_env(),
+            admin.clone(),
+            Some(loyalty_config.clone()),
+        )
+        .unwrap_err();
+        assert!(matches!(err, ContractError::InvalidLoyaltyConfig));
+        loyalty_config.tiers.pop();
+        set_loyalty_config(
+            deps.as_mut(),
+            mock_env(),
+            admin.clone(),
+            Some(loyalty_config),
+        )
+        .unwrap();
+
+        let bet = |deps: DepsMut, amount: u128| {
+            execute(
+                deps,
+                mock_env(),
+                mock_info("alice", &coins(amount, DENOM)),
+                ExecuteMsg::AddBetUserInfinite { client_seed: None },
+            )
+        };
+        let withdraw = |deps: DepsMut| {
+            execute(
+                deps,
+                mock_env(),
+                mock_info("alice", &[]),
+                ExecuteMsg::WithdrawBet {
+                    pool_id: PoolId::Infinite,
+                },
+            )
+        };
+        let points = |deps: Deps| {
+            query_loyalty(deps, Addr::unchecked("alice"))
+                .unwrap()
+                .points
+        };
+
+        // Points are only earned at the draw: a withdrawn bet earns none.
+        bet(deps.as_mut(), 500).unwrap();
+        assert_eq!(0, points(deps.as_ref()));
+        withdraw(deps.as_mut()).unwrap();
+        assert_eq!(0, points(deps.as_ref()));
+
+        bet(deps.as_mut(), 500).unwrap();
+        bet(deps.as_mut(), 500).unwrap();
+        for msg in [
+            ExecuteMsg::ToggleGameInfinte {},
+            ExecuteMsg::EndGameInfinte { seed: 7 },
+        ] {
+            execute(deps.as_mut(), mock_env(), admin.clone(), msg).unwrap();
+        }
+        let loyalty = query_loyalty(deps.as_ref(), Addr::unchecked("alice")).unwrap();
+        assert_eq!((150, 150), (loyalty.points, loyalty.lifetime_points));
+        assert_eq!(Decimal::from_ratio(2u128, 1u128), loyalty.multiplier);
+
+        let redeem = |deps: DepsMut, points: u128| {
+            execute(
+                deps,
+                mock_env(),
+                mock_info("alice", &[]),
+                ExecuteMsg::RedeemPoints {
+                    points,
+                    denom: DENOM.to_string(),
+                },
+            )
+        };
+        let err = redeem(deps.as_mut(), 151).unwrap_err();
+        assert!(matches!(
+            err,
+            ContractError::InsufficientPoints { available: 150 }
+        ));
+        let err = redeem(deps.as_mut(), 5).unwrap_err();
+        assert!(matches!(err, ContractError::RedemptionTooSmall));
+        redeem(deps.as_mut(), 100).unwrap();
+        let loyalty = query_loyalty(deps.as_ref(), Addr::unchecked("alice")).unwrap();
+        assert_eq!((50, 150), (loyalty.points, loyalty.lifetime_points));
+        assert_eq!(vec![coin(10, DENOM)], loyalty.credits);
+
+        let free_bet = |deps: DepsMut, amount: u128| {
+            execute(
+                deps,
+                mock_env(),
+                mock_info("alice", &[]),
+                ExecuteMsg::PlaceFreeBet {
+                    pool_id: PoolId::Infinite,
+                    amount: coin(amount, DENOM),
+                    client_seed: None,
+                },
+            )
+        };
+        let err = free_bet(deps.as_mut(), 11).unwrap_err();
+        assert!(matches!(
+            err,
+            ContractError::InsufficientCredits { available: 10, .. }
+        ));
+        bet(deps.as_mut(), 500).unwrap();
+        free_bet(deps.as_mut(), 10).unwrap();
+        let config = query_config(deps.as_ref()).unwrap();
+        assert_eq!(510, config.pot_total(PoolId::Infinite));
+        assert!(config.user_list(PoolId::Infinite)[1].free_bet);
+        assert!(query_loyalty(deps.as_ref(), Addr::unchecked("alice"))
+            .unwrap()
+            .credits
+            .is_empty());
+
+        // Withdrawn, the free bet returns to the credits, only the paid
+        // stake is refunded, and neither earns points.
+        let res = withdraw(deps.as_mut()).unwrap();
+        assert_eq!(
+            vec![CosmosMsg::Bank(BankMsg::Send {
+                to_address: "alice".to_string(),
+                amount: coins(500, DENOM),
+            })],
+            res.messages
+                .iter()
+                .map(|sub| sub.msg.clone())
+                .collect::<Vec<_>>()
+        );
+        let loyalty = query_loyalty(deps.as_ref(), Addr::unchecked("alice")).unwrap();
+        assert_eq!(vec![coin(10, DENOM)], loyalty.credits);
+        assert_eq!(50, loyalty.points);
+        crate::solvency::assert_tracked(deps.as_ref());
+    }
+
+    #[test]
+    fn test_redemption_needs_surplus() {
+        // Once Alice's round is paid out, the contract only holds Bob's bet,
+        // nothing to back credits with.
+        let mut deps = mock_dependencies_with_balance(&coins(1000, DENOM));
+        let admin = mock_info("fee_address", &[]);
+        let msg = InstantiateMsg {
+            fee_address: Addr::unchecked("fee_address"),
+            start_flag: true,
+        };
+        instantiate(deps.as_mut(), mock_env(), admin.clone(), msg).unwrap();
+        let loyalty_config = LoyaltyConfig {
+            points_per_unit: Decimal::one(),
+            tiers: vec![],
+            redemption_rates: vec![RedemptionRate {
+                denom: DENOM.to_string(),
+                credit_per_point: Decimal::one(),
+            }],
+        };
+        set_loyalty_config(deps.as_mut(), mock_env(), admin, Some(loyalty_config)).unwrap();
+        let bet = ExecuteMsg::AddBetUserInfinite { client_seed: None };
+        for (sender, funds, msg) in [
+            ("alice", 1000, bet.clone()),
+            ("fee_address", 0, ExecuteMsg::ToggleGameInfinte {}),
+            ("fee_address", 0, ExecuteMsg::EndGameInfinte { seed: 7 }),
+            ("bob", 1000, bet),
+        ] {
+            let funds = if funds > 0 {
+                coins(funds, DENOM)
+            } else {
+                vec![]
+            };
+            execute(deps.as_mut(), mock_env(), mock_info(sender, &funds), msg).unwrap();
+        }
+
+        let err = redeem_points(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("alice", &[]),
+            1,
+            DENOM.to_string(),
+        )
+        .unwrap_err();
+        assert!(matches!(err, ContractError::Insolvent { .. }));
+    }
+}
@@ -1,5 +1,7 @@
 //! Defines *InstantiateMsg*, *ExecuteMsg* and *QueryMsg*.
-use cosmwasm_std::Addr;
+use std::fmt;
+
+use cosmwasm_std::{Addr, Coin, Decimal};
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
@@ -16,22 +18,569 @@ pub struct UserBetInfo {
     pub user_amount: u128,
     pub user_order: u128,
     pub coin_type: String,
+    /// Ticket numbers bought with this bet, in ticket pools.
+    #[serde(default)]
+    pub tickets: Option<TicketRange>,
+    /// CW721 ticket minted for this bet, if a collection is configured.
+    #[serde(default)]
+    pub nft_token_id: Option<String>,
+    /// Collection the ticket was minted in.
+    #[serde(default)]
+    pub nft_collection: Option<Addr>,
+    /// Bettor's seed for pools drawing from on-chain entropy.
+    #[serde(default)]
+    pub client_seed: Option<String>,
+    /// Stake value at the oracle's price when placed, in multi-denom pools
+    /// while an oracle is configured.
+    #[serde(default)]
+    pub normalized_value: Option<Decimal>,
+    /// Paid with free-bet credits rather than funds.
+    #[serde(default)]
+    pub free_bet: bool,
+}
+
+/// The betting tiers, each running its own pot.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum PoolId {
+    Infinite,
+    Whale,
+    Shrimp,
+}
+
+impl PoolId {
+    pub const ALL: [PoolId; 3] = [PoolId::Infinite, PoolId::Whale, PoolId::Shrimp];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PoolId::Infinite => "infinite",
+            PoolId::Whale => "whale",
+            PoolId::Shrimp => "shrimp",
+        }
+    }
+
+    /// Largest single bet the tier accepts.
+    pub fn max_bet(&self) -> Option<u128> {
+        match self {
+            PoolId::Infinite => None,
+            PoolId::Whale => Some(500),
+            PoolId::Shrimp => Some(50),
+        }
+    }
+}
+
+impl fmt::Display for PoolId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Fixed-price ticket sales for a pool. The round closes by itself once
+/// `supply` tickets are sold.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct TicketConfig {
+    pub price: u128,
+    pub denom: String,
+    pub max_per_user: u64,
+    pub supply: u64,
+}
+
+/// A denom a pool accepts, and what one unit of it weighs in the draw.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct DenomRate {
+    pub denom: String,
+    pub rate: Decimal,
+}
+
+/// Price oracle valuing the stakes of multi-denom pools. Prices updated
+/// more than `max_price_age` seconds before a bet are refused.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct OracleConfig {
+    pub contract: Addr,
+    pub max_price_age: u64,
+}
+
+/// Loyalty points earned on pool bets and what they redeem for.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct LoyaltyConfig {
+    /// Points per unit wagered, before the tier multiplier.
+    pub points_per_unit: Decimal,
+    /// Multipliers by lifetime points earned, in ascending order. The
+    /// highest tier reached applies, none below the first.
+    pub tiers: Vec<LoyaltyTier>,
+    pub redemption_rates: Vec<RedemptionRate>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct LoyaltyTier {
+    pub min_points: u128,
+    pub multiplier: Decimal,
+}
+
+/// Free-bet credit, in units of `denom`, that a point redeems for.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct RedemptionRate {
+    pub denom: String,
+    pub credit_per_point: Decimal,
+}
+
+/// Source of a pool's draw.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum RandomnessMode {
+    /// The admin's seed, through a 32-bit LCG.
+    #[default]
+    Seed,
+    /// sha256 of the round, the draw's block and the bettors' client seeds.
+    /// Predictable by validators, the last bettor and the admin, only for
+    /// low-value pools. The seed passed to the draw is ignored.
+    Entropy,
+}
+
+/// CW721 collection that mints a ticket NFT for every pool bet. The contract
+/// must be the collection's minter. With `claim_by_holder`, a winning prize
+/// is paid to whoever holds the winning ticket when it is claimed.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct NftConfig {
+    pub collection: Addr,
+    pub claim_by_holder: bool,
+}
+
+/// Restricts betting to verified addresses. An address may bet if it is on
+/// the local allowlist or, when `attestation_contract` is set, if that
+/// contract attests it.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct AllowlistConfig {
+    pub attestation_contract: Option<Addr>,
+}
+
+/// An inclusive range of ticket numbers, starting at 1 in every round.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct TicketRange {
+    pub first: u64,
+    pub last: u64,
+}
+
+/// Caps on the amount an address may wager in the pools, coin flips and
+/// prediction markets per day, week and 30 days. Periods are aligned to the unix epoch and `None` means no cap.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, JsonSchema)]
+pub struct WagerLimits {
+    pub daily: Option<u128>,
+    pub weekly: Option<u128>,
+    pub monthly: Option<u128>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum CoinSide {
+    Heads,
+    Tails,
 }
 
 #[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
 #[serde(rename_all = "snake_case")]
 pub enum ExecuteMsg {
-    AddBetUserInfinite {},
-    AddBetUserWhale {},
-    AddBetUserShrimp {},
+    /// Bets the attached coin. `client_seed` is mixed into the draw of
+    /// pools in `RandomnessMode::Entropy`.
+    AddBetUserInfinite {
+        client_seed: Option<String>,
+    },
+    AddBetUserWhale {
+        client_seed: Option<String>,
+    },
+    AddBetUserShrimp {
+        client_seed: Option<String>,
+    },
     ToggleGameInfinte {},
     ToggleGameWhale {},
     ToggleGameShrimp {},
-    EndGameInfinte { seed: u64 },
-    EndGameWhale { seed: u64 },
-    EndGameShrimp { seed: u64 },
+    EndGameInfinte {
+        seed: u64,
+    },
+    EndGameWhale {
+        seed: u64,
+    },
+    EndGameShrimp {
+        seed: u64,
+    },
+    /// Points the no-loss staking pool at a validator.
+    ConfigureStakingPool {
+        validator: String,
+    },
+    StakingDeposit {},
+    /// Queues `amount` of the sender's deposit for the next undelegation.
+    StakingWithdraw {
+        amount: u128,
+    },
+    /// Undelegates the withdrawals queued over the last epoch. Anyone can
+    /// send it once the epoch is over.
+    SubmitUnbonding {},
+    ClaimUnbonded {},
+    HarvestStakingRewards {},
+    EndGameStaking {
+        seed: u64,
+    },
+    /// Stops bets in a pool at `closes_at`, after which the round can be
+    /// settled without toggling it. `None` clears the close time.
+    SetCloseTime {
+        pool_id: PoolId,
+        closes_at: Option<u64>,
+    },
+    /// Settles up to `limit` pools whose close time has passed. Each pool is
+    /// settled in its own submessage, so one failing draw doesn't stop the
+    /// others.
+    EndDueRounds {
+        seed: u64,
+        limit: Option<u32>,
+    },
+    /// Opens a 1v1 coin flip, staking the attached funds on `side`.
+    OpenChallenge {
+        side: CoinSide,
+    },
+    /// Matches an open challenge with an identical stake.
+    AcceptChallenge {
+        challenge_id: u64,
+    },
+    /// Refunds an unmatched challenge. The creator can cancel at any time,
+    /// anyone else only once it has expired. A matched challenge the admin
+    /// did not resolve in time refunds both stakes.
+    CancelChallenge {
+        challenge_id: u64,
+    },
+    ResolveChallenge {
+        challenge_id: u64,
+        seed: u64,
+    },
+    /// Opens a parimutuel market on `outcomes`. Bets are accepted in `denom`
+    /// until `closes_at`, and a posted result can be disputed by the admin for
+    /// `dispute_window` seconds before it pays out.
+    CreateMarket {
+        question: String,
+        outcomes: Vec<String>,
+        resolver: Addr,
+        denom: String,
+        closes_at: u64,
+        dispute_window: u64,
+    },
+    PlaceMarketBet {
+        market_id: u64,
+        outcome: u32,
+    },
+    /// Posts the result of a closed market. `None` marks the market invalid,
+    /// which refunds every stake.
+    ResolveMarket {
+        market_id: u64,
+        outcome: Option<u32>,
+    },
+    DisputeMarket {
+        market_id: u64,
+    },
+    ClaimMarket {
+        market_id: u64,
+    },
+    /// Sells tickets in a pool instead of free stakes, or goes back to free
+    /// stakes with `None`. Only allowed while the pool has no bets or bonus.
+    SetTicketConfig {
+        pool_id: PoolId,
+        ticket_config: Option<TicketConfig>,
+    },
+    /// Lets a pool take bets in several denoms, or only in the denom of its
+    /// first bet with `None`. Stakes are weighed at the rates in the draw,
+    /// and the winner gets the pot of every denom. Only allowed while the
+    /// pool has no bets or bonus.
+    SetPoolDenoms {
+        pool_id: PoolId,
+        denoms: Option<Vec<DenomRate>>,
+    },
+    /// Sets or removes the price oracle. Bets in multi-denom pools are then
+    /// weighed by their oracle value instead of the denom rates. Only
+    /// allowed while no multi-denom pool has bets.
+    SetOracleConfig {
+        oracle_config: Option<OracleConfig>,
+    },
+    /// Switches how a pool draws its winner. Only allowed while the pool
+    /// has no bets or bonus.
+    SetRandomnessMode {
+        pool_id: PoolId,
+        mode: RandomnessMode,
+    },
+    /// Refuses new bets, deposits, challenges and market bets until
+    /// `Unpause`. Withdrawals and claims keep working.
+    Pause {},
+    Unpause {},
+    /// Share of every settled pot, in basis points, that goes to the fee
+    /// address. 400 until set, at most 1000. Rounds, challenges and markets
+    /// already open keep their fee.
+    SetHouseFee {
+        fee_bps: u64,
+    },
+    /// Replaces the owner, who is also the fee address.
+    TransferOwnership {
+        new_owner: Addr,
+    },
+    /// Ends the open round of a pool without a draw, refunds every bet and
+    /// returns the bonus to the fee address.
+    CancelRound {
+        pool_id: PoolId,
+    },
+    /// Share of a withdrawn bet, in basis points, that goes to the fee
+    /// address instead of back to the bettor.
+    SetWithdrawalPenalty {
+        pool_id: PoolId,
+        penalty_bps: u64,
+    },
+    /// Takes the sender's bets out of the open round of a pool and refunds
+    /// them, minus the pool's withdrawal penalty.
+    WithdrawBet {
+        pool_id: PoolId,
+    },
+    /// Starts minting ticket NFTs for pool bets, or stops with `None`. Only
+    /// while no pool has bets in its round.
+    SetNftConfig {
+        nft_config: Option<NftConfig>,
+    },
+    /// Pays the prize held for a winning ticket NFT to its current holder.
+    ClaimNftPrize {
+        token_id: String,
+    },
+    /// Turns allowlist gating on, or off with `None`.
+    SetAllowlistConfig {
+        allowlist_config: Option<AllowlistConfig>,
+    },
+    /// Adds and removes addresses on the local allowlist.
+    UpdateAllowlist {
+        add: Vec<Addr>,
+        remove: Vec<Addr>,
+    },
+    /// Registers and unregisters contracts notified of bets and settled
+    /// rounds.
+    UpdateHooks {
+        add: Vec<Addr>,
+        remove: Vec<Addr>,
+    },
+    /// Sets or removes the loyalty program. Points already earned and
+    /// credits already redeemed are kept.
+    SetLoyaltyConfig {
+        loyalty_config: Option<LoyaltyConfig>,
+    },
+    /// Turns the sender's loyalty points into free-bet credits in `denom`.
+    /// The contract must hold a surplus covering the credits.
+    RedeemPoints {
+        points: u128,
+        denom: String,
+    },
+    /// Bets `amount` from the sender's free-bet credits, without funds.
+    /// Withdrawn or cancelled free bets return to the credits.
+    PlaceFreeBet {
+        pool_id: PoolId,
+        amount: Coin,
+        client_seed: Option<String>,
+    },
+    /// Adds the attached funds to the prize of the open round of a pool,
+    /// as house money that carries no weight in the draw. Returned to the
+    /// fee address if the round is cancelled.
+    FundPot {
+        pool_id: PoolId,
+    },
+    /// Pays out the sender's escrow balance of bounced draw payouts.
+    ClaimEscrow {},
+    /// Sets the sender's own wager limits. Lower limits apply at once, raised
+    /// ones only after a cooldown.
+    SetWagerLimits {
+        limits: WagerLimits,
+    },
+    /// Blocks the sender from betting for `duration` seconds. An exclusion
+    /// can be extended but not shortened.
+    SelfExclude {
+        duration: u64,
+    },
+    /// Sends the balance of `denom` above what the contract owes, such as
+    /// rounding dust and stray transfers, to the fee address.
+    SweepSurplus {
+        denom: String,
+    },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum QueryMsg {
+    StakingPool {},
+    /// Withdrawals of `address` waiting to be claimed.
+    Unbonding {
+        address: Addr,
+    },
+    Challenge {
+        challenge_id: u64,
+    },
+    /// Unmatched challenges that have not expired yet, ordered by id.
+    OpenChallenges {
+        start_after: Option<u64>,
+        limit: Option<u32>,
+    },
+    Market {
+        market_id: u64,
+    },
+    /// Stakes of `address` in a market, indexed by outcome.
+    MarketPosition {
+        market_id: u64,
+        address: Addr,
+    },
+    TicketConfig {
+        pool_id: PoolId,
+    },
+    /// Settings of a pool, with the guarantees of its randomness.
+    PoolConfig {
+        pool_id: PoolId,
+    },
+    /// Pot of the open round of a pool, per denom.
+    PotTotals {
+        pool_id: PoolId,
+    },
+    /// House money added to the open round of a pool, per denom.
+    PotBonus {
+        pool_id: PoolId,
+    },
+    WithdrawalPenalty {
+        pool_id: PoolId,
+    },
+    OracleConfig {},
+    CloseTime {
+        pool_id: PoolId,
+    },
+    /// Ticket numbers `address` holds in the current round of a pool.
+    Tickets {
+        pool_id: PoolId,
+        address: Addr,
+    },
+    NftConfig {},
+    /// Prize waiting for the holder of a winning ticket NFT.
+    NftPrize {
+        token_id: String,
+    },
+    AllowlistConfig {},
+    /// Whether `address` may bet under the current allowlist config.
+    IsAllowed {
+        address: Addr,
+    },
+    /// Contracts notified of bets and settled rounds.
+    Hooks {},
+    LoyaltyConfig {},
+    /// Loyalty points, tier multiplier and free-bet credits of `address`.
+    Loyalty {
+        address: Addr,
+    },
+    /// A settled round. `pool_id` is a pool's id or `"staking"`.
+    Round {
+        pool_id: String,
+        round_id: u64,
+    },
+    /// Settled rounds of a pool, ordered by round id.
+    RoundHistory {
+        pool_id: String,
+        start_after: Option<u64>,
+        limit: Option<u32>,
+    },
+    /// Bounced payouts `address` can claim.
+    Escrow {
+        address: Addr,
+    },
+    /// Wager limits, pending raises and self-exclusion of `address`.
+    PlayerLimits {
+        address: Addr,
+    },
+    /// Previews the odds and payout if `address` bet `amount` more in a pool
+    /// right now, in `denom`. Multi-denom pools need the denom, the others
+    /// default to the round's.
+    SimulateBet {
+        pool_id: PoolId,
+        address: Addr,
+        amount: u128,
+        #[serde(default)]
+        denom: Option<String>,
+    },
+    /// Contract balance against liabilities, for every denom it holds or
+    /// owes.
+    Solvency {},
+    /// Owner, house fee and whether the contract is paused.
+    HouseSettings {},
 }
 
+/// Messages of the chain's governance module, run through the same code as
+/// the owner's `ExecuteMsg` of the same name.
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
-pub enum QueryMsg {}
+pub enum SudoMsg {
+    Pause {},
+    Unpause {},
+    SetHouseFee { fee_bps: u64 },
+    TransferOwnership { new_owner: Addr },
+    CancelRound { pool_id: PoolId },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct LoyaltyResponse {
+    pub points: u128,
+    pub lifetime_points: u128,
+    pub multiplier: Decimal,
+    pub credits: Vec<Coin>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct HouseSettingsResponse {
+    pub owner: Addr,
+    pub house_fee_bps: u64,
+    pub paused: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct SimulateBetResponse {
+    /// Total stake of the address in the round in the simulated bet's denom,
+    /// including the simulated bet.
+    pub stake: u128,
+    /// Pot of the simulated bet's denom, which the prize figures below
+    /// are taken from.
+    pub pot_total: u128,
+    /// Chance to win the draw, in basis points.
+    pub win_probability: u128,
+    /// The whole pot and the pot bonus, before the house fee.
+    pub gross_prize: u128,
+    pub fee: u128,
+    pub net_payout: u128,
+    /// Net prizes of the pots in other denoms, won alongside.
+    pub other_prizes: Vec<Coin>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PoolConfigResponse {
+    pub max_bet: Option<u128>,
+    pub ticket_config: Option<TicketConfig>,
+    pub closes_at: Option<u64>,
+    pub withdrawal_penalty_bps: u64,
+    /// Accepted denoms and their weights, if the pool takes several.
+    pub denoms: Option<Vec<DenomRate>>,
+    pub randomness: RandomnessMode,
+    /// Who can predict or influence the draw in this mode.
+    pub randomness_guarantees: String,
+}
+
+/// A withdrawal from the staking pool. `release_at` is unset until its
+/// batch has been undelegated.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct UnbondingResponse {
+    pub amount: u128,
+    pub batch: u64,
+    pub release_at: Option<u64>,
+}
+
+/// Balance against liabilities in one denom. At most one of `surplus` and
+/// `deficit` is non-zero. `surplus` is what can be swept; `deficit` counts
+/// the `unbonding` stakes still on their way back as held.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct SolvencyResponse {
+    pub denom: String,
+    pub balance: u128,
+    pub unbonding: u128,
+    pub liabilities: u128,
+    pub surplus: u128,
+    pub deficit: u128,
+}
@@ -0,0 +1,305 @@
+//! Optional CW721 ticket NFTs.
+//! When a collection is configured, every pool bet mints a ticket NFT to the
+//! bettor. The contract only needs the `mint` and `owner_of` messages of
+//! [cw721-base], which are mirrored here.
+//!
+//! [cw721-base]: https://github.com/CosmWasm/cw-nfts/tree/main/contracts/cw721-base
+
+use cosmwasm_std::{
+    to_json_binary, Addr, BankMsg, Coin, CosmosMsg, DepsMut, Env, MessageInfo, QueryRequest,
+    Response, StdResult, WasmMsg, WasmQuery,
+};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::msg::{NftConfig, PoolId};
+use crate::query::query_config;
+use crate::solvency;
+use crate::state::{NFT_CONFIG, NFT_PRIZES};
+use crate::ContractError;
+
+/// Extension stored on every ticket NFT.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct TicketMetadata {
+    pub pool_id: PoolId,
+    pub round_id: u64,
+    pub stake: Coin,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Cw721ExecuteMsg {
+    Mint {
+        token_id: String,
+        owner: String,
+        token_uri: Option<String>,
+        extension: TicketMetadata,
+    },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Cw721QueryMsg {
+    OwnerOf {
+        token_id: String,
+        include_expired: Option<bool>,
+    },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct OwnerOfResponse {
+    pub owner: String,
+}
+
+/// Token ids are unique per pool, round and mint order, e.g. `whale-3-12`.
+pub fn ticket_token_id(metadata: &TicketMetadata, number: u64) -> String {
+    format!("{}-{}-{}", metadata.pool_id, metadata.round_id, number)
+}
+
+pub fn mint_ticket_msg(
+    nft_config: &NftConfig,
+    token_id: &str,
+    owner: &Addr,
+    metadata: TicketMetadata,
+) -> StdResult<CosmosMsg> {
+    let mint = Cw721ExecuteMsg::Mint {
+        token_id: token_id.to_string(),
+        owner: owner.to_string(),
+        token_uri: None,
+        extension: metadata,
+    };
+    Ok(CosmosMsg::Wasm(WasmMsg::Execute {
+        contract_addr: nft_config.collection.to_string(),
+        msg: to_json_binary(&mint)?,
+        funds: vec![],
+    }))
+}
+
+pub fn set_nft_config(
+    deps: DepsMut,
+    _env: Env,
+    info: MessageInfo,
+    nft_config: Option<NftConfig>,
+) -> Result<Response, ContractError> {
+    let config = query_config(deps.as_ref())?;
+    if info.sender != config.fee_address {
+        return Err(ContractError::Unauthorized);
+    }
+    // Open rounds keep the collection their tickets were minted in.
+    for &pool in &PoolId::ALL {
+        if !config.user_list(pool).is_empty() {
+            return Err(ContractError::RoundInProgress { pool });
+        }
+    }
+
+    match &nft_config {
+        Some(nft_config) => {
+            deps.api.addr_validate(nft_config.collection.as_str())?;
+            NFT_CONFIG.save(deps.storage, nft_config)?;
+        }
+        None => NFT_CONFIG.remove(deps.storage),
+    }
+
+    Ok(Response::new()
+        .add_attribute("action", "set_nft_config")
+        .add_attribute(
+            "collection",
+            nft_config.map_or(String::new(), |c| c.collection.to_string()),
+        ))
+}
+
+/// Pays the prize held for `token_id` to the sender, who must currently
+/// hold that ticket in the collection it was minted in.
+pub fn claim_nft_prize(
+    deps: DepsMut,
+    _env: Env,
+    info: MessageInfo,
+    token_id: String,
+) -> Result<Response, ContractError> {
+    let nft_prize =
+        NFT_PRIZES
+            .may_load(deps.storage, &token_id)?
+            .ok_or(ContractError::NoNftPrize {
+                token_id: token_id.clone(),
+            })?;
+
+    let owner: OwnerOfResponse = deps.querier.query(&QueryRequest::Wasm(WasmQuery::Smart {
+        contract_addr: nft_prize.collection.to_string(),
+        msg: to_json_binary(&Cw721QueryMsg::OwnerOf {
+            token_id: token_id.clone(),
+            include_expired: None,
+        })?,
+    }))?;
+    if owner.owner != info.sender {
+        return Err(ContractError::Unauthorized);
+    }
+
+    NFT_PRIZES.remove(deps.storage, &token_id);
+    solvency::discharge_coins(deps.storage, &nft_prize.prize)?;
+
+    let transfer_prize = BankMsg::Send {
+        to_address: info.sender.to_string(),
+        amount: nft_prize.prize,
+    };
+
+    Ok(Response::new()
+        .add_message(CosmosMsg::Bank(transfer_prize))
+        .add_attribute("action", "claim_nft_prize")
+        .add_attribute("token_id", token_id)
+        .add_attribute("winner", info.sender.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::contract::{execute, instantiate};
+    use crate::msg::{ExecuteMsg, InstantiateMsg};
+    use crate::state::NftPrize;
+    use cosmwasm_std::testing::{mock_dependencies_with_balance, mock_env, mock_info};
+    use cosmwasm_std::{coin, coins, from_json, ContractResult, SystemResult};
+
+    const DENOM: &str = "usei";
+
+    #[test]
+    fn test_winning_ticket_is_claimed_by_holder() {
+        let mut deps = mock_dependencies_with_balance(&coins(10_000, DENOM));
+        deps.querier.update_wasm(|query| match query {
+            WasmQuery::Smart { contract_addr, msg } => {
+                assert_eq!("collection", contract_addr);
+                let Cw721QueryMsg::OwnerOf { token_id, .. } = from_json(msg).unwrap();
+                assert_eq!("infinite-1-1", token_id);
+                let owner = OwnerOfResponse {
+                    owner: String::from("ticket_buyer"),
+                };
+                SystemResult::Ok(ContractResult::Ok(to_json_binary(&owner).unwrap()))
+            }
+            _ => panic!("unexpected query"),
+        });
+
+        let admin = mock_info("fee_address", &[]);
+        let msg = InstantiateMsg {
+            fee_address: Addr::unchecked("fee_address"),
+            start_flag: true,
+        };
+        instantiate(deps.as_mut(), mock_env(), admin.clone(), msg).unwrap();
+        let nft_config = NftConfig {
+            collection: Addr::unchecked("collection"),
+            claim_by_holder: true,
+        };
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            admin.clone(),
+            ExecuteMsg::SetNftConfig {
+                nft_config: Some(nft_config.clone()),
+            },
+        )
+        .unwrap();
+
+        let res = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("bettor", &coins(100, DENOM)),
+            ExecuteMsg::AddBetUserInfinite { client_seed: None },
+        )
+        .unwrap();
+        let metadata = TicketMetadata {
+            pool_id: PoolId::Infinite,
+            round_id: 1,
+            stake: coin(100, DENOM),
+        };
+        assert_eq!(
+            res.messages[0].msg,
+            mint_ticket_msg(
+                &nft_config,
+                "infinite-1-1",
+                &Addr::unchecked("bettor"),
+                metadata
+            )
+            .unwrap()
+        );
+        // The collection stays until the round is over.
+        let err = execute(
+            deps.as_mut(),
+            mock_env(),
+            admin.clone(),
+            ExecuteMsg::SetNftConfig { nft_config: None },
+        )
+        .unwrap_err();
+        assert!(matches!(
+            err,
+            ContractError::RoundInProgress {
+                pool: PoolId::Infinite
+            }
+        ));
+
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            admin.clone(),
+            ExecuteMsg::ToggleGameInfinte {},
+        )
+        .unwrap();
+        let res = execute(
+            deps.as_mut(),
+            mock_env(),
+            admin.clone(),
+            ExecuteMsg::EndGameInfinte {
+                seed: 1231231809300,
+            },
+        )
+        .unwrap();
+        // Only the fee is paid out, the prize waits for the ticket holder.
+        assert_eq!(1, res.messages.len());
+        assert_eq!(
+            Some(NftPrize {
+                collection: Addr::unchecked("collection"),
+                prize: coins(96, DENOM),
+            }),
+            NFT_PRIZES.may_load(&deps.storage, "infinite-1-1").unwrap()
+        );
+
+        // The prize is still claimed in the collection the ticket is from.
+        let nft_config = NftConfig {
+            collection: Addr::unchecked("new_collection"),
+            claim_by_holder: true,
+        };
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            admin,
+            ExecuteMsg::SetNftConfig {
+                nft_config: Some(nft_config),
+            },
+        )
+        .unwrap();
+
+        let claim = ExecuteMsg::ClaimNftPrize {
+            token_id: String::from("infinite-1-1"),
+        };
+        let err = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("bettor", &[]),
+            claim.clone(),
+        )
+        .unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized));
+
+        let res = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("ticket_buyer", &[]),
+            claim,
+        )
+        .unwrap();
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: String::from("ticket_buyer"),
+                amount: coins(96, DENOM),
+            })
+        );
+        crate::solvency::assert_tracked(deps.as_ref());
+    }
+}
@@ -0,0 +1,264 @@
+//! Optional USD price oracle for multi-denom pools.
+//! With an oracle configured, every bet in a pool with accepted denoms is
+//! valued in USD when it is placed, and the draw weighs bets by that value
+//! instead of the admin-set rates. The oracle is queried with:
+//!
+//! ```json
+//! { "price": { "denom": "usei" } }
+//! ```
+//!
+//! and must answer `{ "price": "0.25", "updated_at": 1700000000 }`, the USD
+//! price of one unit and the time it was last updated in seconds. A bet
+//! fails if the price is missing, zero or older than `max_price_age`.
+
+use cosmwasm_std::{
+    to_json_binary, Coin, Decimal, Deps, DepsMut, Env, MessageInfo, QueryRequest, Response,
+    StdError, WasmQuery,
+};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::msg::{OracleConfig, PoolId};
+use crate::query::query_config;
+use crate::state::{ORACLE_CONFIG, POOL_DENOMS};
+use crate::ContractError;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum OracleQueryMsg {
+    Price { denom: String },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PriceResponse {
+    pub price: Decimal,
+    pub updated_at: u64,
+}
+
+pub fn set_oracle_config(
+    deps: DepsMut,
+    _env: Env,
+    info: MessageInfo,
+    oracle_config: Option<OracleConfig>,
+) -> Result<Response, ContractError> {
+    let config = query_config(deps.as_ref())?;
+    if info.sender != config.fee_address {
+        return Err(ContractError::Unauthorized);
+    }
+    // Bets of a round must all be weighed the same way.
+    for &pool in &PoolId::ALL {
+        if POOL_DENOMS.has(deps.storage, pool.as_str()) && !config.user_list(pool).is_empty() {
+            return Err(ContractError::RoundInProgress { pool });
+        }
+    }
+
+    match &oracle_config {
+        Some(oracle_config) => {
+            deps.api.addr_validate(oracle_config.contract.as_str())?;
+            ORACLE_CONFIG.save(deps.storage, oracle_config)?;
+        }
+        None => ORACLE_CONFIG.remove(deps.storage),
+    }
+
+    Ok(Response::new()
+        .add_attribute("action", "set_oracle_config")
+        .add_attribute("oracle", oracle_config.is_some().to_string()))
+}
+
+/// USD value of `stake` in the oracle's price units, at the full precision
+/// of the price, or `None` without an oracle.
+pub(crate) fn normalized_value(
+    deps: Deps,
+    env: &Env,
+    stake: &Coin,
+) -> Result<Option<Decimal>, ContractError> {
+    let oracle_config = match ORACLE_CONFIG.may_load(deps.storage)? {
+        Some(oracle_config) => oracle_config,
+        None => return Ok(None),
+    };
+    let missing_price = || ContractError::MissingPrice {
+        denom: stake.denom.clone(),
+    };
+
+    let res: PriceResponse = deps
+        .querier
+        .query(&QueryRequest::Wasm(WasmQuery::Smart {
+            contract_addr: oracle_config.contract.to_string(),
+            msg: to_json_binary(&OracleQueryMsg::Price {
+                denom: stake.denom.clone(),
+            })?,
+        }))
+        .map_err(|_| missing_price())?;
+    if res.price.is_zero() {
+        return Err(missing_price());
+    }
+    // A price fresh until after the end of time is not stale.
+    let fresh_until = res.updated_at.checked_add(oracle_config.max_price_age);
+    if matches!(fresh_until, Some(fresh_until) if fresh_until < env.block.time.seconds()) {
+        return Err(ContractError::StalePrice {
+            denom: stake.denom.clone(),
+            updated_at: res.updated_at,
+        });
+    }
+
+    // Amount times price, keeping the price's decimals.
+    let value = stake
+        .amount
+        .checked_mul(res.price.atomics())
+        .map_err(StdError::from)?;
+    Ok(Some(Decimal::new(value)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::contract::{execute, instantiate};
+    use crate::denoms::weighted_bets;
+    use crate::msg::{DenomRate, ExecuteMsg, InstantiateMsg};
+    use crate::query::query_simulate_bet;
+    use crate::state::CONFIG;
+    use cosmwasm_std::testing::{mock_dependencies_with_balance, mock_env, mock_info};
+    use cosmwasm_std::{coin, coins, from_json, Addr, ContractResult, SystemResult};
+
+    #[test]
+    fn test_oracle_weighted_bets() {
+        let mut deps = mock_dependencies_with_balance(&[
+            coin(10_000, "usei"),
+            coin(10_000, "uatom"),
+            coin(10_000, "ujuno"),
+        ]);
+        let now = mock_env().block.time.seconds();
+        // Mock oracle: usei at $0.5, uatom at $10 but last updated an hour
+        // ago, ujuno at $2 a whole token, and no price for anything else.
+        deps.querier.update_wasm(move |query| match query {
+            WasmQuery::Smart { contract_addr, msg } => {
+                assert_eq!("oracle", contract_addr);
+                let OracleQueryMsg::Price { denom } = from_json(msg).unwrap();
+                let res = match denom.as_str() {
+                    "usei" => PriceResponse {
+                        price: Decimal::percent(50),
+                        updated_at: now,
+                    },
+                    "uatom" => PriceResponse {
+                        price: Decimal::percent(1000),
+                        updated_at: now - 3600,
+                    },
+                    "ujuno" => PriceResponse {
+                        price: Decimal::from_ratio(2u128, 1_000_000u128),
+                        updated_at: now,
+                    },
+                    _ => return SystemResult::Ok(ContractResult::Err("no price".to_string())),
+                };
+                SystemResult::Ok(ContractResult::Ok(to_json_binary(&res).unwrap()))
+            }
+            _ => panic!("unexpected query"),
+        });
+
+        let admin = mock_info("fee_address", &[]);
+        let msg = InstantiateMsg {
+            fee_address: Addr::unchecked("fee_address"),
+            start_flag: true,
+        };
+        instantiate(deps.as_mut(), mock_env(), admin.clone(), msg).unwrap();
+        let denoms = ["usei", "uatom", "ujuno", "uosmo"]
+            .iter()
+            .map(|denom| DenomRate {
+                denom: denom.to_string(),
+                rate: Decimal::one(),
+            })
+            .collect();
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            admin.clone(),
+            ExecuteMsg::SetPoolDenoms {
+                pool_id: PoolId::Infinite,
+                denoms: Some(denoms),
+            },
+        )
+        .unwrap();
+        let oracle_config = OracleConfig {
+            contract: Addr::unchecked("oracle"),
+            max_price_age: 600,
+        };
+        set_oracle_config(
+            deps.as_mut(),
+            mock_env(),
+            admin.clone(),
+            Some(oracle_config.clone()),
+        )
+        .unwrap();
+
+        let bet = |deps: DepsMut, amount: u128, denom: &str| {
+            execute(
+                deps,
+                mock_env(),
+                mock_info("alice", &coins(amount, denom)),
+                ExecuteMsg::AddBetUserInfinite { client_seed: None },
+            )
+        };
+        bet(deps.as_mut(), 100, "usei").unwrap();
+        bet(deps.as_mut(), 3, "ujuno").unwrap();
+        let err = bet(deps.as_mut(), 10, "uatom").unwrap_err();
+        assert!(matches!(err, ContractError::StalePrice { .. }));
+        let err = bet(deps.as_mut(), 10, "uosmo").unwrap_err();
+        assert!(matches!(err, ContractError::MissingPrice { .. }));
+
+        let config = CONFIG.load(&deps.storage).unwrap();
+        let stored = &config.user_list(PoolId::Infinite)[0];
+        assert_eq!(100, stored.user_amount);
+        assert_eq!(Some(Decimal::percent(5000)), stored.normalized_value);
+        // The draw weighs the bet at its USD value, not its rate.
+        // Three micro-units of a token priced per whole token are worth a
+        // fraction of a price unit, and keep it.
+        let micro_value = Decimal::from_ratio(6u128, 1_000_000u128);
+        assert_eq!(
+            Some(micro_value),
+            config.user_list(PoolId::Infinite)[1].normalized_value
+        );
+        let weighted = weighted_bets(config.user_list(PoolId::Infinite), &[]).unwrap();
+        assert_eq!(
+            Decimal::percent(5000).atomics().u128(),
+            weighted[0].user_amount
+        );
+        assert_eq!(micro_value.atomics().u128(), weighted[1].user_amount);
+        // A simulated bet is priced the same way.
+        let err = query_simulate_bet(
+            deps.as_ref(),
+            &mock_env(),
+            PoolId::Infinite,
+            Addr::unchecked("bob"),
+            10,
+            Some("uatom".to_string()),
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("uatom"));
+
+        // The weighing can't change under a running round.
+        let err =
+            set_oracle_config(deps.as_mut(), mock_env(), admin, Some(oracle_config)).unwrap_err();
+        assert!(matches!(err, ContractError::RoundInProgress { .. }));
+    }
+
+    #[test]
+    fn test_price_age_does_not_overflow() {
+        let mut deps = mock_dependencies_with_balance(&[]);
+        deps.querier.update_wasm(|_| {
+            let res = PriceResponse {
+                price: Decimal::one(),
+                updated_at: u64::MAX - 1,
+            };
+            SystemResult::Ok(ContractResult::Ok(to_json_binary(&res).unwrap()))
+        });
+        let oracle_config = OracleConfig {
+            contract: Addr::unchecked("oracle"),
+            max_price_age: u64::MAX,
+        };
+        ORACLE_CONFIG
+            .save(&mut deps.storage, &oracle_config)
+            .unwrap();
+
+        let value = normalized_value(deps.as_ref(), &mock_env(), &coin(10, "usei")).unwrap();
+        assert_eq!(Some(Decimal::percent(1000)), value);
+    }
+}
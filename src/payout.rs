@@ -0,0 +1,228 @@
+//! Draw payouts with an escrow fallback.
+//! Fees and prizes are sent as submessages that always reply. A payout that
+//! can't be delivered stays in the contract as an escrow balance the
+//! recipient can claim later, and the draw itself goes through.
+
+use cosmwasm_std::{
+    Addr, BankMsg, Coin, DepsMut, Env, MessageInfo, Reply, Response, StdError, StdResult, Storage,
+    SubMsg, SubMsgResult,
+};
+
+use crate::solvency;
+use crate::state::{PendingPayout, ESCROW, PENDING_PAYOUTS, ROUND_HISTORY};
+use crate::ContractError;
+
+/// Reply ids of the two payouts of a draw. Submessages run depth first, so
+/// a draw's payouts have replied before the next draw reuses the ids. Every
+/// id from `FEE_REPLY_ID` up is a payout.
+pub const FEE_REPLY_ID: u64 = 100;
+pub const PRIZE_REPLY_ID: u64 = 101;
+
+/// Fee and prize reply ids of the `index`th denom paid out by a draw.
+pub(crate) fn reply_ids(index: usize) -> (u64, u64) {
+    let offset = 2 * index as u64;
+    (FEE_REPLY_ID + offset, PRIZE_REPLY_ID + offset)
+}
+
+pub(crate) fn is_payout_reply(reply_id: u64) -> bool {
+    reply_id >= FEE_REPLY_ID
+}
+
+/// Sends `amount` to `recipient`, or nothing for a zero amount. The payout
+/// is pending until its reply.
+pub(crate) fn payout_msg(
+    storage: &mut dyn Storage,
+    reply_id: u64,
+    recipient: &Addr,
+    amount: Coin,
+    pool_id: &str,
+    round_id: u64,
+) -> StdResult<Option<SubMsg>> {
+    if amount.amount.is_zero() {
+        return Ok(None);
+    }
+    let send = BankMsg::Send {
+        to_address: recipient.to_string(),
+        amount: vec![amount.clone()],
+    };
+    PENDING_PAYOUTS.save(
+        storage,
+        reply_id,
+        &PendingPayout {
+            recipient: recipient.clone(),
+            amount,
+            pool_id: pool_id.to_string(),
+            round_id,
+        },
+    )?;
+    Ok(Some(SubMsg::reply_always(send, reply_id)))
+}
+
+/// Clears a payout that has run. One that bounced is moved into the
+/// recipient's escrow and noted in the round history.
+pub(crate) fn payout_replied(deps: DepsMut, msg: Reply) -> Result<Response, ContractError> {
+    let reply_id = msg.id;
+    let payout = PENDING_PAYOUTS
+        .may_load(deps.storage, reply_id)?
+        .ok_or_else(|| StdError::generic_err(format!("No payout for reply id {}", reply_id)))?;
+    PENDING_PAYOUTS.remove(deps.storage, reply_id);
+    let error = match msg.result {
+        SubMsgResult::Err(error) => error,
+        SubMsgResult::Ok(_) => return Ok(Response::new()),
+    };
+
+    ESCROW.update(deps.storage, &payout.recipient, |balance| -> StdResult<_> {
+        let mut balance = balance.unwrap_or_default();
+        match balance
+            .iter_mut()
+            .find(|coin| coin.denom == payout.amount.denom)
+        {
+            Some(coin) => coin.amount += payout.amount.amount,
+            None => balance.push(payout.amount.clone()),
+        }
+        Ok(balance)
+    })?;
+    solvency::owe(
+        deps.storage,
+        &payout.amount.denom,
+        payout.amount.amount.u128(),
+    )?;
+    let key = (payout.pool_id.as_str(), payout.round_id);
+    if let Some(mut record) = ROUND_HISTORY.may_load(deps.storage, key)? {
+        record.failed_payouts.push(payout.recipient.clone());
+        ROUND_HISTORY.save(deps.storage, key, &record)?;
+    }
+
+    Ok(Response::new()
+        .add_attribute("action", "escrow_payout")
+        .add_attribute("recipient", payout.recipient.to_string())
+        .add_attribute("amount", payout.amount.to_string())
+        .add_attribute("error", error))
+}
+
+pub fn claim_escrow(
+    deps: DepsMut,
+    _env: Env,
+    info: MessageInfo,
+) -> Result<Response, ContractError> {
+    let balance = ESCROW
+        .may_load(deps.storage, &info.sender)?
+        .unwrap_or_default();
+    if balance.is_empty() {
+        return Err(ContractError::NothingToClaim);
+    }
+    ESCROW.remove(deps.storage, &info.sender);
+    solvency::discharge_coins(deps.storage, &balance)?;
+
+    Ok(Response::new()
+        .add_message(BankMsg::Send {
+            to_address: info.sender.to_string(),
+            amount: balance,
+        })
+        .add_attribute("action", "claim_escrow")
+        .add_attribute("recipient", info.sender.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::contract::{execute, instantiate, reply};
+    use crate::msg::{ExecuteMsg, InstantiateMsg};
+    use cosmwasm_std::testing::{mock_dependencies_with_balance, mock_env, mock_info};
+    use cosmwasm_std::{coin, coins, CosmosMsg, SubMsgResponse};
+
+    const DENOM: &str = "usei";
+
+    #[test]
+    fn test_bounced_prize_is_escrowed() {
+        let mut deps = mock_dependencies_with_balance(&coins(10_000, DENOM));
+        let admin = mock_info("fee_address", &[]);
+        let msg = InstantiateMsg {
+            fee_address: Addr::unchecked("fee_address"),
+            start_flag: true,
+        };
+        instantiate(deps.as_mut(), mock_env(), admin.clone(), msg).unwrap();
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("winner", &coins(100, DENOM)),
+            ExecuteMsg::AddBetUserInfinite { client_seed: None },
+        )
+        .unwrap();
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            admin.clone(),
+            ExecuteMsg::ToggleGameInfinte {},
+        )
+        .unwrap();
+        let res = execute(
+            deps.as_mut(),
+            mock_env(),
+            admin,
+            ExecuteMsg::EndGameInfinte { seed: 1 },
+        )
+        .unwrap();
+        assert_eq!(
+            res.messages,
+            vec![
+                SubMsg::reply_always(
+                    BankMsg::Send {
+                        to_address: String::from("fee_address"),
+                        amount: coins(4, DENOM),
+                    },
+                    FEE_REPLY_ID
+                ),
+                SubMsg::reply_always(
+                    BankMsg::Send {
+                        to_address: String::from("winner"),
+                        amount: coins(96, DENOM),
+                    },
+                    PRIZE_REPLY_ID
+                ),
+            ]
+        );
+
+        // The fee went through and is no longer pending.
+        let delivered = Reply {
+            id: FEE_REPLY_ID,
+            result: SubMsgResult::Ok(SubMsgResponse {
+                events: vec![],
+                data: None,
+            }),
+        };
+        reply(deps.as_mut(), mock_env(), delivered).unwrap();
+        assert!(!PENDING_PAYOUTS.has(&deps.storage, FEE_REPLY_ID));
+        assert!(ESCROW
+            .may_load(&deps.storage, &Addr::unchecked("fee_address"))
+            .unwrap()
+            .is_none());
+
+        let bounced = Reply {
+            id: PRIZE_REPLY_ID,
+            result: SubMsgResult::Err(String::from("blocked address")),
+        };
+        reply(deps.as_mut(), mock_env(), bounced).unwrap();
+        assert!(!PENDING_PAYOUTS.has(&deps.storage, PRIZE_REPLY_ID));
+        assert_eq!(
+            vec![coin(96, DENOM)],
+            ESCROW
+                .load(&deps.storage, &Addr::unchecked("winner"))
+                .unwrap()
+        );
+        let record = ROUND_HISTORY.load(&deps.storage, ("infinite", 1)).unwrap();
+        assert_eq!(vec![Addr::unchecked("winner")], record.failed_payouts);
+
+        let res = claim_escrow(deps.as_mut(), mock_env(), mock_info("winner", &[])).unwrap();
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: String::from("winner"),
+                amount: coins(96, DENOM),
+            })
+        );
+        let err = claim_escrow(deps.as_mut(), mock_env(), mock_info("winner", &[])).unwrap_err();
+        assert!(matches!(err, ContractError::NothingToClaim));
+        crate::solvency::assert_tracked(deps.as_ref());
+    }
+}
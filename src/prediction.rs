@@ -0,0 +1,485 @@
+//! Parimutuel prediction markets.
+//! Bettors stake on one of the outcomes of a market. Once the resolver posts
+//! the result and the dispute window has passed, the stakes on the winning
+//! outcome are paid back, and the losing stakes minus the house fee are
+//! split pro-rata among them. Only the winnings pay the fee.
+
+use cosmwasm_std::{
+    coin, Addr, BankMsg, CosmosMsg, DepsMut, Env, MessageInfo, Response, StdResult, Uint128, Uint64,
+};
+
+use crate::allowlist;
+use crate::contract::{one_coin, split_pot};
+use crate::events;
+use crate::governance;
+use crate::limits;
+use crate::query::query_config;
+use crate::solvency;
+use crate::state::{Market, MarketStatus, MARKETS, MARKET_COUNT, MARKET_POSITIONS};
+use crate::ContractError;
+
+#[allow(clippy::too_many_arguments)]
+pub fn create_market(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    question: String,
+    outcomes: Vec<String>,
+    resolver: Addr,
+    denom: String,
+    closes_at: u64,
+    dispute_window: u64,
+) -> Result<Response, ContractError> {
+    let config = query_config(deps.as_ref())?;
+    if info.sender != config.fee_address {
+        return Err(ContractError::Unauthorized);
+    }
+    if outcomes.len() < 2 {
+        return Err(ContractError::TooFewOutcomes);
+    }
+    if closes_at <= env.block.time.seconds() {
+        return Err(ContractError::InvalidCloseTime);
+    }
+
+    let id = MARKET_COUNT.may_load(deps.storage)?.unwrap_or_default() + 1;
+    MARKET_COUNT.save(deps.storage, &id)?;
+
+    let market = Market {
+        id,
+        question,
+        outcome_totals: vec![0; outcomes.len()],
+        outcomes,
+        resolver: deps.api.addr_validate(resolver.as_str())?,
+        coin_type: denom,
+        closes_at,
+        dispute_window,
+        pot_total: 0,
+        status: MarketStatus::Open,
+        winning_outcome: None,
+        resolved_at: 0,
+        paid_out: 0,
+        fee_bps: governance::house_fee_bps(deps.storage)?,
+        claimed_stake: 0,
+    };
+    MARKETS.save(deps.storage, id, &market)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "create_market")
+        .add_attribute("market_id", id.to_string()))
+}
+
+pub fn place_market_bet(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    market_id: u64,
+    outcome: u32,
+) -> Result<Response, ContractError> {
+    let mut market = load_market(&deps, market_id)?;
+
+    if market.status != MarketStatus::Open || env.block.time.seconds() >= market.closes_at {
+        return Err(ContractError::MarketClosed { market_id });
+    }
+    let index = outcome as usize;
+    if index >= market.outcomes.len() {
+        return Err(ContractError::InvalidOutcome { market_id, outcome });
+    }
+
+    allowlist::assert_allowed(deps.as_ref(), &info.sender)?;
+    let stake = one_coin(&info)?;
+    if stake.denom != market.coin_type {
+        return Err(ContractError::InvalidDenom {
+            expected: market.coin_type,
+            received: stake.denom,
+        });
+    }
+    let funds_amount = stake.amount.u128();
+    limits::record_wager(
+        deps.storage,
+        env.block.time.seconds(),
+        &info.sender,
+        funds_amount,
+    )?;
+
+    let key = (market_id, &info.sender);
+    let mut stakes = MARKET_POSITIONS
+        .may_load(deps.storage, key)?
+        .unwrap_or_else(|| vec![0; market.outcomes.len()]);
+    stakes[index] += funds_amount;
+    MARKET_POSITIONS.save(deps.storage, key, &stakes)?;
+
+    market.outcome_totals[index] += funds_amount;
+    market.pot_total += funds_amount;
+    MARKETS.save(deps.storage, market_id, &market)?;
+    solvency::owe(deps.storage, &market.coin_type, funds_amount)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "bet_market")
+        .add_attribute("market_id", market_id.to_string())
+        .add_attribute("outcome", outcome.to_string())
+        .add_attribute("bet_user_address", info.sender.to_string())
+        .add_attribute("bet_user_funds", funds_amount.to_string()))
+}
+
+pub fn resolve_market(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    market_id: u64,
+    outcome: Option<u32>,
+) -> Result<Response, ContractError> {
+    let mut market = load_market(&deps, market_id)?;
+
+    if info.sender != market.resolver {
+        return Err(ContractError::Unauthorized);
+    }
+    if market.status != MarketStatus::Open {
+        return Err(ContractError::MarketResolved { market_id });
+    }
+    if env.block.time.seconds() < market.closes_at {
+        return Err(ContractError::MarketStillOpen { market_id });
+    }
+
+    market.status = match outcome {
+        Some(outcome) if outcome as usize >= market.outcomes.len() => {
+            return Err(ContractError::InvalidOutcome { market_id, outcome })
+        }
+        Some(_) => MarketStatus::Resolved,
+        None => MarketStatus::Invalid,
+    };
+    market.winning_outcome = outcome;
+    market.resolved_at = env.block.time.seconds();
+    MARKETS.save(deps.storage, market_id, &market)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "resolve_market")
+        .add_attribute("market_id", market_id.to_string())
+        .add_attribute(
+            "outcome",
+            outcome.map_or(String::from("invalid"), |o| o.to_string()),
+        ))
+}
+
+/// Withdraws a posted result during its dispute window so the resolver can
+/// post it again.
+pub fn dispute_market(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    market_id: u64,
+) -> Result<Response, ContractError> {
+    let config = query_config(deps.as_ref())?;
+    if info.sender != config.fee_address {
+        return Err(ContractError::Unauthorized);
+    }
+    let mut market = load_market(&deps, market_id)?;
+
+    if market.status == MarketStatus::Open {
+        return Err(ContractError::MarketNotResolved { market_id });
+    }
+    if env.block.time.seconds() >= dispute_ends_at(&market)? {
+        return Err(ContractError::DisputeWindowPassed { market_id });
+    }
+
+    market.status = MarketStatus::Open;
+    market.winning_outcome = None;
+    market.resolved_at = 0;
+    MARKETS.save(deps.storage, market_id, &market)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "dispute_market")
+        .add_attribute("market_id", market_id.to_string()))
+}
+
+/// Pays out the sender's position once the result is final. Invalid markets
+/// and markets where nobody backed the winning outcome refund the stakes.
+pub fn claim_market(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    market_id: u64,
+) -> Result<Response, ContractError> {
+    let config = query_config(deps.as_ref())?;
+    let mut market = load_market(&deps, market_id)?;
+
+    if market.status == MarketStatus::Open || env.block.time.seconds() < dispute_ends_at(&market)? {
+        return Err(ContractError::MarketNotFinal { market_id });
+    }
+
+    let key = (market_id, &info.sender);
+    let stakes = MARKET_POSITIONS
+        .may_load(deps.storage, key)?
+        .unwrap_or_default();
+    MARKET_POSITIONS.remove(deps.storage, key);
+
+    let (winning_stake, winning_total) = match market.winning_outcome {
+        Some(o) => (
+            stakes.get(o as usize).copied().unwrap_or_default(),
+            market.outcome_totals[o as usize],
+        ),
+        None => (0, 0),
+    };
+
+    let mut messages = vec![];
+    let refunded = winning_total == 0;
+    let (payout, paid_out) = if refunded {
+        let stake = stakes.iter().sum();
+        (stake, stake)
+    } else {
+        // The claimant's share of the losing stakes, which alone pays the fee.
+        let winnings = Uint128::new(winning_stake)
+            .multiply_ratio(market.pot_total - winning_total, winning_total)
+            .u128();
+        let (fees, prize) = split_pot(winnings, market.fee_bps);
+        market.claimed_stake += winning_stake;
+        // The last winner to claim releases the rounding dust to the house,
+        // leaving nothing owed on the market.
+        let dust = if market.claimed_stake == winning_total {
+            market.pot_total - market.paid_out - winning_stake - winnings
+        } else {
+            0
+        };
+        if fees + dust > 0 {
+            messages.push(CosmosMsg::Bank(BankMsg::Send {
+                to_address: config.fee_address.to_string(),
+                amount: vec![coin(fees + dust, market.coin_type.clone())],
+            }));
+        }
+        (winning_stake + prize, winning_stake + winnings + dust)
+    };
+    if payout == 0 {
+        return Err(ContractError::NothingToClaim);
+    }
+    market.paid_out += paid_out;
+    MARKETS.save(deps.storage, market_id, &market)?;
+    solvency::discharge(deps.storage, &market.coin_type, paid_out)?;
+    let payout = coin(payout, market.coin_type);
+    messages.push(CosmosMsg::Bank(BankMsg::Send {
+        to_address: info.sender.to_string(),
+        amount: vec![payout.clone()],
+    }));
+
+    let mut res = Response::new()
+        .add_messages(messages)
+        .add_attribute("action", "claim_market")
+        .add_attribute("market_id", market_id.to_string())
+        .add_attribute("payout", payout.amount.to_string());
+    // Invalid markets and markets nobody won give the stakes back.
+    if refunded {
+        res = res.add_event(events::game_refund(
+            "market",
+            market_id,
+            &info.sender,
+            &payout,
+        ));
+    }
+    Ok(res)
+}
+
+/// End of the dispute window of a posted result.
+fn dispute_ends_at(market: &Market) -> StdResult<u64> {
+    Ok(Uint64::new(market.resolved_at)
+        .checked_add(Uint64::new(market.dispute_window))?
+        .u64())
+}
+
+fn load_market(deps: &DepsMut, market_id: u64) -> Result<Market, ContractError> {
+    MARKETS
+        .may_load(deps.storage, market_id)?
+        .ok_or(ContractError::MarketNotFound { market_id })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::contract::instantiate;
+    use crate::msg::InstantiateMsg;
+    use cosmwasm_std::coins;
+    use cosmwasm_std::testing::{mock_dependencies, mock_env, mock_info};
+
+    const DENOM: &str = "usei";
+
+    fn env_at(seconds: u64) -> Env {
+        let mut env = mock_env();
+        env.block.time = env.block.time.plus_seconds(seconds);
+        env
+    }
+
+    /// Creates a yes/no market closing in 100s with a 50s dispute window and
+    /// places 300 on "yes" (alice 100, bob 200) and 100 on "no" (carol).
+    fn setup_market(mut deps: DepsMut) -> u64 {
+        let msg = InstantiateMsg {
+            fee_address: Addr::unchecked("fee_address"),
+            start_flag: true,
+        };
+        instantiate(deps.branch(), mock_env(), mock_info("creator", &[]), msg).unwrap();
+        create_market(
+            deps.branch(),
+            mock_env(),
+            mock_info("fee_address", &[]),
+            String::from("Will it rain?"),
+            vec![String::from("yes"), String::from("no")],
+            Addr::unchecked("resolver"),
+            String::from(DENOM),
+            mock_env().block.time.seconds() + 100,
+            50,
+        )
+        .unwrap();
+        let market_id = MARKET_COUNT.load(deps.storage).unwrap();
+        for &(bettor, amount, outcome) in &[("alice", 100, 0), ("bob", 200, 0), ("carol", 100, 1)] {
+            place_market_bet(
+                deps.branch(),
+                mock_env(),
+                mock_info(bettor, &coins(amount, DENOM)),
+                market_id,
+                outcome,
+            )
+            .unwrap();
+        }
+        market_id
+    }
+
+    #[test]
+    fn test_winners_split_pot_after_dispute_window() {
+        let mut deps = mock_dependencies();
+        let market_id = setup_market(deps.as_mut());
+
+        resolve_market(
+            deps.as_mut(),
+            env_at(100),
+            mock_info("resolver", &[]),
+            market_id,
+            Some(0),
+        )
+        .unwrap();
+
+        let err =
+            claim_market(deps.as_mut(), env_at(120), mock_info("bob", &[]), market_id).unwrap_err();
+        assert!(matches!(err, ContractError::MarketNotFinal { .. }));
+
+        // bob holds 2/3 of the winning stake, so he gets his 200 back and
+        // 2/3 of carol's 100, 66, minus the 4% fee on those 66.
+        let res =
+            claim_market(deps.as_mut(), env_at(150), mock_info("bob", &[]), market_id).unwrap();
+        assert_eq!(
+            vec![
+                CosmosMsg::Bank(BankMsg::Send {
+                    to_address: String::from("fee_address"),
+                    amount: coins(2, DENOM),
+                }),
+                CosmosMsg::Bank(BankMsg::Send {
+                    to_address: String::from("bob"),
+                    amount: coins(264, DENOM),
+                }),
+            ],
+            res.messages
+                .iter()
+                .map(|sub| sub.msg.clone())
+                .collect::<Vec<_>>()
+        );
+
+        claim_market(
+            deps.as_mut(),
+            env_at(150),
+            mock_info("carol", &[]),
+            market_id,
+        )
+        .unwrap_err();
+
+        // alice gets 100 and 33 minus a fee of 1. As the last winner, she
+        // also releases the 1 left over from rounding to the house.
+        let res = claim_market(
+            deps.as_mut(),
+            env_at(150),
+            mock_info("alice", &[]),
+            market_id,
+        )
+        .unwrap();
+        assert_eq!(
+            vec![
+                CosmosMsg::Bank(BankMsg::Send {
+                    to_address: String::from("fee_address"),
+                    amount: coins(2, DENOM),
+                }),
+                CosmosMsg::Bank(BankMsg::Send {
+                    to_address: String::from("alice"),
+                    amount: coins(132, DENOM),
+                }),
+            ],
+            res.messages
+                .iter()
+                .map(|sub| sub.msg.clone())
+                .collect::<Vec<_>>()
+        );
+        let market = MARKETS.load(&deps.storage, market_id).unwrap();
+        assert_eq!(market.pot_total, market.paid_out);
+        crate::solvency::assert_tracked(deps.as_ref());
+    }
+
+    #[test]
+    fn test_invalid_market_refunds_stakes() {
+        let mut deps = mock_dependencies();
+        let market_id = setup_market(deps.as_mut());
+
+        resolve_market(
+            deps.as_mut(),
+            env_at(100),
+            mock_info("resolver", &[]),
+            market_id,
+            None,
+        )
+        .unwrap();
+
+        let res = claim_market(
+            deps.as_mut(),
+            env_at(150),
+            mock_info("carol", &[]),
+            market_id,
+        )
+        .unwrap();
+        assert_eq!(1, res.messages.len());
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: String::from("carol"),
+                amount: coins(100, DENOM),
+            })
+        );
+        crate::solvency::assert_tracked(deps.as_ref());
+    }
+
+    #[test]
+    fn test_dispute_reopens_resolution() {
+        let mut deps = mock_dependencies();
+        let market_id = setup_market(deps.as_mut());
+
+        resolve_market(
+            deps.as_mut(),
+            env_at(100),
+            mock_info("resolver", &[]),
+            market_id,
+            Some(1),
+        )
+        .unwrap();
+        dispute_market(
+            deps.as_mut(),
+            env_at(120),
+            mock_info("fee_address", &[]),
+            market_id,
+        )
+        .unwrap();
+
+        let market = MARKETS.load(&deps.storage, market_id).unwrap();
+        assert_eq!(MarketStatus::Open, market.status);
+        assert_eq!(None, market.winning_outcome);
+
+        resolve_market(
+            deps.as_mut(),
+            env_at(130),
+            mock_info("resolver", &[]),
+            market_id,
+            Some(0),
+        )
+        .unwrap();
+    }
+}
@@ -1,11 +1,108 @@
-use cosmwasm_std::{entry_point, Binary, Deps, Env, StdError, StdResult};
+use cosmwasm_std::{
+    entry_point, to_json_binary, Addr, Binary, Coin, Deps, Env, Order, StdError, StdResult,
+};
+use cw_storage_plus::Bound;
 
-use crate::msg::QueryMsg;
-use crate::state::{State, CONFIG};
+use crate::allowlist::is_allowed;
+use crate::bonus::{assert_round_denom, pot_bonus};
+use crate::contract::{issue_tickets, split_pot, tickets_held, win_chance};
+use crate::denoms::{pot_totals, total_weight, weighted_bets};
+use crate::governance::{query_house_settings, round_fee_bps};
+use crate::hooks::query_hooks;
+use crate::limits::load_player_limits;
+use crate::loyalty::query_loyalty;
+use crate::msg::{
+    PoolConfigResponse, PoolId, QueryMsg, SimulateBetResponse, TicketRange, UnbondingResponse,
+    UserBetInfo,
+};
+use crate::oracle::normalized_value;
+use crate::randomness;
+use crate::solvency::query_solvency;
+use crate::state::{
+    Challenge, RoundRecord, State, ALLOWLIST_CONFIG, CHALLENGES, CLOSE_TIMES, CONFIG, ESCROW,
+    LOYALTY_CONFIG, MARKETS, MARKET_POSITIONS, NFT_CONFIG, NFT_PRIZES, ORACLE_CONFIG, POOL_DENOMS,
+    RANDOMNESS_MODES, ROUND_HISTORY, STAKING_POOL, TICKET_CONFIGS, UNBONDING_BATCHES,
+    UNBONDING_QUEUE, WITHDRAWAL_PENALTIES,
+};
+
+const DEFAULT_LIMIT: u32 = 10;
+const MAX_LIMIT: u32 = 30;
 
 #[cfg_attr(not(feature = "library"), entry_point)]
-pub fn query(_deps: Deps, _env: Env, msg: QueryMsg) -> StdResult<Binary> {
-    match msg {}
+pub fn query(deps: Deps, env: Env, msg: QueryMsg) -> StdResult<Binary> {
+    match msg {
+        QueryMsg::StakingPool {} => to_json_binary(&STAKING_POOL.may_load(deps.storage)?),
+        QueryMsg::Unbonding { address } => to_json_binary(&query_unbonding(deps, address)?),
+        QueryMsg::Challenge { challenge_id } => {
+            to_json_binary(&CHALLENGES.may_load(deps.storage, challenge_id)?)
+        }
+        QueryMsg::OpenChallenges { start_after, limit } => {
+            to_json_binary(&query_open_challenges(deps, env, start_after, limit)?)
+        }
+        QueryMsg::Market { market_id } => {
+            to_json_binary(&MARKETS.may_load(deps.storage, market_id)?)
+        }
+        QueryMsg::MarketPosition { market_id, address } => to_json_binary(
+            &MARKET_POSITIONS
+                .may_load(deps.storage, (market_id, &address))?
+                .unwrap_or_default(),
+        ),
+        QueryMsg::TicketConfig { pool_id } => {
+            to_json_binary(&TICKET_CONFIGS.may_load(deps.storage, pool_id.as_str())?)
+        }
+        QueryMsg::PoolConfig { pool_id } => to_json_binary(&query_pool_config(deps, pool_id)?),
+        QueryMsg::PotBonus { pool_id } => to_json_binary(&pot_bonus(deps.storage, pool_id)?),
+        QueryMsg::PotTotals { pool_id } => {
+            to_json_binary(&pot_totals(query_config(deps)?.user_list(pool_id)))
+        }
+        QueryMsg::OracleConfig {} => to_json_binary(&ORACLE_CONFIG.may_load(deps.storage)?),
+        QueryMsg::CloseTime { pool_id } => {
+            to_json_binary(&CLOSE_TIMES.may_load(deps.storage, pool_id.as_str())?)
+        }
+        QueryMsg::WithdrawalPenalty { pool_id } => to_json_binary(
+            &WITHDRAWAL_PENALTIES
+                .may_load(deps.storage, pool_id.as_str())?
+                .unwrap_or_default(),
+        ),
+        QueryMsg::Tickets { pool_id, address } => {
+            to_json_binary(&query_tickets(deps, pool_id, address)?)
+        }
+        QueryMsg::NftConfig {} => to_json_binary(&NFT_CONFIG.may_load(deps.storage)?),
+        QueryMsg::NftPrize { token_id } => {
+            to_json_binary(&NFT_PRIZES.may_load(deps.storage, &token_id)?)
+        }
+        QueryMsg::AllowlistConfig {} => to_json_binary(&ALLOWLIST_CONFIG.may_load(deps.storage)?),
+        QueryMsg::IsAllowed { address } => to_json_binary(&is_allowed(deps, &address)?),
+        QueryMsg::Hooks {} => to_json_binary(&query_hooks(deps)?),
+        QueryMsg::LoyaltyConfig {} => to_json_binary(&LOYALTY_CONFIG.may_load(deps.storage)?),
+        QueryMsg::Loyalty { address } => to_json_binary(&query_loyalty(deps, address)?),
+        QueryMsg::Round { pool_id, round_id } => {
+            to_json_binary(&ROUND_HISTORY.may_load(deps.storage, (&pool_id, round_id))?)
+        }
+        QueryMsg::RoundHistory {
+            pool_id,
+            start_after,
+            limit,
+        } => to_json_binary(&query_round_history(deps, pool_id, start_after, limit)?),
+        QueryMsg::Escrow { address } => {
+            to_json_binary(&ESCROW.may_load(deps.storage, &address)?.unwrap_or_default())
+        }
+        QueryMsg::PlayerLimits { address } => to_json_binary(&load_player_limits(
+            deps.storage,
+            &address,
+            env.block.time.seconds(),
+        )?),
+        QueryMsg::SimulateBet {
+            pool_id,
+            address,
+            amount,
+            denom,
+        } => to_json_binary(&query_simulate_bet(
+            deps, &env, pool_id, address, amount, denom,
+        )?),
+        QueryMsg::Solvency {} => to_json_binary(&query_solvency(deps, env)?),
+        QueryMsg::HouseSettings {} => to_json_binary(&query_house_settings(deps)?),
+    }
 }
 
 pub fn query_config(deps: Deps) -> StdResult<State> {
@@ -17,3 +114,426 @@ pub fn query_config(deps: Deps) -> StdResult<State> {
         }),
     }
 }
+
+pub fn query_unbonding(deps: Deps, address: Addr) -> StdResult<Vec<UnbondingResponse>> {
+    UNBONDING_QUEUE
+        .may_load(deps.storage, &address)?
+        .unwrap_or_default()
+        .into_iter()
+        .map(|entry| {
+            let batch = UNBONDING_BATCHES.load(deps.storage, entry.batch)?;
+            Ok(UnbondingResponse {
+                amount: entry.amount,
+                batch: entry.batch,
+                release_at: batch.release_at,
+            })
+        })
+        .collect()
+}
+
+pub fn query_open_challenges(
+    deps: Deps,
+    env: Env,
+    start_after: Option<u64>,
+    limit: Option<u32>,
+) -> StdResult<Vec<Challenge>> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start = start_after.map(Bound::exclusive);
+    let now = env.block.time.seconds();
+
+    CHALLENGES
+        .range(deps.storage, start, None, Order::Ascending)
+        .map(|item| item.map(|(_, challenge)| challenge))
+        .filter(|item| match item {
+            Ok(challenge) => challenge.opponent.is_none() && challenge.expires_at > now,
+            Err(_) => true,
+        })
+        .take(limit)
+        .collect()
+}
+
+pub fn query_round_history(
+    deps: Deps,
+    pool_id: String,
+    start_after: Option<u64>,
+    limit: Option<u32>,
+) -> StdResult<Vec<(u64, RoundRecord)>> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start = start_after.map(Bound::exclusive);
+
+    ROUND_HISTORY
+        .prefix(&pool_id)
+        .range(deps.storage, start, None, Order::Ascending)
+        .take(limit)
+        .collect()
+}
+
+pub fn query_pool_config(deps: Deps, pool_id: PoolId) -> StdResult<PoolConfigResponse> {
+    let pool = pool_id.as_str();
+    let randomness = RANDOMNESS_MODES
+        .may_load(deps.storage, pool)?
+        .unwrap_or_default();
+    Ok(PoolConfigResponse {
+        max_bet: pool_id.max_bet(),
+        ticket_config: TICKET_CONFIGS.may_load(deps.storage, pool)?,
+        closes_at: CLOSE_TIMES.may_load(deps.storage, pool)?,
+        withdrawal_penalty_bps: WITHDRAWAL_PENALTIES
+            .may_load(deps.storage, pool)?
+            .unwrap_or_default(),
+        denoms: POOL_DENOMS.may_load(deps.storage, pool)?,
+        randomness,
+        randomness_guarantees: randomness::guarantees(randomness).to_string(),
+    })
+}
+
+pub fn query_tickets(deps: Deps, pool_id: PoolId, address: Addr) -> StdResult<Vec<TicketRange>> {
+    let config = query_config(deps)?;
+    Ok(config
+        .user_list(pool_id)
+        .iter()
+        .filter(|bet| bet.user_address == address)
+        .filter_map(|bet| bet.tickets.clone())
+        .collect())
+}
+
+/// Runs the settlement math of the pool on its current bets plus the
+/// simulated one, in `denom` or the round's denom. The figures are those of
+/// the pot of that denom, the other pots' net prizes are listed apart.
+pub fn query_simulate_bet(
+    deps: Deps,
+    env: &Env,
+    pool_id: PoolId,
+    address: Addr,
+    amount: u128,
+    denom: Option<String>,
+) -> StdResult<SimulateBetResponse> {
+    let config = query_config(deps)?;
+    let bets = config.user_list(pool_id);
+    let pool_denoms = POOL_DENOMS.may_load(deps.storage, pool_id.as_str())?;
+    let bonus = pot_bonus(deps.storage, pool_id)?;
+    let mut pots = pot_totals(bets);
+    for coin in &bonus {
+        if !pots.iter().any(|pot| pot.denom == coin.denom) {
+            pots.push(Coin::new(0, coin.denom.clone()));
+        }
+    }
+
+    let denom = match denom {
+        Some(denom) => {
+            assert_round_denom(deps.storage, &config, pool_id, &denom)
+                .map_err(|err| StdError::generic_err(err.to_string()))?;
+            denom
+        }
+        None if pool_denoms.is_some() => {
+            return Err(StdError::generic_err(
+                "Multi-denom pools need the denom of the simulated bet",
+            ))
+        }
+        None => pots
+            .first()
+            .map(|pot| pot.denom.clone())
+            .unwrap_or_default(),
+    };
+    let stake_coin = Coin::new(amount, denom.clone());
+    let pot_total = match pots.iter_mut().find(|pot| pot.denom == denom) {
+        Some(pot) => {
+            pot.amount += stake_coin.amount;
+            pot.amount.u128()
+        }
+        None => {
+            pots.insert(0, stake_coin.clone());
+            amount
+        }
+    };
+    let normalized_value = match pool_denoms {
+        Some(_) => normalized_value(deps, env, &stake_coin)
+            .map_err(|err| StdError::generic_err(err.to_string()))?,
+        None => None,
+    };
+    let mut simulated_bets = bets.clone();
+    simulated_bets.push(UserBetInfo {
+        user_address: address.clone(),
+        user_amount: amount,
+        user_order: bets.len() as u128 + 1,
+        coin_type: denom.clone(),
+        tickets: None,
+        nft_token_id: None,
+        nft_collection: None,
+        client_seed: None,
+        normalized_value,
+        free_bet: false,
+    });
+    let stake = simulated_bets
+        .iter()
+        .filter(|bet| bet.user_address == address && bet.coin_type == denom)
+        .map(|bet| bet.user_amount)
+        .sum();
+
+    // Ticket pools draw a ticket, the others weigh the stakes like the draw.
+    // A ticket purchase is checked like a real one.
+    let (chances, total_chances) = match TICKET_CONFIGS.may_load(deps.storage, pool_id.as_str())? {
+        Some(ticket_config) => {
+            let bought =
+                issue_tickets(bets, &address, &ticket_config, amount, &ticket_config.denom)
+                    .map_err(|err| StdError::generic_err(err.to_string()))?;
+            (
+                (tickets_held(bets, &address) + bought.last + 1 - bought.first) as u128,
+                bought.last as u128,
+            )
+        }
+        None => {
+            let weighted = match &pool_denoms {
+                Some(pool_denoms) => weighted_bets(&simulated_bets, pool_denoms)?,
+                None => simulated_bets,
+            };
+            let total = total_weight(&weighted)?;
+            let own = weighted
+                .iter()
+                .filter(|bet| bet.user_address == address)
+                .map(|bet| bet.user_amount)
+                .sum();
+            (own, total)
+        }
+    };
+    let win_probability = match total_chances {
+        0 => 0,
+        total_chances => win_chance(chances, total_chances),
+    };
+
+    // Every pot is split on its own, and the bonus of its denom paid on top
+    // without a fee.
+    let bonus_in = |denom: &str| {
+        bonus
+            .iter()
+            .find(|coin| coin.denom == denom)
+            .map_or(0, |coin| coin.amount.u128())
+    };
+    let fee_bps = round_fee_bps(deps.storage, pool_id.as_str())?;
+    let mut other_prizes = vec![];
+    for pot in pots.into_iter().filter(|pot| pot.denom != denom) {
+        let (_, prize) = split_pot(pot.amount.u128(), fee_bps);
+        let won = prize + bonus_in(&pot.denom);
+        if won > 0 {
+            other_prizes.push(Coin::new(won, pot.denom));
+        }
+    }
+    let (fee, prize) = split_pot(pot_total, fee_bps);
+
+    Ok(SimulateBetResponse {
+        stake,
+        pot_total,
+        win_probability,
+        gross_prize: pot_total + bonus_in(&denom),
+        fee,
+        net_payout: prize + bonus_in(&denom),
+        other_prizes,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::contract::{execute, instantiate};
+    use crate::msg::{ExecuteMsg, InstantiateMsg, RandomnessMode, TicketConfig};
+    use crate::ContractError;
+    use cosmwasm_std::coins;
+    use cosmwasm_std::testing::{mock_dependencies_with_balance, mock_env, mock_info};
+
+    const DENOM: &str = "usei";
+
+    #[test]
+    fn test_simulate_bet() {
+        let mut deps = mock_dependencies_with_balance(&coins(10_000, DENOM));
+        let msg = InstantiateMsg {
+            fee_address: Addr::unchecked("fee_address"),
+            start_flag: true,
+        };
+        instantiate(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("fee_address", &[]),
+            msg,
+        )
+        .unwrap();
+        for (bettor, amount) in &[("alice", 100), ("bob", 200)] {
+            execute(
+                deps.as_mut(),
+                mock_env(),
+                mock_info(bettor, &coins(*amount, DENOM)),
+                ExecuteMsg::AddBetUserWhale { client_seed: None },
+            )
+            .unwrap();
+        }
+
+        let res = query_simulate_bet(
+            deps.as_ref(),
+            &mock_env(),
+            PoolId::Whale,
+            Addr::unchecked("alice"),
+            100,
+            None,
+        )
+        .unwrap();
+        assert_eq!(
+            SimulateBetResponse {
+                stake: 200,
+                pot_total: 400,
+                win_probability: 5000,
+                gross_prize: 400,
+                fee: 16,
+                net_payout: 384,
+                other_prizes: vec![],
+            },
+            res
+        );
+
+        // The winner also gets the pot bonus, free of the fee.
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("fee_address", &coins(100, DENOM)),
+            ExecuteMsg::FundPot {
+                pool_id: PoolId::Whale,
+            },
+        )
+        .unwrap();
+        let res = query_simulate_bet(
+            deps.as_ref(),
+            &mock_env(),
+            PoolId::Whale,
+            Addr::unchecked("alice"),
+            100,
+            None,
+        )
+        .unwrap();
+        assert_eq!((500, 16, 484), (res.gross_prize, res.fee, res.net_payout));
+
+        let res = query_simulate_bet(
+            deps.as_ref(),
+            &mock_env(),
+            PoolId::Shrimp,
+            Addr::unchecked("alice"),
+            0,
+            None,
+        )
+        .unwrap();
+        assert_eq!(0, res.win_probability);
+    }
+
+    #[test]
+    fn test_simulate_ticket_bet() {
+        let mut deps = mock_dependencies_with_balance(&coins(10_000, DENOM));
+        let admin = mock_info("fee_address", &[]);
+        let msg = InstantiateMsg {
+            fee_address: Addr::unchecked("fee_address"),
+            start_flag: true,
+        };
+        instantiate(deps.as_mut(), mock_env(), admin.clone(), msg).unwrap();
+        let ticket_config = TicketConfig {
+            price: 10,
+            denom: DENOM.to_string(),
+            max_per_user: 10,
+            supply: 10,
+        };
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            admin,
+            ExecuteMsg::SetTicketConfig {
+                pool_id: PoolId::Infinite,
+                ticket_config: Some(ticket_config),
+            },
+        )
+        .unwrap();
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("bob", &coins(30, DENOM)),
+            ExecuteMsg::AddBetUserInfinite { client_seed: None },
+        )
+        .unwrap();
+
+        // One ticket out of four
+        let res = query_simulate_bet(
+            deps.as_ref(),
+            &mock_env(),
+            PoolId::Infinite,
+            Addr::unchecked("alice"),
+            10,
+            None,
+        )
+        .unwrap();
+        assert_eq!(2500, res.win_probability);
+        assert_eq!(40, res.gross_prize);
+
+        // Like a purchase, only whole tickets within the limits.
+        for amount in [15, 100] {
+            query_simulate_bet(
+                deps.as_ref(),
+                &mock_env(),
+                PoolId::Infinite,
+                Addr::unchecked("alice"),
+                amount,
+                None,
+            )
+            .unwrap_err();
+        }
+    }
+
+    #[test]
+    fn test_pool_config() {
+        let mut deps = mock_dependencies_with_balance(&coins(10_000, DENOM));
+        let admin = mock_info("fee_address", &[]);
+        let msg = InstantiateMsg {
+            fee_address: Addr::unchecked("fee_address"),
+            start_flag: true,
+        };
+        instantiate(deps.as_mut(), mock_env(), admin.clone(), msg).unwrap();
+        let set_entropy = ExecuteMsg::SetRandomnessMode {
+            pool_id: PoolId::Shrimp,
+            mode: RandomnessMode::Entropy,
+        };
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            admin.clone(),
+            set_entropy.clone(),
+        )
+        .unwrap();
+
+        let res = query_pool_config(deps.as_ref(), PoolId::Shrimp).unwrap();
+        assert_eq!(Some(50), res.max_bet);
+        assert_eq!(RandomnessMode::Entropy, res.randomness);
+        assert!(res.randomness_guarantees.contains("low-value"));
+        let res = query_pool_config(deps.as_ref(), PoolId::Whale).unwrap();
+        assert_eq!(RandomnessMode::Seed, res.randomness);
+
+        // The mode can't change under a running round, and the draw ignores
+        // the admin's seed.
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("bob", &coins(30, DENOM)),
+            ExecuteMsg::AddBetUserShrimp {
+                client_seed: Some("lucky".to_string()),
+            },
+        )
+        .unwrap();
+        let err = execute(deps.as_mut(), mock_env(), admin.clone(), set_entropy).unwrap_err();
+        assert!(matches!(err, ContractError::RoundInProgress { .. }));
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            admin.clone(),
+            ExecuteMsg::ToggleGameShrimp {},
+        )
+        .unwrap();
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            admin,
+            ExecuteMsg::EndGameShrimp { seed: 0 },
+        )
+        .unwrap();
+    }
+}
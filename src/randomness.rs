@@ -0,0 +1,134 @@
+//! Per-pool randomness modes.
+//! Pools draw from the admin's seed by default. A low-value pool can instead
+//! draw from on-chain entropy, the sha256 of:
+//!
+//! - the pool id and round id,
+//! - the address of the last bettor,
+//! - the block height and time of the draw,
+//! - every bettor's client seed, in bet order.
+//!
+//! No one has to be trusted with a seed, but the inputs are public or known
+//! in advance to whoever produces the block, and the last bettor sees all the
+//! others before choosing theirs. Either can predict the draw, and grind it
+//! by retrying or delaying. So can the admin, who alone sends `EndGame` and
+//! `EndDueRounds` and so picks the block of the draw.
+
+use cosmwasm_std::{DepsMut, Env, MessageInfo, Response};
+use sha2::{Digest, Sha256};
+
+use crate::bonus;
+use crate::msg::{PoolId, RandomnessMode, UserBetInfo};
+use crate::query::query_config;
+use crate::state::RANDOMNESS_MODES;
+use crate::ContractError;
+
+pub fn set_randomness_mode(
+    deps: DepsMut,
+    _env: Env,
+    info: MessageInfo,
+    pool: PoolId,
+    mode: RandomnessMode,
+) -> Result<Response, ContractError> {
+    let config = query_config(deps.as_ref())?;
+    if info.sender != config.fee_address {
+        return Err(ContractError::Unauthorized);
+    }
+    if bonus::round_started(deps.storage, &config, pool)? {
+        return Err(ContractError::RoundInProgress { pool });
+    }
+
+    match mode {
+        RandomnessMode::Seed => RANDOMNESS_MODES.remove(deps.storage, pool.as_str()),
+        RandomnessMode::Entropy => RANDOMNESS_MODES.save(deps.storage, pool.as_str(), &mode)?,
+    }
+
+    Ok(Response::new()
+        .add_attribute("action", "set_randomness_mode")
+        .add_attribute("pool_id", pool.to_string())
+        .add_attribute("mode", format!("{:?}", mode).to_lowercase()))
+}
+
+/// Who can predict or influence a draw in `mode`, for the pool config query.
+pub fn guarantees(mode: RandomnessMode) -> &'static str {
+    match mode {
+        RandomnessMode::Seed => {
+            "The admin's seed decides the draw. Whoever knows the seed knows the winner."
+        }
+        RandomnessMode::Entropy => {
+            "The draw hashes public block data and the bettors' client seeds. The block \
+             producer, the last bettor and the admin, who picks the block of the draw, can \
+             predict it and may bias it by retrying or delaying, so only use it for \
+             low-value pools."
+        }
+    }
+}
+
+/// Random number in basis points from the round's on-chain entropy.
+pub fn entropy_random_value(pool: PoolId, round_id: u64, bets: &[UserBetInfo], env: &Env) -> u128 {
+    let mut hasher = Sha256::new();
+    // Every field is length-prefixed so no two inputs hash alike.
+    let mut field = |bytes: &[u8]| {
+        hasher.update((bytes.len() as u64).to_be_bytes());
+        hasher.update(bytes);
+    };
+    field(pool.as_str().as_bytes());
+    field(&round_id.to_be_bytes());
+    let last_bettor = bets.last().map_or("", |bet| bet.user_address.as_str());
+    field(last_bettor.as_bytes());
+    field(&env.block.height.to_be_bytes());
+    field(&env.block.time.nanos().to_be_bytes());
+    for bet in bets {
+        field(bet.client_seed.as_deref().unwrap_or_default().as_bytes());
+    }
+
+    let digest = hasher.finalize();
+    let mut head = [0u8; 16];
+    head.copy_from_slice(&digest[..16]);
+    u128::from_be_bytes(head) % 10000
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cosmwasm_std::testing::mock_env;
+    use cosmwasm_std::Addr;
+
+    fn bet(address: &str, client_seed: Option<&str>) -> UserBetInfo {
+        UserBetInfo {
+            user_address: Addr::unchecked(address),
+            user_amount: 10,
+            user_order: 1,
+            coin_type: "usei".to_string(),
+            tickets: None,
+            nft_token_id: None,
+            nft_collection: None,
+            client_seed: client_seed.map(String::from),
+            normalized_value: None,
+            free_bet: false,
+        }
+    }
+
+    #[test]
+    fn test_entropy_inputs() {
+        let env = mock_env();
+        let bets = vec![bet("alice", Some("lucky")), bet("bob", None)];
+        let value = entropy_random_value(PoolId::Infinite, 1, &bets, &env);
+        assert!(value < 10000);
+        assert_eq!(
+            value,
+            entropy_random_value(PoolId::Infinite, 1, &bets, &env)
+        );
+
+        let mut later = mock_env();
+        later.block.height += 1;
+        let reseeded = vec![bet("alice", Some("lucky")), bet("bob", Some("7"))];
+        let variants = [
+            entropy_random_value(PoolId::Whale, 1, &bets, &env),
+            entropy_random_value(PoolId::Infinite, 2, &bets, &env),
+            entropy_random_value(PoolId::Infinite, 1, &bets, &later),
+            entropy_random_value(PoolId::Infinite, 1, &reseeded, &env),
+        ];
+        // Each input moves the draw.
+        assert!(variants.iter().all(|&variant| variant != value));
+    }
+}
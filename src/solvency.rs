@@ -0,0 +1,453 @@
+//! Solvency accounting.
+//! The contract owes every coin it holds for someone: the open pot bets and
+//! bonuses, prizes waiting for NFT holders, escrowed payouts, free-bet credits,
+//! challenge stakes, unpaid market stakes, the staking prize pot and every
+//! queued unbonding claim. Its bank balance must cover these in every denom.
+//! Stakes delegated by the staking pool are not held by the contract and are
+//! left out. Unbonding stakes are owed from the moment they are withdrawn;
+//! until their release time they are still on their way back, and count as
+//! held when checking for a deficit but never as surplus. Anything above the
+//! liabilities can be swept to the fee address.
+//! The liabilities are kept as running totals, updated by every message that
+//! takes in or pays out money held for someone, so no check scans storage.
+
+use std::collections::BTreeMap;
+
+use cosmwasm_std::{
+    coins, BankMsg, Coin, Deps, DepsMut, Env, MessageInfo, Order, Response, StdResult, Storage,
+    Uint128,
+};
+
+use crate::msg::SolvencyResponse;
+use crate::query::query_config;
+use crate::state::{LIABILITIES, STAKING_POOL, UNBONDING_BATCHES};
+use crate::ContractError;
+
+/// Everything the contract owes, per denom.
+pub fn liabilities(deps: Deps) -> StdResult<BTreeMap<String, u128>> {
+    LIABILITIES
+        .range(deps.storage, None, None, Order::Ascending)
+        .collect()
+}
+
+/// Records that the contract now holds `amount` of `denom` for someone.
+pub(crate) fn owe(storage: &mut dyn Storage, denom: &str, amount: u128) -> StdResult<()> {
+    if amount > 0 {
+        LIABILITIES.update(storage, denom, |owed| -> StdResult<_> {
+            Ok(owed.unwrap_or_default() + amount)
+        })?;
+    }
+    Ok(())
+}
+
+/// Records that `amount` of `denom` held for someone was paid out or handed
+/// to the house.
+pub(crate) fn discharge(storage: &mut dyn Storage, denom: &str, amount: u128) -> StdResult<()> {
+    if amount == 0 {
+        return Ok(());
+    }
+    let owed = LIABILITIES.may_load(storage, denom)?.unwrap_or_default();
+    let owed = Uint128::new(owed).checked_sub(Uint128::new(amount))?;
+    if owed.is_zero() {
+        LIABILITIES.remove(storage, denom);
+    } else {
+        LIABILITIES.save(storage, denom, &owed.u128())?;
+    }
+    Ok(())
+}
+
+pub(crate) fn owe_coins(storage: &mut dyn Storage, coins: &[Coin]) -> StdResult<()> {
+    for coin in coins {
+        owe(storage, &coin.denom, coin.amount.u128())?;
+    }
+    Ok(())
+}
+
+pub(crate) fn discharge_coins(storage: &mut dyn Storage, coins: &[Coin]) -> StdResult<()> {
+    for coin in coins {
+        discharge(storage, &coin.denom, coin.amount.u128())?;
+    }
+    Ok(())
+}
+
+/// Unbonding stakes not released yet, per denom. They are owed but not
+/// back in the balance.
+pub fn unbonding(deps: Deps, env: &Env) -> StdResult<BTreeMap<String, u128>> {
+    let mut pending = BTreeMap::new();
+    if let Some(pool) = STAKING_POOL.may_load(deps.storage)? {
+        let now = env.block.time.seconds();
+        // Batches are undelegated in order, so only the latest few are still
+        // on their way back.
+        for item in UNBONDING_BATCHES.range(deps.storage, None, None, Order::Descending) {
+            let (_, batch) = item?;
+            if matches!(batch.release_at, Some(release_at) if release_at <= now) {
+                break;
+            }
+            add(&mut pending, &pool.denom, batch.amount);
+        }
+    }
+    Ok(pending)
+}
+
+fn add(owed: &mut BTreeMap<String, u128>, denom: &str, amount: u128) {
+    if amount > 0 {
+        *owed.entry(denom.to_string()).or_default() += amount;
+    }
+}
+
+/// Everything the contract owes, per denom, counted from storage. The
+/// running totals must always agree with it.
+#[cfg(test)]
+pub(crate) fn counted_liabilities(deps: Deps) -> StdResult<BTreeMap<String, u128>> {
+    use crate::msg::PoolId;
+    use crate::state::{
+        CHALLENGES, ESCROW, FREE_BET_CREDITS, MARKETS, NFT_PRIZES, POT_BONUSES, UNBONDING_QUEUE,
+    };
+
+    let mut owed = BTreeMap::new();
+
+    let config = query_config(deps)?;
+    for &pool in &PoolId::ALL {
+        for bet in config.user_list(pool) {
+            add(&mut owed, &bet.coin_type, bet.user_amount);
+        }
+    }
+    for item in POT_BONUSES.range(deps.storage, None, None, Order::Ascending) {
+        for coin in item?.1 {
+            add(&mut owed, &coin.denom, coin.amount.u128());
+        }
+    }
+    for item in NFT_PRIZES.range(deps.storage, None, None, Order::Ascending) {
+        for prize in item?.1.prize {
+            add(&mut owed, &prize.denom, prize.amount.u128());
+        }
+    }
+    for item in ESCROW.range(deps.storage, None, None, Order::Ascending) {
+        for coin in item?.1 {
+            add(&mut owed, &coin.denom, coin.amount.u128());
+        }
+    }
+    for item in FREE_BET_CREDITS.range(deps.storage, None, None, Order::Ascending) {
+        for coin in item?.1 {
+            add(&mut owed, &coin.denom, coin.amount.u128());
+        }
+    }
+    for item in CHALLENGES.range(deps.storage, None, None, Order::Ascending) {
+        let (_, challenge) = item?;
+        let stakes = if challenge.opponent.is_some() { 2 } else { 1 };
+        add(&mut owed, &challenge.coin_type, stakes * challenge.amount);
+    }
+    for item in MARKETS.range(deps.storage, None, None, Order::Ascending) {
+        let (_, market) = item?;
+        add(
+            &mut owed,
+            &market.coin_type,
+            market.pot_total - market.paid_out,
+        );
+    }
+    if let Some(pool) = STAKING_POOL.may_load(deps.storage)? {
+        add(&mut owed, &pool.denom, pool.prize_pot);
+        for item in UNBONDING_QUEUE.range(deps.storage, None, None, Order::Ascending) {
+            for entry in item?.1 {
+                add(&mut owed, &pool.denom, entry.amount);
+            }
+        }
+    }
+    Ok(owed)
+}
+
+/// Fails unless the running totals match what storage holds.
+#[cfg(test)]
+pub(crate) fn assert_tracked(deps: Deps) {
+    assert_eq!(
+        counted_liabilities(deps).unwrap(),
+        liabilities(deps).unwrap()
+    );
+}
+
+/// Solvency of every denom the contract holds or owes.
+pub fn query_solvency(deps: Deps, env: Env) -> StdResult<Vec<SolvencyResponse>> {
+    let mut balances: BTreeMap<String, u128> = deps
+        .querier
+        .query_all_balances(&env.contract.address)?
+        .into_iter()
+        .map(|coin| (coin.denom, coin.amount.u128()))
+        .collect();
+    let owed = liabilities(deps)?;
+    let pending = unbonding(deps, &env)?;
+    for denom in owed.keys() {
+        balances.entry(denom.clone()).or_default();
+    }
+
+    Ok(balances
+        .into_iter()
+        .map(|(denom, balance)| {
+            let liabilities = owed.get(&denom).copied().unwrap_or_default();
+            let unbonding = pending.get(&denom).copied().unwrap_or_default();
+            SolvencyResponse {
+                surplus: balance.saturating_sub(liabilities),
+                deficit: liabilities.saturating_sub(balance + unbonding),
+                denom,
+                balance,
+                unbonding,
+                liabilities,
+            }
+        })
+        .collect())
+}
+
+/// Sends the surplus of `denom` to the fee address, leaving exactly what
+/// the contract owes.
+pub fn sweep_surplus(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    denom: String,
+) -> Result<Response, ContractError> {
+    let config = query_config(deps.as_ref())?;
+    if info.sender != config.fee_address {
+        return Err(ContractError::Unauthorized);
+    }
+
+    let balance = deps
+        .querier
+        .query_balance(&env.contract.address, &denom)?
+        .amount
+        .u128();
+    let liabilities = liabilities(deps.as_ref())?
+        .get(&denom)
+        .copied()
+        .unwrap_or_default();
+    let surplus = balance.saturating_sub(liabilities);
+    if surplus == 0 {
+        return Err(ContractError::NoSurplus { denom });
+    }
+
+    Ok(Response::new()
+        .add_message(BankMsg::Send {
+            to_address: config.fee_address.to_string(),
+            amount: coins(surplus, &denom),
+        })
+        .add_attribute("action", "sweep_surplus")
+        .add_attribute("amount", surplus.to_string())
+        .add_attribute("denom", denom))
+}
+
+/// Fails if the balance of `denom` and its unbonding stakes, less `outgoing`
+/// still to be sent by this transaction, do not cover what the contract
+/// owes in it.
+pub(crate) fn assert_solvent(
+    deps: Deps,
+    env: &Env,
+    denom: &str,
+    outgoing: u128,
+) -> Result<(), ContractError> {
+    let balance = deps
+        .querier
+        .query_balance(&env.contract.address, denom)?
+        .amount
+        .u128()
+        + unbonding(deps, env)?
+            .get(denom)
+            .copied()
+            .unwrap_or_default();
+    let liabilities = liabilities(deps)?.get(denom).copied().unwrap_or_default();
+    if balance < liabilities + outgoing {
+        return Err(ContractError::Insolvent {
+            denom: denom.to_string(),
+            balance,
+            liabilities: liabilities + outgoing,
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::contract::{execute, instantiate};
+    use crate::msg::{ExecuteMsg, InstantiateMsg};
+    use crate::state::{StakingPool, UnbondingBatch, UnbondingEntry, UNBONDING_QUEUE};
+    use cosmwasm_std::testing::{
+        mock_dependencies_with_balance, mock_env, mock_info, MOCK_CONTRACT_ADDR,
+    };
+    use cosmwasm_std::Addr;
+
+    const DENOM: &str = "usei";
+
+    #[test]
+    fn test_solvency() {
+        // The mock balance already includes the first bet.
+        let mut deps = mock_dependencies_with_balance(&coins(100, DENOM));
+        let msg = InstantiateMsg {
+            fee_address: Addr::unchecked("fee_address"),
+            start_flag: true,
+        };
+        instantiate(deps.as_mut(), mock_env(), mock_info("creator", &[]), msg).unwrap();
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("alice", &coins(100, DENOM)),
+            ExecuteMsg::AddBetUserInfinite { client_seed: None },
+        )
+        .unwrap();
+
+        deps.querier
+            .update_balance(MOCK_CONTRACT_ADDR, coins(120, DENOM));
+        assert_eq!(
+            vec![SolvencyResponse {
+                denom: DENOM.to_string(),
+                balance: 120,
+                unbonding: 0,
+                liabilities: 100,
+                surplus: 20,
+                deficit: 0,
+            }],
+            query_solvency(deps.as_ref(), mock_env()).unwrap()
+        );
+
+        deps.querier
+            .update_balance(MOCK_CONTRACT_ADDR, coins(100, DENOM));
+        // A bet whose funds never reached the contract.
+        let err = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("bob", &coins(50, DENOM)),
+            ExecuteMsg::AddBetUserInfinite { client_seed: None },
+        )
+        .unwrap_err();
+        assert!(matches!(
+            err,
+            ContractError::Insolvent {
+                balance: 100,
+                liabilities: 150,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn test_sweep_surplus() {
+        let mut deps = mock_dependencies_with_balance(&coins(103, DENOM));
+        let admin = mock_info("fee_address", &[]);
+        let msg = InstantiateMsg {
+            fee_address: Addr::unchecked("fee_address"),
+            start_flag: true,
+        };
+        instantiate(deps.as_mut(), mock_env(), admin.clone(), msg).unwrap();
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("alice", &coins(100, DENOM)),
+            ExecuteMsg::AddBetUserInfinite { client_seed: None },
+        )
+        .unwrap();
+
+        let sweep = ExecuteMsg::SweepSurplus {
+            denom: DENOM.to_string(),
+        };
+        let err = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("alice", &[]),
+            sweep.clone(),
+        )
+        .unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized));
+
+        // Only the 3 coins above the open bet go.
+        let res = execute(deps.as_mut(), mock_env(), admin.clone(), sweep).unwrap();
+        assert_eq!(
+            res.messages[0].msg,
+            BankMsg::Send {
+                to_address: "fee_address".to_string(),
+                amount: coins(3, DENOM),
+            }
+            .into()
+        );
+
+        deps.querier
+            .update_balance(MOCK_CONTRACT_ADDR, coins(100, DENOM));
+        let err = sweep_surplus(deps.as_mut(), mock_env(), admin, DENOM.to_string()).unwrap_err();
+        assert!(matches!(err, ContractError::NoSurplus { .. }));
+    }
+
+    #[test]
+    fn test_unbonding_is_owed_until_claimed() {
+        let mut deps = mock_dependencies_with_balance(&coins(500, DENOM));
+        let admin = mock_info("fee_address", &[]);
+        let msg = InstantiateMsg {
+            fee_address: Addr::unchecked("fee_address"),
+            start_flag: true,
+        };
+        instantiate(deps.as_mut(), mock_env(), admin.clone(), msg).unwrap();
+        let now = mock_env().block.time.seconds();
+        let pool = StakingPool {
+            validator: "seivaloper1".to_string(),
+            denom: DENOM.to_string(),
+            total_deposits: 0,
+            prize_pot: 0,
+            round: 0,
+            open_batch: None,
+            batch_count: 2,
+        };
+        STAKING_POOL.save(&mut deps.storage, &pool).unwrap();
+        for (batch_id, amount, release_at) in [(1, 300, now), (2, 400, now + 100)] {
+            let batch = UnbondingBatch {
+                amount,
+                opened_at: 0,
+                release_at: Some(release_at),
+            };
+            UNBONDING_BATCHES
+                .save(&mut deps.storage, batch_id, &batch)
+                .unwrap();
+        }
+        let queue = vec![
+            UnbondingEntry {
+                amount: 300,
+                batch: 1,
+            },
+            UnbondingEntry {
+                amount: 400,
+                batch: 2,
+            },
+        ];
+        UNBONDING_QUEUE
+            .save(&mut deps.storage, &Addr::unchecked("alice"), &queue)
+            .unwrap();
+        owe(&mut deps.storage, DENOM, 700).unwrap();
+        assert_tracked(deps.as_ref());
+
+        // The 400 still unbonding are owed but not back yet: no deficit, and
+        // nothing to sweep either.
+        assert_eq!(
+            vec![SolvencyResponse {
+                denom: DENOM.to_string(),
+                balance: 500,
+                unbonding: 400,
+                liabilities: 700,
+                surplus: 0,
+                deficit: 0,
+            }],
+            query_solvency(deps.as_ref(), mock_env()).unwrap()
+        );
+        let err =
+            sweep_surplus(deps.as_mut(), mock_env(), admin.clone(), DENOM.to_string()).unwrap_err();
+        assert!(matches!(err, ContractError::NoSurplus { .. }));
+
+        // Once released, they are in the balance and still owed.
+        deps.querier
+            .update_balance(MOCK_CONTRACT_ADDR, coins(900, DENOM));
+        let mut env = mock_env();
+        env.block.time = env.block.time.plus_seconds(100);
+        let res = sweep_surplus(deps.as_mut(), env, admin, DENOM.to_string()).unwrap();
+        assert_eq!(
+            res.messages[0].msg,
+            BankMsg::Send {
+                to_address: "fee_address".to_string(),
+                amount: coins(200, DENOM),
+            }
+            .into()
+        );
+    }
+}
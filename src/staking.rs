@@ -0,0 +1,528 @@
+//! The no-loss staking pool.
+//! Deposits are delegated to the configured validator and stay withdrawable,
+//! subject to the unbonding period. Only the staking rewards are raffled.
+//!
+//! Withdrawals are collected into batches and undelegated once per epoch,
+//! as the chain keeps only a few unbonding entries per delegation. The
+//! release time of a batch is not configured but taken from the
+//! `completion_time` of the `unbond` event the staking module emits for the
+//! undelegation, so it always matches the chain's unbonding time.
+
+use cosmwasm_std::{
+    coin, Addr, BankMsg, CosmosMsg, Deps, DepsMut, DistributionMsg, Env, MessageInfo, Order, Reply,
+    Response, StakingMsg, StdResult, Storage, SubMsg, SubMsgResult,
+};
+
+use crate::allowlist;
+use crate::contract::{generate_random_value, one_coin, select_winner, split_pot};
+use crate::events;
+use crate::governance;
+use crate::hooks::{self, HookMsg};
+use crate::msg::UserBetInfo;
+use crate::payout;
+use crate::query::query_config;
+use crate::solvency;
+use crate::state::{
+    RoundRecord, StakingPool, UnbondingBatch, UnbondingEntry, ROUND_FEES, ROUND_HISTORY,
+    STAKING_DEPOSITS, STAKING_POOL, UNBONDING_BATCHES, UNBONDING_QUEUE,
+};
+use crate::ContractError;
+
+/// Time between two undelegations, in seconds. Cosmos SDK chains keep at
+/// most 7 unbonding entries per delegation, which a 21-day unbonding time
+/// fills at one undelegation every 3 days.
+pub const UNBONDING_EPOCH: u64 = 3 * 24 * 60 * 60;
+/// Reply id of the undelegation of a batch, between the hook and payout ids.
+pub const UNBONDING_REPLY_ID: u64 = 20;
+
+pub fn configure_staking_pool(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    validator: String,
+) -> Result<Response, ContractError> {
+    let config = query_config(deps.as_ref())?;
+    if info.sender != config.fee_address {
+        return Err(ContractError::Unauthorized);
+    }
+
+    let existing = STAKING_POOL.may_load(deps.storage)?;
+    if let Some(pool) = &existing {
+        // Withdrawn stakes stay with the old validator until their batch is
+        // undelegated and released.
+        let unbonding = pool.open_batch.is_some()
+            || (pool.batch_count > 0
+                && !is_released(deps.storage, pool.batch_count, env.block.time.seconds())?);
+        if (pool.total_deposits > 0 || unbonding) && pool.validator != validator {
+            return Err(ContractError::ValidatorLocked);
+        }
+    }
+    if deps.querier.query_validator(validator.clone())?.is_none() {
+        return Err(ContractError::UnknownValidator { validator });
+    }
+
+    let pool = match existing {
+        Some(pool) => StakingPool { validator, ..pool },
+        None => StakingPool {
+            validator,
+            denom: deps.querier.query_bonded_denom()?,
+            total_deposits: 0,
+            prize_pot: 0,
+            round: 0,
+            open_batch: None,
+            batch_count: 0,
+        },
+    };
+    STAKING_POOL.save(deps.storage, &pool)?;
+    governance::fix_round_fee(deps.storage, "staking")?;
+
+    Ok(Response::new()
+        .add_attribute("action", "configure_staking_pool")
+        .add_attribute("validator", pool.validator)
+        .add_attribute("denom", pool.denom))
+}
+
+pub fn staking_deposit(
+    mut deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+) -> Result<Response, ContractError> {
+    let mut pool = load_staking_pool(deps.as_ref())?;
+
+    allowlist::assert_allowed(deps.as_ref(), &info.sender)?;
+    let stake = one_coin(&info)?;
+    if stake.denom != pool.denom {
+        return Err(ContractError::InvalidDenom {
+            expected: pool.denom,
+            received: stake.denom,
+        });
+    }
+    let funds_amount = stake.amount.u128();
+
+    // Changing the delegation withdraws the pending rewards as a side effect,
+    // so they are booked into the prize pot before that happens.
+    accrue_rewards(deps.branch(), &env, &mut pool)?;
+
+    let deposit = STAKING_DEPOSITS
+        .may_load(deps.storage, &info.sender)?
+        .unwrap_or_default()
+        + funds_amount;
+    STAKING_DEPOSITS.save(deps.storage, &info.sender, &deposit)?;
+    pool.total_deposits += funds_amount;
+    STAKING_POOL.save(deps.storage, &pool)?;
+
+    let delegate = StakingMsg::Delegate {
+        validator: pool.validator.clone(),
+        amount: coin(funds_amount, pool.denom.clone()),
+    };
+
+    Ok(Response::new()
+        .add_message(CosmosMsg::Staking(delegate))
+        .add_attribute("action", "staking_deposit")
+        .add_attribute("depositor", info.sender.to_string())
+        .add_attribute("amount", funds_amount.to_string()))
+}
+
+pub fn staking_withdraw(
+    mut deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    amount: u128,
+) -> Result<Response, ContractError> {
+    let mut pool = load_staking_pool(deps.as_ref())?;
+
+    let deposit = STAKING_DEPOSITS
+        .may_load(deps.storage, &info.sender)?
+        .unwrap_or_default();
+    if amount == 0 || amount > deposit {
+        return Err(ContractError::InvalidWithdrawal {
+            available: deposit,
+            denom: pool.denom,
+        });
+    }
+
+    if deposit == amount {
+        STAKING_DEPOSITS.remove(deps.storage, &info.sender);
+    } else {
+        STAKING_DEPOSITS.save(deps.storage, &info.sender, &(deposit - amount))?;
+    }
+    pool.total_deposits -= amount;
+
+    let batch_id = match pool.open_batch {
+        Some(batch_id) => batch_id,
+        None => {
+            pool.batch_count += 1;
+            pool.open_batch = Some(pool.batch_count);
+            pool.batch_count
+        }
+    };
+    let mut batch = UNBONDING_BATCHES
+        .may_load(deps.storage, batch_id)?
+        .unwrap_or(UnbondingBatch {
+            amount: 0,
+            opened_at: env.block.time.seconds(),
+            release_at: None,
+        });
+    batch.amount += amount;
+    UNBONDING_BATCHES.save(deps.storage, batch_id, &batch)?;
+
+    let mut queue = UNBONDING_QUEUE
+        .may_load(deps.storage, &info.sender)?
+        .unwrap_or_default();
+    queue.push(UnbondingEntry {
+        amount,
+        batch: batch_id,
+    });
+    UNBONDING_QUEUE.save(deps.storage, &info.sender, &queue)?;
+    solvency::owe(deps.storage, &pool.denom, amount)?;
+
+    let mut res = Response::new();
+    if let Some(undelegate) = submit_due_batch(deps.branch(), &env, &mut pool)? {
+        res = res.add_submessage(undelegate);
+    }
+    STAKING_POOL.save(deps.storage, &pool)?;
+
+    Ok(res
+        .add_attribute("action", "staking_withdraw")
+        .add_attribute("depositor", info.sender.to_string())
+        .add_attribute("amount", amount.to_string())
+        .add_attribute("batch", batch_id.to_string()))
+}
+
+pub fn submit_unbonding(
+    mut deps: DepsMut,
+    env: Env,
+    _info: MessageInfo,
+) -> Result<Response, ContractError> {
+    let mut pool = load_staking_pool(deps.as_ref())?;
+    let undelegate =
+        submit_due_batch(deps.branch(), &env, &mut pool)?.ok_or(ContractError::NoUnbondingDue)?;
+    STAKING_POOL.save(deps.storage, &pool)?;
+
+    Ok(Response::new()
+        .add_submessage(undelegate)
+        .add_attribute("action", "submit_unbonding")
+        .add_attribute("batch", pool.batch_count.to_string()))
+}
+
+/// Closes the open batch and undelegates it once it has collected
+/// withdrawals for a whole epoch.
+fn submit_due_batch(
+    deps: DepsMut,
+    env: &Env,
+    pool: &mut StakingPool,
+) -> Result<Option<SubMsg>, ContractError> {
+    let batch_id = match pool.open_batch {
+        Some(batch_id) => batch_id,
+        None => return Ok(None),
+    };
+    let batch = UNBONDING_BATCHES.load(deps.storage, batch_id)?;
+    if batch.opened_at + UNBONDING_EPOCH > env.block.time.seconds() {
+        return Ok(None);
+    }
+
+    // Undelegating withdraws the pending rewards, as changing a delegation
+    // does.
+    accrue_rewards(deps, env, pool)?;
+    pool.open_batch = None;
+    let undelegate = StakingMsg::Undelegate {
+        validator: pool.validator.clone(),
+        amount: coin(batch.amount, pool.denom.clone()),
+    };
+    Ok(Some(SubMsg::reply_on_success(
+        undelegate,
+        UNBONDING_REPLY_ID,
+    )))
+}
+
+/// Records when the batch just undelegated, always the latest one, is
+/// released, from the `unbond` event of the staking module.
+pub(crate) fn unbonding_submitted(deps: DepsMut, msg: Reply) -> Result<Response, ContractError> {
+    let events = match msg.result {
+        SubMsgResult::Ok(response) => response.events,
+        SubMsgResult::Err(_) => return Err(ContractError::UnknownCompletionTime),
+    };
+    let release_at = events
+        .iter()
+        .filter(|event| event.ty == "unbond")
+        .flat_map(|event| event.attributes.iter())
+        .find(|attr| attr.key == "completion_time")
+        .and_then(|attr| parse_completion_time(&attr.value))
+        .ok_or(ContractError::UnknownCompletionTime)?;
+
+    let batch_id = load_staking_pool(deps.as_ref())?.batch_count;
+    let mut batch = UNBONDING_BATCHES.load(deps.storage, batch_id)?;
+    batch.release_at = Some(release_at);
+    UNBONDING_BATCHES.save(deps.storage, batch_id, &batch)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "unbonding_submitted")
+        .add_attribute("batch", batch_id.to_string())
+        .add_attribute("release_at", release_at.to_string()))
+}
+
+/// Seconds since the unix epoch of an RFC 3339 UTC time such as
+/// `2024-05-01T12:00:00Z`, the format of the staking module's completion
+/// times. Fractions of a second are dropped.
+fn parse_completion_time(value: &str) -> Option<u64> {
+    let (date, time) = value.split_once('T')?;
+    let time = time.strip_suffix('Z')?;
+    let time = time.split('.').next()?;
+    let date: Vec<u64> = date
+        .split('-')
+        .map(|part| part.parse().ok())
+        .collect::<Option<_>>()?;
+    let time: Vec<u64> = time
+        .split(':')
+        .map(|part| part.parse().ok())
+        .collect::<Option<_>>()?;
+    match (date.as_slice(), time.as_slice()) {
+        (&[year, month, day], &[hour, minute, second])
+            if year >= 1970
+                && (1..=12).contains(&month)
+                && (1..=31).contains(&day)
+                && hour < 24
+                && minute < 60
+                && second < 60 =>
+        {
+            // Days since 1970-01-01 of the proleptic Gregorian calendar,
+            // counting years from March so the leap day comes last.
+            let year = if month <= 2 { year - 1 } else { year };
+            let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+            let days = year * 365 + year / 4 - year / 100 + year / 400 + day_of_year - 719_468;
+            Some(days * 86_400 + hour * 3_600 + minute * 60 + second)
+        }
+        _ => None,
+    }
+}
+
+/// Whether the withdrawals of `batch_id` are back and can be claimed.
+fn is_released(storage: &dyn Storage, batch_id: u64, now: u64) -> StdResult<bool> {
+    Ok(matches!(
+        UNBONDING_BATCHES.may_load(storage, batch_id)?,
+        Some(UnbondingBatch { release_at: Some(release_at), .. }) if release_at <= now
+    ))
+}
+
+/// Pays out every unbonding entry of the sender whose batch has been
+/// released.
+pub fn claim_unbonded(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+) -> Result<Response, ContractError> {
+    let pool = load_staking_pool(deps.as_ref())?;
+    let queue = UNBONDING_QUEUE
+        .may_load(deps.storage, &info.sender)?
+        .unwrap_or_default();
+
+    let now = env.block.time.seconds();
+    let mut matured = vec![];
+    let mut pending = vec![];
+    for entry in queue {
+        if is_released(deps.storage, entry.batch, now)? {
+            matured.push(entry);
+        } else {
+            pending.push(entry);
+        }
+    }
+    let claim_amount: u128 = matured.iter().map(|entry| entry.amount).sum();
+    if claim_amount == 0 {
+        return Err(ContractError::NothingToClaim);
+    }
+
+    if pending.is_empty() {
+        UNBONDING_QUEUE.remove(deps.storage, &info.sender);
+    } else {
+        UNBONDING_QUEUE.save(deps.storage, &info.sender, &pending)?;
+    }
+    solvency::discharge(deps.storage, &pool.denom, claim_amount)?;
+
+    let transfer_unbonded = BankMsg::Send {
+        to_address: info.sender.to_string(),
+        amount: vec![coin(claim_amount, pool.denom)],
+    };
+
+    Ok(Response::new()
+        .add_message(CosmosMsg::Bank(transfer_unbonded))
+        .add_attribute("action", "claim_unbonded")
+        .add_attribute("depositor", info.sender.to_string())
+        .add_attribute("amount", claim_amount.to_string()))
+}
+
+/// Withdraws the pending delegation rewards into the prize pot.
+pub fn harvest_staking_rewards(
+    mut deps: DepsMut,
+    env: Env,
+    _info: MessageInfo,
+) -> Result<Response, ContractError> {
+    let mut pool = load_staking_pool(deps.as_ref())?;
+    let rewards = accrue_rewards(deps.branch(), &env, &mut pool)?;
+    STAKING_POOL.save(deps.storage, &pool)?;
+
+    let mut res = Response::new();
+    if rewards > 0 {
+        res = res.add_message(CosmosMsg::Distribution(
+            DistributionMsg::WithdrawDelegatorReward {
+                validator: pool.validator.clone(),
+            },
+        ));
+    }
+    Ok(res
+        .add_attribute("action", "harvest_staking_rewards")
+        .add_attribute("rewards", rewards.to_string())
+        .add_attribute("prize_pot", pool.prize_pot.to_string()))
+}
+
+/// Raffles the harvested rewards among depositors, weighted by principal.
+pub fn end_game_staking(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    seed: u64,
+) -> Result<Response, ContractError> {
+    let config = query_config(deps.as_ref())?;
+    if info.sender != config.fee_address {
+        return Err(ContractError::Unauthorized);
+    }
+    let mut pool = load_staking_pool(deps.as_ref())?;
+
+    if pool.prize_pot == 0 {
+        return Err(ContractError::EmptyPot {
+            pool: String::from("staking"),
+        });
+    }
+
+    let deposits = STAKING_DEPOSITS
+        .range(deps.storage, None, None, Order::Ascending)
+        .collect::<StdResult<Vec<(Addr, u128)>>>()?;
+    let bets: Vec<UserBetInfo> = deposits
+        .into_iter()
+        .enumerate()
+        .map(|(order_id, (user_address, user_amount))| UserBetInfo {
+            user_address,
+            user_amount,
+            user_order: order_id as u128 + 1,
+            coin_type: pool.denom.clone(),
+            tickets: None,
+            nft_token_id: None,
+            nft_collection: None,
+            client_seed: None,
+            normalized_value: None,
+            free_bet: false,
+        })
+        .collect();
+
+    let random_number = generate_random_value(seed);
+    let winner_address = match select_winner(&bets, pool.total_deposits, random_number) {
+        Some(winner) => winner.user_address.clone(),
+        None => return Err(ContractError::NoWinner),
+    };
+
+    let (fees, prize) = split_pot(
+        pool.prize_pot,
+        governance::round_fee_bps(deps.storage, "staking")?,
+    );
+    let payouts = [
+        (payout::FEE_REPLY_ID, &config.fee_address, fees),
+        (payout::PRIZE_REPLY_ID, &winner_address, prize),
+    ];
+    let mut submessages = vec![];
+    for &(reply_id, recipient, amount) in &payouts {
+        submessages.extend(payout::payout_msg(
+            deps.storage,
+            reply_id,
+            recipient,
+            coin(amount, pool.denom.clone()),
+            "staking",
+            pool.round,
+        )?);
+    }
+
+    let pot = coin(pool.prize_pot, pool.denom.clone());
+    let round_settled =
+        events::round_settled("staking", pool.round, &winner_address, &pot, prize, fees);
+    submessages.extend(hooks::hook_msgs(
+        deps.storage,
+        &HookMsg::RoundSettledHook {
+            pool_id: "staking".to_string(),
+            round_id: pool.round,
+            winner: winner_address.clone(),
+            pots: vec![pot.clone()],
+        },
+    )?);
+    ROUND_HISTORY.save(
+        deps.storage,
+        ("staking", pool.round),
+        &RoundRecord {
+            winner: winner_address,
+            pot,
+            prize,
+            fee: fees,
+            settled_at: env.block.time.seconds(),
+            failed_payouts: vec![],
+            other_pots: vec![],
+            bonus: vec![],
+        },
+    )?;
+    solvency::discharge(deps.storage, &pool.denom, pool.prize_pot)?;
+    pool.prize_pot = 0;
+    pool.round += 1;
+    STAKING_POOL.save(deps.storage, &pool)?;
+    // The next round opens now, at the current fee.
+    ROUND_FEES.remove(deps.storage, "staking");
+    governance::fix_round_fee(deps.storage, "staking")?;
+
+    Ok(Response::new()
+        .add_submessages(submessages)
+        .add_attribute("action", "end_game")
+        .add_event(round_settled))
+}
+
+fn load_staking_pool(deps: Deps) -> Result<StakingPool, ContractError> {
+    STAKING_POOL
+        .may_load(deps.storage)?
+        .ok_or(ContractError::StakingNotConfigured)
+}
+
+/// Adds the rewards currently pending on the delegation to the prize pot and
+/// returns the amount added.
+fn accrue_rewards(deps: DepsMut, env: &Env, pool: &mut StakingPool) -> StdResult<u128> {
+    let rewards = match deps
+        .querier
+        .query_delegation(env.contract.address.clone(), pool.validator.clone())?
+    {
+        Some(delegation) => delegation
+            .accumulated_rewards
+            .iter()
+            .filter(|c| c.denom == pool.denom)
+            .map(|c| c.amount.u128())
+            .sum(),
+        None => 0,
+    };
+    pool.prize_pot += rewards;
+    solvency::owe(deps.storage, &pool.denom, rewards)?;
+    Ok(rewards)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_completion_time() {
+        assert_eq!(Some(0), parse_completion_time("1970-01-01T00:00:00Z"));
+        assert_eq!(
+            Some(1_709_210_096),
+            parse_completion_time("2024-02-29T12:34:56Z")
+        );
+        // Nanoseconds, as some chains print them, are dropped.
+        assert_eq!(
+            Some(1_709_210_096),
+            parse_completion_time("2024-02-29T12:34:56.789012345Z")
+        );
+        assert_eq!(None, parse_completion_time("2024-02-29T12:34:56+02:00"));
+        assert_eq!(None, parse_completion_time("2024-13-01T00:00:00Z"));
+        assert_eq!(None, parse_completion_time("1714564800"));
+    }
+}
@@ -1,11 +1,14 @@
 //! Defines the state and tokeninfo structs
 
-use crate::msg::UserBetInfo;
+use crate::msg::{
+    AllowlistConfig, CoinSide, DenomRate, LoyaltyConfig, NftConfig, OracleConfig, PoolId,
+    RandomnessMode, TicketConfig, UserBetInfo, WagerLimits,
+};
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-use cosmwasm_std::Addr;
-use cw_storage_plus::Item;
+use cosmwasm_std::{Addr, Coin};
+use cw_storage_plus::{Item, Map};
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct State {
@@ -24,4 +27,286 @@ pub struct State {
     pub start_flag_shrimp: bool,
 }
 
+impl State {
+    pub fn user_list(&self, pool: PoolId) -> &Vec<UserBetInfo> {
+        match pool {
+            PoolId::Infinite => &self.user_list_infinte,
+            PoolId::Whale => &self.user_list_whale,
+            PoolId::Shrimp => &self.user_list_shrimp,
+        }
+    }
+
+    pub fn user_list_mut(&mut self, pool: PoolId) -> &mut Vec<UserBetInfo> {
+        match pool {
+            PoolId::Infinite => &mut self.user_list_infinte,
+            PoolId::Whale => &mut self.user_list_whale,
+            PoolId::Shrimp => &mut self.user_list_shrimp,
+        }
+    }
+
+    pub fn pot_total(&self, pool: PoolId) -> u128 {
+        match pool {
+            PoolId::Infinite => self.pot_total_infinte,
+            PoolId::Whale => self.pot_total_whale,
+            PoolId::Shrimp => self.pot_total_shrimp,
+        }
+    }
+
+    pub fn pot_total_mut(&mut self, pool: PoolId) -> &mut u128 {
+        match pool {
+            PoolId::Infinite => &mut self.pot_total_infinte,
+            PoolId::Whale => &mut self.pot_total_whale,
+            PoolId::Shrimp => &mut self.pot_total_shrimp,
+        }
+    }
+
+    /// Whether the pool is taking bets.
+    pub fn start_flag(&self, pool: PoolId) -> bool {
+        match pool {
+            PoolId::Infinite => self.start_flag_infinte,
+            PoolId::Whale => self.start_flag_whale,
+            PoolId::Shrimp => self.start_flag_shrimp,
+        }
+    }
+
+    pub fn start_flag_mut(&mut self, pool: PoolId) -> &mut bool {
+        match pool {
+            PoolId::Infinite => &mut self.start_flag_infinte,
+            PoolId::Whale => &mut self.start_flag_whale,
+            PoolId::Shrimp => &mut self.start_flag_shrimp,
+        }
+    }
+}
+
 pub const CONFIG: Item<State> = Item::new("config");
+/// Whether bets and deposits are refused contract-wide.
+pub const PAUSED: Item<bool> = Item::new("paused");
+/// Share of every settled pot that goes to the fee address, in basis
+/// points. `governance::DEFAULT_HOUSE_FEE_BPS` if unset.
+pub const HOUSE_FEE_BPS: Item<u64> = Item::new("house_fee_bps");
+/// House fee of each open round, fixed when the round opened, keyed by pool
+/// id (`"staking"` for the staking pool).
+pub const ROUND_FEES: Map<&str, u64> = Map::new("round_fees");
+/// Ticket sales of the pools that sell tickets, keyed by pool id.
+pub const TICKET_CONFIGS: Map<&str, TicketConfig> = Map::new("ticket_configs");
+/// Round currently taking bets in each pool, keyed by pool id.
+pub const ROUND_IDS: Map<&str, u64> = Map::new("round_ids");
+
+/// A settled round of a pool or of the staking pool.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct RoundRecord {
+    pub winner: Addr,
+    pub pot: Coin,
+    pub prize: u128,
+    pub fee: u128,
+    pub settled_at: u64,
+    /// Recipients whose payout bounced and was moved to the escrow.
+    pub failed_payouts: Vec<Addr>,
+    /// Pots in other denoms of a multi-denom pool, settled alongside `pot`.
+    pub other_pots: Vec<SettledPot>,
+    /// House money added to the round, paid to the winner on top of the
+    /// prizes above.
+    pub bonus: Vec<Coin>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct SettledPot {
+    pub pot: Coin,
+    pub prize: u128,
+    pub fee: u128,
+}
+
+/// Settled rounds keyed by pool id (`"staking"` for the staking pool) and
+/// round id.
+pub const ROUND_HISTORY: Map<(&str, u64), RoundRecord> = Map::new("round_history");
+
+/// A payout in flight, kept until its submessage has run.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PendingPayout {
+    pub recipient: Addr,
+    pub amount: Coin,
+    pub pool_id: String,
+    pub round_id: u64,
+}
+
+pub const PENDING_PAYOUTS: Map<u64, PendingPayout> = Map::new("pending_payouts");
+/// Payouts that could not be delivered, claimable by their recipient.
+pub const ESCROW: Map<&Addr, Vec<Coin>> = Map::new("escrow");
+
+/// What the contract owes per denom, kept up to date wherever money is taken
+/// in for someone or paid out.
+pub const LIABILITIES: Map<&str, u128> = Map::new("liabilities");
+
+/// Time at which each scheduled pool stops taking bets.
+pub const CLOSE_TIMES: Map<&str, u64> = Map::new("close_times");
+
+/// Withdrawal penalty of each pool in basis points, zero if unset.
+pub const WITHDRAWAL_PENALTIES: Map<&str, u64> = Map::new("withdrawal_penalties");
+
+/// Accepted denoms of the pools that take several, keyed by pool id.
+pub const POOL_DENOMS: Map<&str, Vec<DenomRate>> = Map::new("pool_denoms");
+
+/// Price oracle weighing the bets of multi-denom pools, if any.
+pub const ORACLE_CONFIG: Item<OracleConfig> = Item::new("oracle_config");
+
+/// House money added to the open round of each pool, keyed by pool id.
+pub const POT_BONUSES: Map<&str, Vec<Coin>> = Map::new("pot_bonuses");
+
+/// Randomness mode of each pool, `RandomnessMode::Seed` if unset.
+pub const RANDOMNESS_MODES: Map<&str, RandomnessMode> = Map::new("randomness_modes");
+
+pub const NFT_CONFIG: Item<NftConfig> = Item::new("nft_config");
+
+/// Prize held for a winning ticket NFT of `collection`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct NftPrize {
+    pub collection: Addr,
+    pub prize: Vec<Coin>,
+}
+
+/// Prizes held for winning ticket NFTs, keyed by token id.
+pub const NFT_PRIZES: Map<&str, NftPrize> = Map::new("nft_prizes");
+/// Ticket NFTs minted in each round, keyed by pool id and round id.
+pub const NFT_TICKET_COUNTS: Map<(&str, u64), u64> = Map::new("nft_ticket_counts");
+
+pub const ALLOWLIST_CONFIG: Item<AllowlistConfig> = Item::new("allowlist_config");
+pub const ALLOWLIST: Map<&Addr, ()> = Map::new("allowlist");
+
+pub const LOYALTY_CONFIG: Item<LoyaltyConfig> = Item::new("loyalty_config");
+
+/// Loyalty points of an address. `lifetime_points` never goes down and sets
+/// the tier.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, JsonSchema)]
+pub struct LoyaltyAccount {
+    pub points: u128,
+    pub lifetime_points: u128,
+}
+
+pub const LOYALTY_ACCOUNTS: Map<&Addr, LoyaltyAccount> = Map::new("loyalty_accounts");
+/// Free-bet credits redeemed from points, not yet bet.
+pub const FREE_BET_CREDITS: Map<&Addr, Vec<Coin>> = Map::new("free_bet_credits");
+
+/// Contracts notified of bets and settled rounds.
+pub const HOOKS: Map<&Addr, ()> = Map::new("hooks");
+
+/// The no-loss pool: deposits are delegated to `validator` and only the
+/// rewards collected in `prize_pot` are raffled each round.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct StakingPool {
+    pub validator: String,
+    pub denom: String,
+    pub total_deposits: u128,
+    pub prize_pot: u128,
+    pub round: u64,
+    /// Batch collecting the withdrawals not undelegated yet, if any.
+    pub open_batch: Option<u64>,
+    /// Batches opened so far, which is also the id of the latest one.
+    pub batch_count: u64,
+}
+
+/// Withdrawals undelegated together. `release_at` is the completion time
+/// the chain reported once the undelegation went through.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct UnbondingBatch {
+    pub amount: u128,
+    pub opened_at: u64,
+    pub release_at: Option<u64>,
+}
+
+/// A withdrawal waiting for its batch to be released.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct UnbondingEntry {
+    pub amount: u128,
+    pub batch: u64,
+}
+
+pub const STAKING_POOL: Item<StakingPool> = Item::new("staking_pool");
+pub const STAKING_DEPOSITS: Map<&Addr, u128> = Map::new("staking_deposits");
+pub const UNBONDING_BATCHES: Map<u64, UnbondingBatch> = Map::new("unbonding_batches");
+pub const UNBONDING_QUEUE: Map<&Addr, Vec<UnbondingEntry>> = Map::new("unbonding_queue");
+
+/// A 1v1 coin flip. `opponent` is set once the challenge has been matched,
+/// and `expires_at` then moves to the resolution deadline.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Challenge {
+    pub id: u64,
+    pub creator: Addr,
+    pub side: CoinSide,
+    pub amount: u128,
+    pub coin_type: String,
+    pub expires_at: u64,
+    pub opponent: Option<Addr>,
+    /// House fee when the challenge was opened.
+    pub fee_bps: u64,
+}
+
+pub const CHALLENGE_COUNT: Item<u64> = Item::new("challenge_count");
+pub const CHALLENGES: Map<u64, Challenge> = Map::new("challenges");
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum MarketStatus {
+    Open,
+    Resolved,
+    Invalid,
+}
+
+/// A parimutuel market. `outcome_totals` holds the stake on each outcome and
+/// `resolved_at` starts the dispute window once a result is posted.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Market {
+    pub id: u64,
+    pub question: String,
+    pub outcomes: Vec<String>,
+    pub resolver: Addr,
+    pub coin_type: String,
+    pub closes_at: u64,
+    pub dispute_window: u64,
+    pub outcome_totals: Vec<u128>,
+    pub pot_total: u128,
+    pub status: MarketStatus,
+    pub winning_outcome: Option<u32>,
+    pub resolved_at: u64,
+    /// Stakes and fees already paid out of `pot_total`.
+    pub paid_out: u128,
+    /// House fee when the market was created.
+    pub fee_bps: u64,
+    /// Winning stake whose claim has been paid. Once it reaches the total on
+    /// the winning outcome, the market is fully paid.
+    pub claimed_stake: u128,
+}
+
+pub const MARKET_COUNT: Item<u64> = Item::new("market_count");
+pub const MARKETS: Map<u64, Market> = Map::new("markets");
+pub const MARKET_POSITIONS: Map<(u64, &Addr), Vec<u128>> = Map::new("market_positions");
+
+/// Responsible-gambling settings of an address, and what it wagered in the
+/// current periods.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, JsonSchema)]
+pub struct PlayerLimits {
+    pub limits: WagerLimits,
+    /// Raised limits that replace `limits` once the cooldown has passed.
+    pub pending: Option<PendingLimits>,
+    pub excluded_until: u64,
+    pub wagered: WagerTotals,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PendingLimits {
+    pub limits: WagerLimits,
+    pub effective_at: u64,
+}
+
+/// Amounts wagered in the day, week and 30 days numbered `day`, `week` and
+/// `month` since the unix epoch.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, JsonSchema)]
+pub struct WagerTotals {
+    pub day: u64,
+    pub daily: u128,
+    pub week: u64,
+    pub weekly: u128,
+    pub month: u64,
+    pub monthly: u128,
+}
+
+pub const PLAYER_LIMITS: Map<&Addr, PlayerLimits> = Map::new("player_limits");
@@ -0,0 +1,225 @@
+//! Full round lifecycles on a simulated chain with real bank balances.
+
+use cosmwasm_std::{coin, coins, Addr, Empty};
+use cw_multi_test::{App, Contract, ContractWrapper, Executor};
+
+use sefi_bet_contract::contract::{execute, instantiate, reply};
+use sefi_bet_contract::msg::{ExecuteMsg, InstantiateMsg, PoolId, QueryMsg};
+use sefi_bet_contract::query::query;
+use sefi_bet_contract::state::RoundRecord;
+
+const DENOM: &str = "usei";
+const ADMIN: &str = "fee_address";
+const ALICE: &str = "alice";
+const BOB: &str = "bob";
+const INITIAL_BALANCE: u128 = 10_000;
+
+fn bet_contract() -> Box<dyn Contract<Empty>> {
+    Box::new(ContractWrapper::new(execute, instantiate, query).with_reply(reply))
+}
+
+fn setup() -> (App, Addr) {
+    let mut app = App::new(|router, _, storage| {
+        for player in &[ALICE, BOB] {
+            router
+                .bank
+                .init_balance(
+                    storage,
+                    &Addr::unchecked(*player),
+                    coins(INITIAL_BALANCE, DENOM),
+                )
+                .unwrap();
+        }
+    });
+    let code_id = app.store_code(bet_contract());
+    let contract = app
+        .instantiate_contract(
+            code_id,
+            Addr::unchecked(ADMIN),
+            &InstantiateMsg {
+                fee_address: Addr::unchecked(ADMIN),
+                start_flag: true,
+            },
+            &[],
+            "sefi-bet",
+            None,
+        )
+        .unwrap();
+    (app, contract)
+}
+
+fn balance(app: &App, address: &str) -> u128 {
+    app.wrap()
+        .query_balance(address, DENOM)
+        .unwrap()
+        .amount
+        .u128()
+}
+
+fn bet_msg(pool: PoolId) -> ExecuteMsg {
+    match pool {
+        PoolId::Infinite => ExecuteMsg::AddBetUserInfinite { client_seed: None },
+        PoolId::Whale => ExecuteMsg::AddBetUserWhale { client_seed: None },
+        PoolId::Shrimp => ExecuteMsg::AddBetUserShrimp { client_seed: None },
+    }
+}
+
+fn toggle_msg(pool: PoolId) -> ExecuteMsg {
+    match pool {
+        PoolId::Infinite => ExecuteMsg::ToggleGameInfinte {},
+        PoolId::Whale => ExecuteMsg::ToggleGameWhale {},
+        PoolId::Shrimp => ExecuteMsg::ToggleGameShrimp {},
+    }
+}
+
+fn end_game_msg(pool: PoolId, seed: u64) -> ExecuteMsg {
+    match pool {
+        PoolId::Infinite => ExecuteMsg::EndGameInfinte { seed },
+        PoolId::Whale => ExecuteMsg::EndGameWhale { seed },
+        PoolId::Shrimp => ExecuteMsg::EndGameShrimp { seed },
+    }
+}
+
+/// Alice stakes 60% and Bob 40% of the pot, so seeds 7 (5898) and 1 (8748)
+/// pick Alice and Bob respectively.
+fn play_round(
+    app: &mut App,
+    contract: &Addr,
+    pool: PoolId,
+    round_id: u64,
+    stakes: (u128, u128),
+    seed: u64,
+    expected_winner: &str,
+) {
+    let admin = Addr::unchecked(ADMIN);
+    let before = [balance(app, ALICE), balance(app, BOB), balance(app, ADMIN)];
+
+    for &(player, stake) in &[(ALICE, stakes.0), (BOB, stakes.1)] {
+        app.execute_contract(
+            Addr::unchecked(player),
+            contract.clone(),
+            &bet_msg(pool),
+            &coins(stake, DENOM),
+        )
+        .unwrap();
+    }
+    let pot = stakes.0 + stakes.1;
+    assert_eq!(pot, balance(app, contract.as_str()));
+
+    // The round can only be drawn once closed.
+    app.execute_contract(
+        admin.clone(),
+        contract.clone(),
+        &end_game_msg(pool, seed),
+        &[],
+    )
+    .unwrap_err();
+    app.execute_contract(admin.clone(), contract.clone(), &toggle_msg(pool), &[])
+        .unwrap();
+    app.execute_contract(admin, contract.clone(), &end_game_msg(pool, seed), &[])
+        .unwrap();
+
+    let fee = pot * 4 / 100;
+    let prize = pot - fee;
+    let record: Option<RoundRecord> = app
+        .wrap()
+        .query_wasm_smart(
+            contract.to_string(),
+            &QueryMsg::Round {
+                pool_id: pool.to_string(),
+                round_id,
+            },
+        )
+        .unwrap();
+    let record = record.unwrap();
+    assert_eq!(Addr::unchecked(expected_winner), record.winner);
+    assert_eq!((prize, fee), (record.prize, record.fee));
+    assert_eq!(coin(pot, DENOM), record.pot);
+
+    let (alice_prize, bob_prize) = if expected_winner == ALICE {
+        (prize, 0)
+    } else {
+        (0, prize)
+    };
+    assert_eq!(before[0] - stakes.0 + alice_prize, balance(app, ALICE));
+    assert_eq!(before[1] - stakes.1 + bob_prize, balance(app, BOB));
+    assert_eq!(before[2] + fee, balance(app, ADMIN));
+    assert_eq!(0, balance(app, contract.as_str()));
+}
+
+#[test]
+fn shrimp_round_lifecycle() {
+    let (mut app, contract) = setup();
+    play_round(&mut app, &contract, PoolId::Shrimp, 1, (30, 20), 7, ALICE);
+    play_round(&mut app, &contract, PoolId::Shrimp, 2, (30, 20), 1, BOB);
+}
+
+#[test]
+fn whale_round_lifecycle() {
+    let (mut app, contract) = setup();
+    play_round(&mut app, &contract, PoolId::Whale, 1, (300, 200), 7, ALICE);
+    play_round(&mut app, &contract, PoolId::Whale, 2, (300, 200), 1, BOB);
+}
+
+#[test]
+fn infinite_round_lifecycle() {
+    let (mut app, contract) = setup();
+    play_round(
+        &mut app,
+        &contract,
+        PoolId::Infinite,
+        1,
+        (3000, 2000),
+        7,
+        ALICE,
+    );
+    play_round(
+        &mut app,
+        &contract,
+        PoolId::Infinite,
+        2,
+        (3000, 2000),
+        1,
+        BOB,
+    );
+}
+
+#[test]
+fn tiers_settle_independently() {
+    let (mut app, contract) = setup();
+    // An open shrimp bet stays in the contract while the whale round settles.
+    app.execute_contract(
+        Addr::unchecked(BOB),
+        contract.clone(),
+        &bet_msg(PoolId::Shrimp),
+        &coins(10, DENOM),
+    )
+    .unwrap();
+    let before = balance(&app, ALICE);
+    app.execute_contract(
+        Addr::unchecked(ALICE),
+        contract.clone(),
+        &bet_msg(PoolId::Whale),
+        &coins(100, DENOM),
+    )
+    .unwrap();
+    let admin = Addr::unchecked(ADMIN);
+    app.execute_contract(
+        admin.clone(),
+        contract.clone(),
+        &toggle_msg(PoolId::Whale),
+        &[],
+    )
+    .unwrap();
+    app.execute_contract(
+        admin,
+        contract.clone(),
+        &end_game_msg(PoolId::Whale, 7),
+        &[],
+    )
+    .unwrap();
+
+    assert_eq!(before - 4, balance(&app, ALICE));
+    assert_eq!(4, balance(&app, ADMIN));
+    assert_eq!(10, balance(&app, contract.as_str()));
+}
@@ -0,0 +1,132 @@
+//! Property tests of the betting and settlement math over arbitrary bets,
+//! denoms and seeds.
+
+use cosmwasm_std::testing::{mock_dependencies_with_balance, mock_env, mock_info};
+use cosmwasm_std::{coin, from_json, Addr, BankMsg, CosmosMsg};
+use proptest::prelude::*;
+
+use sefi_bet_contract::contract::{execute, instantiate};
+use sefi_bet_contract::msg::{ExecuteMsg, InstantiateMsg, PoolId, QueryMsg, SimulateBetResponse};
+use sefi_bet_contract::query::query;
+use sefi_bet_contract::state::RoundRecord;
+use sefi_bet_contract::ContractError;
+
+const ADMIN: &str = "fee_address";
+const DENOMS: [&str; 2] = ["usei", "uatom"];
+const PLAYERS: [&str; 4] = ["alice", "bob", "carol", "dave"];
+/// Keeps the sum of up to 32 bets within `u128`.
+const MAX_STAKE: u128 = u128::MAX / 64;
+
+fn bets() -> impl Strategy<Value = Vec<(usize, usize, u128)>> {
+    prop::collection::vec((0..PLAYERS.len(), 0..DENOMS.len(), 1..=MAX_STAKE), 1..32)
+}
+
+proptest! {
+    #[test]
+    fn settlement_invariants(bets in bets(), seed in any::<u64>()) {
+        let balance = [coin(u128::MAX, DENOMS[0]), coin(u128::MAX, DENOMS[1])];
+        let mut deps = mock_dependencies_with_balance(&balance);
+        let admin = mock_info(ADMIN, &[]);
+        let msg = InstantiateMsg {
+            fee_address: Addr::unchecked(ADMIN),
+            start_flag: true,
+        };
+        instantiate(deps.as_mut(), mock_env(), admin.clone(), msg).unwrap();
+
+        // The round is held in the denom of its first bet.
+        let round_denom = DENOMS[bets[0].1];
+        let mut pot = 0;
+        let mut bettors = vec![];
+        for &(player, denom, amount) in &bets {
+            let info = mock_info(PLAYERS[player], &[coin(amount, DENOMS[denom])]);
+            let msg = ExecuteMsg::AddBetUserInfinite { client_seed: None };
+            let res = execute(deps.as_mut(), mock_env(), info, msg);
+            if DENOMS[denom] == round_denom {
+                prop_assert!(res.is_ok());
+                pot += amount;
+                bettors.push(Addr::unchecked(PLAYERS[player]));
+            } else {
+                let is_invalid_denom = matches!(res, Err(ContractError::InvalidDenom { .. }));
+                prop_assert!(is_invalid_denom);
+            }
+        }
+
+        execute(deps.as_mut(), mock_env(), admin.clone(), ExecuteMsg::ToggleGameInfinte {})
+            .unwrap();
+        let res = execute(deps.as_mut(), mock_env(), admin, ExecuteMsg::EndGameInfinte { seed });
+        // A non-empty pot always has a winner.
+        let res = res.unwrap();
+
+        let record: Option<RoundRecord> = from_json(
+            query(
+                deps.as_ref(),
+                mock_env(),
+                QueryMsg::Round {
+                    pool_id: PoolId::Infinite.to_string(),
+                    round_id: 1,
+                },
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        let record = record.unwrap();
+        prop_assert_eq!(coin(pot, round_denom), record.pot.clone());
+        prop_assert_eq!(pot, record.fee + record.prize);
+        prop_assert!(bettors.contains(&record.winner));
+
+        // The fee and the prize are the only payouts.
+        let payouts: Vec<_> = res
+            .messages
+            .iter()
+            .map(|sub_msg| match &sub_msg.msg {
+                CosmosMsg::Bank(BankMsg::Send { to_address, amount }) => {
+                    (to_address.clone(), amount.clone())
+                }
+                msg => panic!("unexpected message {:?}", msg),
+            })
+            .collect();
+        let mut expected = vec![];
+        if record.fee > 0 {
+            expected.push((ADMIN.to_string(), vec![coin(record.fee, round_denom)]));
+        }
+        expected.push((record.winner.to_string(), vec![coin(record.prize, round_denom)]));
+        prop_assert_eq!(expected, payouts);
+    }
+
+    #[test]
+    fn simulated_odds_never_overflow(
+        stake in 1..=u128::MAX / 2,
+        amount in 1..=u128::MAX / 2,
+    ) {
+        let mut deps = mock_dependencies_with_balance(&[coin(u128::MAX, DENOMS[0])]);
+        let msg = InstantiateMsg {
+            fee_address: Addr::unchecked(ADMIN),
+            start_flag: true,
+        };
+        instantiate(deps.as_mut(), mock_env(), mock_info(ADMIN, &[]), msg).unwrap();
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("alice", &[coin(stake, DENOMS[0])]),
+            ExecuteMsg::AddBetUserInfinite { client_seed: None },
+        )
+        .unwrap();
+
+        let res: SimulateBetResponse = from_json(
+            query(
+                deps.as_ref(),
+                mock_env(),
+                QueryMsg::SimulateBet {
+                    pool_id: PoolId::Infinite,
+                    address: Addr::unchecked("bob"),
+                    amount,
+                    denom: None,
+                },
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        prop_assert!(res.win_probability <= 10000);
+        prop_assert_eq!(res.gross_prize, res.fee + res.net_payout);
+    }
+}
@@ -0,0 +1,556 @@
+//! The no-loss staking pool on a simulated chain.
+//! cw-multi-test 0.13 only ships a staking module that fails every message,
+//! so `StakeKeeper` stands in for the chain's. Like the SDK, it holds the
+//! delegated coins, reports the completion time of an undelegation in its
+//! `unbond` event, keeps at most 7 unbonding entries per delegation, pays
+//! out matured unbondings at the end of a block and pays the pending
+//! rewards whenever a delegation changes. A distribution module cannot be
+//! supplied, so rewards are only withdrawn through delegation changes.
+
+use anyhow::{bail, Result as AnyResult};
+use cosmwasm_std::testing::{MockApi, MockStorage};
+use cosmwasm_std::{
+    coin, coins, to_json_binary, Addr, Api, BankMsg, Binary, BlockInfo, BondedDenomResponse,
+    CustomQuery, Decimal, DelegationResponse, Empty, Event, FullDelegation, Querier, StakingMsg,
+    StakingQuery, Storage, Validator, ValidatorResponse,
+};
+use cw_multi_test::{
+    App, AppBuilder, AppResponse, BankKeeper, BankSudo, BasicApp, Contract, ContractWrapper,
+    CosmosRouter, Executor, FailingDistribution, Module, Staking, StakingSudo, WasmKeeper,
+};
+use cw_storage_plus::{Item, Map};
+use schemars::JsonSchema;
+use serde::de::DeserializeOwned;
+
+use sefi_bet_contract::contract::{execute, instantiate, reply};
+use sefi_bet_contract::msg::{
+    ExecuteMsg, InstantiateMsg, QueryMsg, SolvencyResponse, UnbondingResponse,
+};
+use sefi_bet_contract::query::query;
+use sefi_bet_contract::staking::UNBONDING_EPOCH;
+use sefi_bet_contract::state::StakingPool;
+use sefi_bet_contract::ContractError;
+
+const DENOM: &str = "usei";
+const VALIDATOR: &str = "seivaloper1";
+/// A validator nothing is delegated to.
+const OTHER_VALIDATOR: &str = "seivaloper2";
+const ADMIN: &str = "fee_address";
+const ALICE: &str = "alice";
+const BOB: &str = "bob";
+const INITIAL_BALANCE: u128 = 10_000;
+/// Unbonding time of the simulated chain. The contract is never told it.
+const UNBONDING_TIME: u64 = 21 * 24 * 60 * 60;
+const MAX_ENTRIES: usize = 7;
+const STAKING_MODULE: &str = "staking_module";
+
+/// Delegations to `VALIDATOR` and their pending rewards, by delegator.
+const DELEGATIONS: Map<&str, u128> = Map::new("test_delegations");
+const REWARDS: Map<&str, u128> = Map::new("test_rewards");
+/// Delegator, amount and completion time of every unbonding.
+const UNBONDINGS: Item<Vec<(String, u128, u64)>> = Item::new("test_unbondings");
+
+#[derive(Default)]
+struct StakeKeeper;
+
+impl Staking for StakeKeeper {}
+
+impl StakeKeeper {
+    /// Pays out the unbondings that completed by `block`.
+    fn end_block<ExecC, QueryC>(
+        &self,
+        api: &dyn Api,
+        storage: &mut dyn Storage,
+        router: &dyn CosmosRouter<ExecC = ExecC, QueryC = QueryC>,
+        block: &BlockInfo,
+    ) -> AnyResult<()>
+    where
+        ExecC: std::fmt::Debug + Clone + PartialEq + JsonSchema + DeserializeOwned + 'static,
+        QueryC: CustomQuery + DeserializeOwned + 'static,
+    {
+        let now = block.time.seconds();
+        let (done, pending): (Vec<_>, Vec<_>) = UNBONDINGS
+            .may_load(storage)?
+            .unwrap_or_default()
+            .into_iter()
+            .partition(|&(_, _, completion)| completion <= now);
+        UNBONDINGS.save(storage, &pending)?;
+        for (delegator, amount, _) in done {
+            let payout = BankMsg::Send {
+                to_address: delegator,
+                amount: coins(amount, DENOM),
+            };
+            router.execute(
+                api,
+                storage,
+                block,
+                Addr::unchecked(STAKING_MODULE),
+                payout.into(),
+            )?;
+        }
+        Ok(())
+    }
+}
+
+fn withdraw_rewards<ExecC, QueryC: CustomQuery>(
+    api: &dyn Api,
+    storage: &mut dyn Storage,
+    router: &dyn CosmosRouter<ExecC = ExecC, QueryC = QueryC>,
+    block: &BlockInfo,
+    delegator: &Addr,
+) -> AnyResult<()> {
+    let rewards = REWARDS
+        .may_load(storage, delegator.as_str())?
+        .unwrap_or_default();
+    if rewards > 0 {
+        REWARDS.remove(storage, delegator.as_str());
+        let mint = BankSudo::Mint {
+            to_address: delegator.to_string(),
+            amount: coins(rewards, DENOM),
+        };
+        router.sudo(api, storage, block, mint.into())?;
+    }
+    Ok(())
+}
+
+impl Module for StakeKeeper {
+    type ExecT = StakingMsg;
+    type QueryT = StakingQuery;
+    type SudoT = StakingSudo;
+
+    fn execute<ExecC, QueryC>(
+        &self,
+        api: &dyn Api,
+        storage: &mut dyn Storage,
+        router: &dyn CosmosRouter<ExecC = ExecC, QueryC = QueryC>,
+        block: &BlockInfo,
+        sender: Addr,
+        msg: StakingMsg,
+    ) -> AnyResult<AppResponse>
+    where
+        ExecC: std::fmt::Debug + Clone + PartialEq + JsonSchema + DeserializeOwned + 'static,
+        QueryC: CustomQuery + DeserializeOwned + 'static,
+    {
+        let delegated = DELEGATIONS
+            .may_load(storage, sender.as_str())?
+            .unwrap_or_default();
+        match msg {
+            StakingMsg::Delegate { validator, amount } => {
+                if validator != VALIDATOR || amount.denom != DENOM {
+                    bail!("cannot delegate {} to {}", amount, validator);
+                }
+                withdraw_rewards(api, storage, router, block, &sender)?;
+                let deposit = BankMsg::Send {
+                    to_address: STAKING_MODULE.to_string(),
+                    amount: vec![amount.clone()],
+                };
+                router.execute(api, storage, block, sender.clone(), deposit.into())?;
+                DELEGATIONS.save(
+                    storage,
+                    sender.as_str(),
+                    &(delegated + amount.amount.u128()),
+                )?;
+                Ok(AppResponse::default())
+            }
+            StakingMsg::Undelegate { validator, amount } => {
+                let amount = amount.amount.u128();
+                if validator != VALIDATOR || amount > delegated {
+                    bail!("cannot undelegate {} from {}", amount, validator);
+                }
+                let mut unbondings = UNBONDINGS.may_load(storage)?.unwrap_or_default();
+                let entries = unbondings
+                    .iter()
+                    .filter(|(delegator, _, _)| *delegator == sender.as_str())
+                    .count();
+                if entries >= MAX_ENTRIES {
+                    bail!("too many unbonding entries");
+                }
+                withdraw_rewards(api, storage, router, block, &sender)?;
+                DELEGATIONS.save(storage, sender.as_str(), &(delegated - amount))?;
+                let completion = block.time.seconds() + UNBONDING_TIME;
+                unbondings.push((sender.to_string(), amount, completion));
+                UNBONDINGS.save(storage, &unbondings)?;
+                let unbond = Event::new("unbond")
+                    .add_attribute("validator", validator)
+                    .add_attribute("amount", coin(amount, DENOM).to_string())
+                    .add_attribute("completion_time", rfc3339(completion));
+                Ok(AppResponse {
+                    events: vec![unbond],
+                    data: None,
+                })
+            }
+            msg => bail!("unsupported staking message {:?}", msg),
+        }
+    }
+
+    fn sudo<ExecC, QueryC>(
+        &self,
+        _api: &dyn Api,
+        _storage: &mut dyn Storage,
+        _router: &dyn CosmosRouter<ExecC = ExecC, QueryC = QueryC>,
+        _block: &BlockInfo,
+        msg: StakingSudo,
+    ) -> AnyResult<AppResponse> {
+        bail!("unsupported staking sudo {:?}", msg)
+    }
+
+    fn query(
+        &self,
+        _api: &dyn Api,
+        storage: &dyn Storage,
+        _querier: &dyn Querier,
+        _block: &BlockInfo,
+        request: StakingQuery,
+    ) -> AnyResult<Binary> {
+        match request {
+            StakingQuery::BondedDenom {} => Ok(to_json_binary(&BondedDenomResponse {
+                denom: DENOM.to_string(),
+            })?),
+            StakingQuery::Validator { address } => {
+                let validator = Validator {
+                    address: address.clone(),
+                    commission: Decimal::percent(5),
+                    max_commission: Decimal::percent(10),
+                    max_change_rate: Decimal::percent(1),
+                };
+                Ok(to_json_binary(&ValidatorResponse {
+                    validator: Some(validator)
+                        .filter(|_| address == VALIDATOR || address == OTHER_VALIDATOR),
+                })?)
+            }
+            StakingQuery::Delegation {
+                delegator,
+                validator,
+            } => {
+                let amount = DELEGATIONS
+                    .may_load(storage, &delegator)?
+                    .unwrap_or_default();
+                let rewards = REWARDS.may_load(storage, &delegator)?.unwrap_or_default();
+                let delegation = FullDelegation {
+                    delegator: Addr::unchecked(delegator),
+                    validator: validator.clone(),
+                    amount: coin(amount, DENOM),
+                    can_redelegate: coin(amount, DENOM),
+                    accumulated_rewards: coins(rewards, DENOM),
+                };
+                Ok(to_json_binary(&DelegationResponse {
+                    delegation: Some(delegation)
+                        .filter(|_| validator == VALIDATOR && amount + rewards > 0),
+                })?)
+            }
+            request => bail!("unsupported staking query {:?}", request),
+        }
+    }
+}
+
+/// Formats unix seconds the way the staking module prints completion times.
+fn rfc3339(seconds: u64) -> String {
+    // Civil date of a day count, with years starting in March.
+    let days = seconds / 86_400 + 719_468;
+    let era = days / 146_097;
+    let day_of_era = days % 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = era * 400 + year_of_era + if month <= 2 { 1 } else { 0 };
+    let time = seconds % 86_400;
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        time / 3600,
+        time % 3600 / 60,
+        time % 60
+    )
+}
+
+/// The custom module of a basic app, which cw-multi-test does not export.
+trait CustomModule {
+    type Custom;
+}
+
+impl<Bank, ApiT, StorageT, Custom, Wasm, StakingT, Distr> CustomModule
+    for App<Bank, ApiT, StorageT, Custom, Wasm, StakingT, Distr>
+{
+    type Custom = Custom;
+}
+
+type StakingApp = App<
+    BankKeeper,
+    MockApi,
+    MockStorage,
+    <BasicApp as CustomModule>::Custom,
+    WasmKeeper<Empty, Empty>,
+    StakeKeeper,
+    FailingDistribution,
+>;
+
+fn bet_contract() -> Box<dyn Contract<Empty>> {
+    Box::new(ContractWrapper::new(execute, instantiate, query).with_reply(reply))
+}
+
+fn setup() -> (StakingApp, Addr) {
+    let mut app = AppBuilder::new()
+        .with_staking(StakeKeeper)
+        .build(|router, _, storage| {
+            for player in &[ALICE, BOB] {
+                router
+                    .bank
+                    .init_balance(
+                        storage,
+                        &Addr::unchecked(*player),
+                        coins(INITIAL_BALANCE, DENOM),
+                    )
+                    .unwrap();
+            }
+        });
+    let code_id = app.store_code(bet_contract());
+    let contract = app
+        .instantiate_contract(
+            code_id,
+            Addr::unchecked(ADMIN),
+            &InstantiateMsg {
+                fee_address: Addr::unchecked(ADMIN),
+                start_flag: true,
+            },
+            &[],
+            "sefi-bet",
+            None,
+        )
+        .unwrap();
+    app.execute_contract(
+        Addr::unchecked(ADMIN),
+        contract.clone(),
+        &ExecuteMsg::ConfigureStakingPool {
+            validator: VALIDATOR.to_string(),
+        },
+        &[],
+    )
+    .unwrap();
+    (app, contract)
+}
+
+fn balance(app: &StakingApp, address: &str) -> u128 {
+    app.wrap()
+        .query_balance(address, DENOM)
+        .unwrap()
+        .amount
+        .u128()
+}
+
+fn delegated(app: &StakingApp, contract: &Addr) -> u128 {
+    app.wrap()
+        .query_delegation(contract, VALIDATOR)
+        .unwrap()
+        .map_or(0, |delegation| delegation.amount.amount.u128())
+}
+
+fn unbonding(app: &StakingApp, contract: &Addr, address: &str) -> Vec<UnbondingResponse> {
+    app.wrap()
+        .query_wasm_smart(
+            contract.to_string(),
+            &QueryMsg::Unbonding {
+                address: Addr::unchecked(address),
+            },
+        )
+        .unwrap()
+}
+
+fn staking_pool(app: &StakingApp, contract: &Addr) -> StakingPool {
+    let pool: Option<StakingPool> = app
+        .wrap()
+        .query_wasm_smart(contract.to_string(), &QueryMsg::StakingPool {})
+        .unwrap();
+    pool.unwrap()
+}
+
+fn stake(app: &mut StakingApp, contract: &Addr, depositor: &str, amount: u128) {
+    app.execute_contract(
+        Addr::unchecked(depositor),
+        contract.clone(),
+        &ExecuteMsg::StakingDeposit {},
+        &coins(amount, DENOM),
+    )
+    .unwrap();
+}
+
+fn run(
+    app: &mut StakingApp,
+    contract: &Addr,
+    sender: &str,
+    msg: ExecuteMsg,
+) -> Result<AppResponse, ContractError> {
+    app.execute_contract(Addr::unchecked(sender), contract.clone(), &msg, &[])
+        .map_err(|err| err.downcast().unwrap())
+}
+
+fn advance(app: &mut StakingApp, seconds: u64) {
+    app.update_block(|block| block.time = block.time.plus_seconds(seconds));
+    let block = app.block_info();
+    app.init_modules(|router, api, storage| {
+        router.staking.end_block(api, storage, &*router, &block)
+    })
+    .unwrap();
+}
+
+#[test]
+fn deposits_are_delegated() {
+    let (mut app, contract) = setup();
+    stake(&mut app, &contract, ALICE, 1000);
+
+    assert_eq!(1000, delegated(&app, &contract));
+    assert_eq!(0, balance(&app, contract.as_str()));
+    assert_eq!(1000, staking_pool(&app, &contract).total_deposits);
+}
+
+#[test]
+fn withdrawals_unbond_in_batches() {
+    let (mut app, contract) = setup();
+    stake(&mut app, &contract, ALICE, 1000);
+    stake(&mut app, &contract, BOB, 1000);
+    let withdraw = |amount| ExecuteMsg::StakingWithdraw { amount };
+
+    // Withdrawals over an epoch wait for one undelegation.
+    run(&mut app, &contract, ALICE, withdraw(400)).unwrap();
+    advance(&mut app, UNBONDING_EPOCH / 2);
+    run(&mut app, &contract, BOB, withdraw(100)).unwrap();
+    assert_eq!(2000, delegated(&app, &contract));
+    let err = run(&mut app, &contract, ALICE, ExecuteMsg::SubmitUnbonding {}).unwrap_err();
+    assert!(matches!(err, ContractError::NoUnbondingDue));
+    assert_eq!(
+        vec![UnbondingResponse {
+            amount: 400,
+            batch: 1,
+            release_at: None,
+        }],
+        unbonding(&app, &contract, ALICE)
+    );
+
+    // Anyone can send the batch once the epoch is over. Its release time is
+    // the one the chain reports.
+    advance(&mut app, UNBONDING_EPOCH / 2);
+    run(
+        &mut app,
+        &contract,
+        "anyone",
+        ExecuteMsg::SubmitUnbonding {},
+    )
+    .unwrap();
+    assert_eq!(1500, delegated(&app, &contract));
+    let release_at = app.block_info().time.seconds() + UNBONDING_TIME;
+    assert_eq!(
+        vec![UnbondingResponse {
+            amount: 100,
+            batch: 1,
+            release_at: Some(release_at),
+        }],
+        unbonding(&app, &contract, BOB)
+    );
+    // The 500 on their way back are owed but not missing.
+    let solvency: Vec<SolvencyResponse> = app
+        .wrap()
+        .query_wasm_smart(contract.to_string(), &QueryMsg::Solvency {})
+        .unwrap();
+    assert_eq!(
+        vec![SolvencyResponse {
+            denom: DENOM.to_string(),
+            balance: 0,
+            unbonding: 500,
+            liabilities: 500,
+            surplus: 0,
+            deficit: 0,
+        }],
+        solvency
+    );
+
+    // A new withdrawal opens the next batch.
+    run(&mut app, &contract, ALICE, withdraw(50)).unwrap();
+    assert_eq!(Some(2), staking_pool(&app, &contract).open_batch);
+
+    let err = run(&mut app, &contract, ALICE, ExecuteMsg::ClaimUnbonded {}).unwrap_err();
+    assert!(matches!(err, ContractError::NothingToClaim));
+    advance(&mut app, UNBONDING_TIME);
+    assert_eq!(500, balance(&app, contract.as_str()));
+    run(&mut app, &contract, ALICE, ExecuteMsg::ClaimUnbonded {}).unwrap();
+    run(&mut app, &contract, BOB, ExecuteMsg::ClaimUnbonded {}).unwrap();
+    assert_eq!(INITIAL_BALANCE - 600, balance(&app, ALICE));
+    assert_eq!(INITIAL_BALANCE - 900, balance(&app, BOB));
+    // Alice's 50 in the open batch are no longer deposited, but still
+    // delegated.
+    assert_eq!(1500, delegated(&app, &contract));
+    assert_eq!(1450, staking_pool(&app, &contract).total_deposits);
+}
+
+#[test]
+fn validator_is_locked_until_withdrawals_are_back() {
+    let (mut app, contract) = setup();
+    stake(&mut app, &contract, ALICE, 1000);
+    let switch = || ExecuteMsg::ConfigureStakingPool {
+        validator: OTHER_VALIDATOR.to_string(),
+    };
+
+    // Nothing is deposited once Alice withdraws, but her stake is still
+    // delegated in the open batch, then unbonding.
+    run(
+        &mut app,
+        &contract,
+        ALICE,
+        ExecuteMsg::StakingWithdraw { amount: 1000 },
+    )
+    .unwrap();
+    assert_eq!(0, staking_pool(&app, &contract).total_deposits);
+    let err = run(&mut app, &contract, ADMIN, switch()).unwrap_err();
+    assert!(matches!(err, ContractError::ValidatorLocked));
+
+    advance(&mut app, UNBONDING_EPOCH);
+    run(&mut app, &contract, ALICE, ExecuteMsg::SubmitUnbonding {}).unwrap();
+    assert_eq!(0, delegated(&app, &contract));
+    let err = run(&mut app, &contract, ADMIN, switch()).unwrap_err();
+    assert!(matches!(err, ContractError::ValidatorLocked));
+
+    advance(&mut app, UNBONDING_TIME);
+    run(&mut app, &contract, ADMIN, switch()).unwrap();
+    assert_eq!(OTHER_VALIDATOR, staking_pool(&app, &contract).validator);
+    run(&mut app, &contract, ALICE, ExecuteMsg::ClaimUnbonded {}).unwrap();
+    assert_eq!(INITIAL_BALANCE, balance(&app, ALICE));
+}
+
+#[test]
+fn end_game_staking_raffles_rewards_only() {
+    let (mut app, contract) = setup();
+    stake(&mut app, &contract, ALICE, 1000);
+    app.init_modules(|_, _, storage| REWARDS.save(storage, contract.as_str(), &50))
+        .unwrap();
+
+    // Bob's deposit withdraws the rewards into the prize pot.
+    stake(&mut app, &contract, BOB, 500);
+    assert_eq!(50, balance(&app, contract.as_str()));
+    assert_eq!(50, staking_pool(&app, &contract).prize_pot);
+
+    run(
+        &mut app,
+        &contract,
+        ADMIN,
+        ExecuteMsg::EndGameStaking {
+            seed: 1231231809300,
+        },
+    )
+    .unwrap();
+    // The seed draws 9427 bps, in Bob's third of the deposits.
+    assert_eq!(INITIAL_BALANCE - 500 + 48, balance(&app, BOB));
+    assert_eq!(2, balance(&app, ADMIN));
+    assert_eq!(1500, delegated(&app, &contract));
+    let pool = staking_pool(&app, &contract);
+    assert_eq!(
+        (0, 1500, 1),
+        (pool.prize_pot, pool.total_deposits, pool.round)
+    );
+}